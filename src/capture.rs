@@ -0,0 +1,277 @@
+// CAPTURE BACKENDS
+
+use std::env;
+use image::{DynamicImage, GenericImage, GenericImageView, Rgba};
+use screenshots::Screen;
+use crate::constants::CAPTURE_BACKEND_VAR;
+
+// geometry of one monitor in the virtual desktop (physical pixels)
+#[derive(Clone, Debug, PartialEq)]
+pub struct MonitorInfo {
+    pub id: u32,
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f32,
+    pub is_primary: bool,
+}
+
+impl MonitorInfo {
+    pub fn right(&self) -> i32 {
+        self.x + self.width as i32
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height as i32
+    }
+}
+
+pub trait CaptureBackend {
+    // list the monitors with their position, size and scale factor
+    fn monitors(&self) -> Result<Vec<MonitorInfo>, String>;
+
+    // capture the whole content of one monitor
+    fn capture_monitor(&self, monitor: &MonitorInfo) -> Result<DynamicImage, String>;
+
+    // capture a rectangle expressed in virtual desktop coordinates, also across many monitors.
+    // the default implementation captures every monitor touched by the rect and stitches the pieces,
+    // areas not covered by any monitor are left transparent
+    fn capture_rect(&self, x: i32, y: i32, width: u32, height: u32) -> Result<DynamicImage, String> {
+        let mut img = DynamicImage::new_rgba8(width, height);
+        let (right, bottom) = (x + width as i32, y + height as i32);
+
+        for monitor in self.monitors()? {
+            // intersection between the monitor and the requested rect
+            let (ix0, iy0) = (x.max(monitor.x), y.max(monitor.y));
+            let (ix1, iy1) = (right.min(monitor.right()), bottom.min(monitor.bottom()));
+            if ix0 >= ix1 || iy0 >= iy1 {
+                continue;
+            }
+            let screenshot = self.capture_monitor(&monitor)?;
+            let piece = screenshot.view(
+                (ix0 - monitor.x) as u32,
+                (iy0 - monitor.y) as u32,
+                ((ix1 - ix0) as u32).min(screenshot.width().saturating_sub((ix0 - monitor.x) as u32)),
+                ((iy1 - iy0) as u32).min(screenshot.height().saturating_sub((iy0 - monitor.y) as u32)),
+            );
+            img.copy_from(&*piece, (ix0 - x) as u32, (iy0 - y) as u32)
+                .map_err(|e| e.to_string())?;
+        }
+
+        Ok(img)
+    }
+}
+
+// bounding box (x_min, y_min, x_max, y_max) of all the monitors
+pub fn virtual_bounds(monitors: &[MonitorInfo]) -> Option<(i32,i32,i32,i32)> {
+    let x_min = monitors.iter().map(|m| m.x).min()?;
+    let y_min = monitors.iter().map(|m| m.y).min()?;
+    let x_max = monitors.iter().map(|m| m.right()).max()?;
+    let y_max = monitors.iter().map(|m| m.bottom()).max()?;
+    Some((x_min,y_min,x_max,y_max))
+}
+
+// true if every pixel of the rect is on a monitor: the bounding box of the monitors also has the areas
+// outside them, e.g. under a smaller monitor next to a bigger one
+pub fn is_on_monitors(monitors: &[MonitorInfo], (x, y, width, height): (i32,i32,u32,u32)) -> bool {
+    if width == 0 || height == 0 {
+        return false;
+    }
+    let (right, bottom) = (x + width as i32, y + height as i32);
+    // the edges of the monitors split the rect in cells, each one is on a monitor or outside all of them
+    let mut xs = vec![x, right];
+    xs.extend(monitors.iter().flat_map(|m| [m.x, m.right()]).filter(|edge| x < *edge && *edge < right));
+    let mut ys = vec![y, bottom];
+    ys.extend(monitors.iter().flat_map(|m| [m.y, m.bottom()]).filter(|edge| y < *edge && *edge < bottom));
+    xs.sort_unstable();
+    xs.dedup();
+    ys.sort_unstable();
+    ys.dedup();
+    xs.windows(2).all(|cell_x| ys.windows(2).all(|cell_y| monitors.iter().any(|m|
+        m.x <= cell_x[0] && cell_x[1] <= m.right() && m.y <= cell_y[0] && cell_y[1] <= m.bottom())))
+}
+
+// identity of the monitor layout, e.g. "1920x1080+0+0,1280x1024+1920+0": the same region means
+// something else after a monitor is connected, moved or resized
+pub fn layout_signature(monitors: &[MonitorInfo]) -> String {
+    monitors.iter()
+        .map(|m| format!("{}x{}+{}+{}", m.width, m.height, m.x, m.y))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// the backend used by the application, a synthetic one can be forced through the environment
+// (e.g. PDS_CAPTURE_BACKEND=synthetic) to run the capture path without a display
+pub fn default_backend() -> Box<dyn CaptureBackend> {
+    match env::var(CAPTURE_BACKEND_VAR) {
+        Ok(name) if name == "synthetic" => Box::new(SyntheticBackend::default()),
+        _ => Box::new(ScreenshotsBackend),
+    }
+}
+
+// real backend based on the screenshots crate
+pub struct ScreenshotsBackend;
+
+impl ScreenshotsBackend {
+    fn find_screen(monitor: &MonitorInfo) -> Result<Screen, String> {
+        Screen::all()
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .find(|s| s.display_info.id == monitor.id)
+            .ok_or(format!("Monitor {} not found", monitor.name))
+    }
+}
+
+impl CaptureBackend for ScreenshotsBackend {
+    fn monitors(&self) -> Result<Vec<MonitorInfo>, String> {
+        let screens = Screen::all().map_err(|e| e.to_string())?;
+        Ok(screens.iter().enumerate().map(|(index, s)| MonitorInfo {
+            id: s.display_info.id,
+            name: format!("Monitor {}", index + 1),
+            x: s.display_info.x,
+            y: s.display_info.y,
+            width: s.display_info.width,
+            height: s.display_info.height,
+            scale_factor: s.display_info.scale_factor,
+            is_primary: s.display_info.is_primary,
+        }).collect())
+    }
+
+    fn capture_monitor(&self, monitor: &MonitorInfo) -> Result<DynamicImage, String> {
+        let image = Self::find_screen(monitor)?.capture().map_err(|e| e.to_string())?;
+        let png = image.to_png(None).map_err(|e| e.to_string())?;
+        image::load_from_memory_with_format(&png, image::ImageFormat::Png).map_err(|e| e.to_string())
+    }
+}
+
+// in-memory backend serving fixed images, used to run the crop/stitch logic without a display
+pub struct SyntheticBackend {
+    screens: Vec<(MonitorInfo, DynamicImage)>,
+}
+
+impl SyntheticBackend {
+    pub fn new(screens: Vec<(MonitorInfo, DynamicImage)>) -> Self {
+        SyntheticBackend { screens }
+    }
+
+    // build a monitor filled with a solid color
+    pub fn solid_monitor(id: u32, x: i32, y: i32, width: u32, height: u32, color: (u8,u8,u8,u8)) -> (MonitorInfo, DynamicImage) {
+        let info = MonitorInfo {
+            id,
+            name: format!("Synthetic {}", id),
+            x,
+            y,
+            width,
+            height,
+            scale_factor: 1.0,
+            is_primary: id == 0,
+        };
+        let image = DynamicImage::from(image::ImageBuffer::from_pixel(width, height, Rgba([color.0, color.1, color.2, color.3])));
+        (info, image)
+    }
+}
+
+impl Default for SyntheticBackend {
+    // two full hd monitors side by side, red on the left and blue on the right
+    fn default() -> Self {
+        SyntheticBackend::new(vec![
+            SyntheticBackend::solid_monitor(0, 0, 0, 1920, 1080, (255, 0, 0, 255)),
+            SyntheticBackend::solid_monitor(1, 1920, 0, 1920, 1080, (0, 0, 255, 255)),
+        ])
+    }
+}
+
+impl CaptureBackend for SyntheticBackend {
+    fn monitors(&self) -> Result<Vec<MonitorInfo>, String> {
+        Ok(self.screens.iter().map(|(info, _)| info.clone()).collect())
+    }
+
+    fn capture_monitor(&self, monitor: &MonitorInfo) -> Result<DynamicImage, String> {
+        self.screens.iter()
+            .find(|(info, _)| info.id == monitor.id)
+            .map(|(_, image)| image.clone())
+            .ok_or(format!("Monitor {} not found", monitor.name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: (u8,u8,u8,u8) = (255, 0, 0, 255);
+    const BLUE: (u8,u8,u8,u8) = (0, 0, 255, 255);
+    const GREEN: (u8,u8,u8,u8) = (0, 255, 0, 255);
+    const TRANSPARENT: (u8,u8,u8,u8) = (0, 0, 0, 0);
+
+    fn pixel(image: &DynamicImage, x: u32, y: u32) -> (u8,u8,u8,u8) {
+        let Rgba([r, g, b, a]) = image.get_pixel(x, y);
+        (r, g, b, a)
+    }
+
+    #[test]
+    fn rect_on_monitors() {
+        let monitors = SyntheticBackend::new(vec![
+            SyntheticBackend::solid_monitor(0, 0, 0, 1920, 1080, RED),
+            SyntheticBackend::solid_monitor(1, 1920, 0, 1280, 1024, BLUE),
+        ]).monitors().unwrap();
+        assert!(is_on_monitors(&monitors, (0, 0, 1920, 1080)));
+        assert!(is_on_monitors(&monitors, (1800, 100, 300, 900)));
+        // under the smaller monitor, inside the bounding box
+        assert!(!is_on_monitors(&monitors, (1800, 1000, 300, 40)));
+        assert!(!is_on_monitors(&monitors, (3000, 0, 300, 100)));
+        assert!(!is_on_monitors(&monitors, (-10, 0, 100, 100)));
+        assert!(!is_on_monitors(&monitors, (0, 0, 0, 100)));
+    }
+
+    #[test]
+    fn rect_across_two_monitors() {
+        let backend = SyntheticBackend::default();
+        let image = backend.capture_rect(1900, 100, 40, 10).unwrap();
+        assert_eq!(image.dimensions(), (40, 10));
+        assert_eq!(pixel(&image, 0, 0), RED);
+        assert_eq!(pixel(&image, 19, 9), RED);
+        assert_eq!(pixel(&image, 20, 0), BLUE);
+        assert_eq!(pixel(&image, 39, 9), BLUE);
+    }
+
+    #[test]
+    fn gap_between_monitors_is_transparent() {
+        let backend = SyntheticBackend::new(vec![
+            SyntheticBackend::solid_monitor(0, 0, 0, 100, 100, RED),
+            SyntheticBackend::solid_monitor(1, 200, 0, 100, 100, BLUE),
+        ]);
+        let image = backend.capture_rect(50, 0, 200, 10).unwrap();
+        assert_eq!(pixel(&image, 49, 5), RED);
+        assert_eq!(pixel(&image, 50, 5), TRANSPARENT);
+        assert_eq!(pixel(&image, 149, 5), TRANSPARENT);
+        assert_eq!(pixel(&image, 150, 5), BLUE);
+    }
+
+    #[test]
+    fn negative_origin() {
+        // a monitor on the left of and above the primary one
+        let backend = SyntheticBackend::new(vec![
+            SyntheticBackend::solid_monitor(0, 0, 0, 100, 100, RED),
+            SyntheticBackend::solid_monitor(1, -100, -50, 100, 100, GREEN),
+        ]);
+        let image = backend.capture_rect(-10, -10, 20, 20).unwrap();
+        assert_eq!(pixel(&image, 0, 0), GREEN);
+        assert_eq!(pixel(&image, 9, 19), GREEN);
+        assert_eq!(pixel(&image, 10, 0), TRANSPARENT);
+        assert_eq!(pixel(&image, 10, 10), RED);
+        assert_eq!(virtual_bounds(&backend.monitors().unwrap()), Some((-100, -50, 100, 100)));
+    }
+
+    #[test]
+    fn rect_partly_off_screen() {
+        let backend = SyntheticBackend::default();
+        let image = backend.capture_rect(3830, 1070, 20, 20).unwrap();
+        assert_eq!(image.dimensions(), (20, 20));
+        assert_eq!(pixel(&image, 0, 0), BLUE);
+        assert_eq!(pixel(&image, 9, 9), BLUE);
+        assert_eq!(pixel(&image, 10, 0), TRANSPARENT);
+        assert_eq!(pixel(&image, 0, 10), TRANSPARENT);
+    }
+}
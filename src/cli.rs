@@ -0,0 +1,363 @@
+// COMMAND LINE INTERFACE (headless mode, no window is opened)
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+use image::DynamicImage;
+use crate::capture::{CaptureBackend, default_backend};
+use crate::constants::BASE_DPI;
+use crate::export::images_to_pdf;
+use crate::naming::{expand_template, TemplateValues, unique_path};
+use crate::error::{Context, Error, Result};
+use crate::settings::Settings;
+use crate::utilities::{capture_dpi, compute_screening_coordinates, copy_to_clipboard, encode_image, preset_rect};
+
+const USAGE: &str = "Usage:
+  pds_project <command> [options]
+
+Commands:
+  full                                  capture all the monitors
+  screen --index N                      capture the monitor N (see list)
+  region --x X --y Y --w W --h H        capture a rect in virtual desktop coordinates
+  preset --name NAME                    capture the region preset NAME of the settings
+  list                                  print the detected monitors and the region presets
+  pdf --output PATH FILE...             bundle saved captures in a multi-page pdf
+  help                                  print this message
+
+Options:
+  --delay SECONDS     wait before capturing (fractions allowed)
+  --format FORMAT     output format (png, jpg, webp, avif, qoi, pdf..), default from the settings
+  --quality Q         quality (0-100) of jpg, webp and avif, default from the settings
+  --lossless          lossless webp
+  --profile NAME      use the settings of the profile NAME
+  --config PATH       settings file to use instead of the one in the user config directory
+  --output PATH       file to write, default in the save path named by the settings template
+  --clipboard         copy the capture to the clipboard (on linux waits until the clipboard is replaced)
+  --stdout            write the encoded image to the standard output
+";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Full,
+    Screen(usize),
+    Region(i32,i32,u32,u32),
+    Preset(String),
+    List,
+    Pdf(Vec<PathBuf>),
+    Help,
+}
+
+#[derive(Debug)]
+struct CliOptions {
+    command: Command,
+    delay: f64,
+    format: Option<String>,
+    output: Option<PathBuf>,
+    clipboard: bool,
+    stdout: bool,
+    quality: Option<u8>,
+    lossless: bool,
+    profile: Option<String>,
+}
+
+fn parse_value<T: std::str::FromStr>(args: &[String], index: usize, name: &str) -> Result<T, String> {
+    args.get(index + 1)
+        .ok_or(format!("Missing value for {}", name))?
+        .parse::<T>()
+        .map_err(|_| format!("Invalid value for {}: {}", name, args[index + 1]))
+}
+
+// remove --config PATH from the arguments, returning the path
+pub fn take_config_option(args: &mut Vec<String>) -> Result<Option<PathBuf>, String> {
+    match args.iter().position(|arg| arg == "--config") {
+        Some(index) => {
+            let path = parse_value::<PathBuf>(args, index, "--config")?;
+            args.drain(index..=index + 1);
+            Ok(Some(path))
+        }
+        None => Ok(None),
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<CliOptions, String> {
+    let command_name = args.first().ok_or("Missing command".to_string())?;
+    let (mut index, mut x, mut y, mut w, mut h) = (None, None, None, None, None);
+    let mut name = None;
+    let mut inputs = vec![];
+    let mut options = CliOptions { command: Command::Help, delay: 0.0, format: None, output: None, clipboard: false, stdout: false, quality: None, lossless: false, profile: None };
+
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--index" => { index = Some(parse_value::<usize>(args, i, "--index")?); i += 1; }
+            "--x" => { x = Some(parse_value::<i32>(args, i, "--x")?); i += 1; }
+            "--y" => { y = Some(parse_value::<i32>(args, i, "--y")?); i += 1; }
+            "--w" => { w = Some(parse_value::<u32>(args, i, "--w")?); i += 1; }
+            "--h" => { h = Some(parse_value::<u32>(args, i, "--h")?); i += 1; }
+            "--name" => { name = Some(parse_value::<String>(args, i, "--name")?); i += 1; }
+            "--delay" => { options.delay = parse_value::<f64>(args, i, "--delay")?; i += 1; }
+            "--format" => { options.format = Some(parse_value::<String>(args, i, "--format")?.to_ascii_lowercase()); i += 1; }
+            "--output" => { options.output = Some(parse_value::<PathBuf>(args, i, "--output")?); i += 1; }
+            "--clipboard" => options.clipboard = true,
+            "--stdout" => options.stdout = true,
+            "--quality" => { options.quality = Some(parse_value::<u8>(args, i, "--quality")?); i += 1; }
+            "--lossless" => options.lossless = true,
+            "--profile" => { options.profile = Some(parse_value::<String>(args, i, "--profile")?); i += 1; }
+            other if other.starts_with("--") => return Err(format!("Unknown option: {}", other)),
+            other => inputs.push(PathBuf::from(other)),
+        }
+        i += 1;
+    }
+
+    if options.delay < 0.0 || !options.delay.is_finite() {
+        return Err("The delay must be a positive number of seconds".to_string());
+    }
+
+    if options.quality.is_some_and(|quality| quality > 100) {
+        return Err("The quality must be between 0 and 100".to_string());
+    }
+
+    if !inputs.is_empty() && command_name != "pdf" {
+        return Err(format!("Unexpected argument: {}", inputs[0].display()));
+    }
+
+    options.command = match command_name.as_str() {
+        "full" => Command::Full,
+        "screen" => Command::Screen(index.ok_or("screen requires --index".to_string())?),
+        "region" => match (x, y, w, h) {
+            (Some(x), Some(y), Some(w), Some(h)) if w > 0 && h > 0 => Command::Region(x, y, w, h),
+            (Some(_), Some(_), Some(_), Some(_)) => return Err("The region must have a positive size".to_string()),
+            _ => return Err("region requires --x, --y, --w and --h".to_string()),
+        },
+        "preset" => Command::Preset(name.ok_or("preset requires --name".to_string())?),
+        "list" => Command::List,
+        "pdf" if inputs.is_empty() => return Err("pdf requires at least one input file".to_string()),
+        "pdf" if options.output.is_none() => return Err("pdf requires --output".to_string()),
+        "pdf" => Command::Pdf(inputs.clone()),
+        "help" | "--help" | "-h" => Command::Help,
+        other => return Err(format!("Unknown command: {}", other)),
+    };
+
+    Ok(options)
+}
+
+// the capture and what it shows, for the {monitor} token: the monitor name, "region", the preset name or "all"
+fn capture(backend: &dyn CaptureBackend, settings: &Settings, command: &Command) -> Result<(DynamicImage, String), String> {
+    match *command {
+        Command::Full => {
+            let (x_min,y_min,x_max,y_max) = compute_screening_coordinates(backend)?;
+            Ok((backend.capture_rect(x_min, y_min, (x_max - x_min) as u32, (y_max - y_min) as u32)?, "all".to_string()))
+        }
+        Command::Screen(index) => {
+            let monitors = backend.monitors()?;
+            let monitor = monitors.get(index).ok_or(format!("No monitor with index {}, {} found", index, monitors.len()))?;
+            Ok((backend.capture_monitor(monitor)?, monitor.name.clone()))
+        }
+        Command::Region(x, y, w, h) => Ok((backend.capture_rect(x, y, w, h)?, "region".to_string())),
+        Command::Preset(ref name) => {
+            let preset = settings.find_preset(name)
+                .ok_or(format!("No region preset named {}, available: {}", name,
+                               settings.region_presets.iter().map(|p| p.name.as_str()).collect::<Vec<_>>().join(", ")))?;
+            let (x, y, w, h) = preset_rect(backend, preset)?;
+            Ok((backend.capture_rect(x, y, w, h)?, name.clone()))
+        }
+        Command::List | Command::Pdf(_) | Command::Help => Err("Nothing to capture".to_string()),
+    }
+}
+
+// without any destination, the capture is saved in the settings folder with the automatic name, as the save button does
+fn writes_file(options: &CliOptions) -> bool {
+    options.output.is_some() || (!options.stdout && !options.clipboard)
+}
+
+fn execute(options: CliOptions) -> Result<()> {
+    let backend = default_backend();
+
+    match options.command {
+        Command::Help => {
+            print!("{}", USAGE);
+            return Ok(());
+        }
+        Command::List => {
+            for (index, monitor) in backend.monitors().map_err(Error::Capture)?.iter().enumerate() {
+                println!("{}: {} {}x{} at ({},{}) scale {} id {}{}", index, monitor.name, monitor.width, monitor.height,
+                         monitor.x, monitor.y, monitor.scale_factor, monitor.id, if monitor.is_primary { " (primary)" } else { "" });
+            }
+            for preset in Settings::load().region_presets {
+                println!("preset \"{}\": {}x{} at ({},{}) of the monitor with id {}", preset.name, preset.width, preset.height,
+                         preset.x, preset.y, preset.monitor_id);
+            }
+            return Ok(());
+        }
+        Command::Pdf(ref inputs) => {
+            // the dpi of saved captures is unknown, use the standard one
+            let mut pages = vec![];
+            for path in inputs {
+                let image = image::open(path).context(format!("Cannot read {}", path.display()))?;
+                pages.push((image, BASE_DPI));
+            }
+            let output = options.output.as_ref().ok_or("pdf requires --output".to_string())?;
+            fs::write(output, images_to_pdf(&pages)?).context(format!("Cannot write {}", output.display()))?;
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let mut settings = Settings::load();
+    if let Some(ref profile) = options.profile {
+        if !settings.profile_names().contains(profile) {
+            return Err(format!("No profile named {}, available: {}", profile, settings.profile_names().join(", ")).into());
+        }
+        settings.switch_profile(profile)?;
+    }
+    let format = options.format.clone().unwrap_or(settings.save_format.clone());
+
+    if options.delay > 0.0 {
+        thread::sleep(Duration::from_secs_f64(options.delay));
+    }
+    let (image, monitor) = capture(&*backend, &settings, &options.command).map_err(Error::Capture)?;
+    let bytes = if format == "pdf" {
+        let dpi = match options.command {
+            Command::Screen(index) => capture_dpi(&*backend, Some(index)),
+            _ => capture_dpi(&*backend, None),
+        };
+        images_to_pdf(&[(image.clone(), dpi)])?
+    } else {
+        encode_image(&image, &format, options.quality.unwrap_or(settings.quality as u8), options.lossless || settings.lossless)?
+    };
+
+    if options.stdout {
+        std::io::stdout().write_all(&bytes).context("Cannot write to the standard output")?;
+    }
+
+    if writes_file(&options) {
+        let path = match options.output {
+            Some(ref path) => path.clone(),
+            None => {
+                let values = TemplateValues { counter: settings.screenshot_number, monitor, width: image.width(), height: image.height(), frame: 0 };
+                unique_path(&settings.save_path, &expand_template(&settings.file_template, &values), &format)
+            }
+        };
+        fs::write(&path, bytes).context(format!("Cannot write {}", path.display()))?;
+        // the number is used only once the file is written; only the counter is stored, --profile does not
+        // change the active profile
+        if options.output.is_none() {
+            let mut stored = Settings::load();
+            stored.screenshot_number = settings.screenshot_number.checked_add(1).unwrap_or(0);
+            stored.save()?;
+        }
+        if !options.stdout {
+            println!("{}", path.display());
+        }
+    }
+
+    if options.clipboard {
+        copy_to_clipboard(&image, true)?;
+    }
+
+    Ok(())
+}
+
+// run the command line mode and return the process exit code
+pub fn run_cli(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("Error: {}\n\n{}", error, USAGE);
+            return 2;
+        }
+    };
+    match execute(options) {
+        Ok(()) => 0,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::GenericImageView;
+    use crate::capture::SyntheticBackend;
+    use crate::settings::RegionPreset;
+    use super::*;
+
+    fn parse(line: &str) -> Result<CliOptions, String> {
+        parse_args(&line.split_whitespace().map(str::to_string).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(parse("full").unwrap().command, Command::Full);
+        assert_eq!(parse("screen --index 1").unwrap().command, Command::Screen(1));
+        assert_eq!(parse("region --x -10 --y 80 --w 1280 --h 720").unwrap().command, Command::Region(-10, 80, 1280, 720));
+        assert_eq!(parse("preset --name browser").unwrap().command, Command::Preset("browser".to_string()));
+        assert_eq!(parse("pdf --output out.pdf a.png b.png").unwrap().command,
+                   Command::Pdf(vec![PathBuf::from("a.png"), PathBuf::from("b.png")]));
+        assert_eq!(parse("--help").unwrap().command, Command::Help);
+    }
+
+    #[test]
+    fn options() {
+        let options = parse("full --delay 2.5 --format JPG --quality 70 --lossless --profile work").unwrap();
+        assert_eq!(options.delay, 2.5);
+        assert_eq!(options.format.as_deref(), Some("jpg"));
+        assert_eq!(options.quality, Some(70));
+        assert!(options.lossless);
+        assert_eq!(options.profile.as_deref(), Some("work"));
+    }
+
+    #[test]
+    fn destinations() {
+        // without a destination the capture is saved with the automatic name
+        assert!(writes_file(&parse("full").unwrap()));
+        assert!(!writes_file(&parse("full --stdout").unwrap()));
+        assert!(!writes_file(&parse("full --clipboard").unwrap()));
+        let options = parse("full --stdout --clipboard --output a.png").unwrap();
+        assert!(options.stdout && options.clipboard);
+        assert!(writes_file(&options));
+    }
+
+    #[test]
+    fn bad_input() {
+        for line in ["", "shot", "screen", "screen --index -1", "screen --index", "region --x 0 --y 0 --w 10",
+                     "region --x 0 --y 0 --w 0 --h 10", "preset", "full --delay -1", "full --delay nan",
+                     "full --quality 101", "full --quality 300", "full --verbose", "full a.png", "pdf a.png",
+                     "pdf --output out.pdf"] {
+            assert!(parse(line).is_err(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn config_option_is_taken() {
+        let mut args: Vec<String> = ["full", "--config", "/tmp/s.json", "--stdout"].iter().map(|a| a.to_string()).collect();
+        assert_eq!(take_config_option(&mut args), Ok(Some(PathBuf::from("/tmp/s.json"))));
+        assert_eq!(args, ["full", "--stdout"]);
+        assert!(take_config_option(&mut vec!["full".to_string(), "--config".to_string()]).is_err());
+    }
+
+    #[test]
+    fn captures() {
+        let backend = SyntheticBackend::default();
+        let mut settings = Settings::default();
+        settings.add_preset(RegionPreset { name: "corner".to_string(), monitor_id: 1, x: 10, y: 20, width: 30, height: 40 }).unwrap();
+
+        let (image, monitor) = capture(&backend, &settings, &Command::Full).unwrap();
+        assert_eq!((image.dimensions(), monitor.as_str()), ((3840, 1080), "all"));
+        let (image, monitor) = capture(&backend, &settings, &Command::Screen(1)).unwrap();
+        assert_eq!((image.dimensions(), monitor.as_str()), ((1920, 1080), "Synthetic 1"));
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 255, 255]);
+        let (image, monitor) = capture(&backend, &settings, &Command::Region(1900, 0, 40, 10)).unwrap();
+        assert_eq!((image.dimensions(), monitor.as_str()), ((40, 10), "region"));
+        let (image, monitor) = capture(&backend, &settings, &Command::Preset("corner".to_string())).unwrap();
+        assert_eq!((image.dimensions(), monitor.as_str()), ((30, 40), "corner"));
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 255, 255]);
+
+        assert!(capture(&backend, &settings, &Command::Screen(2)).is_err());
+        assert!(capture(&backend, &settings, &Command::Preset("missing".to_string())).is_err());
+        assert!(capture(&backend, &settings, &Command::List).is_err());
+    }
+}
@@ -1,5 +1,5 @@
 pub const APP_NAME: &str = "Multi-platform screen-grabbing utility";
-pub const INIT_FILE : &[u8] = include_bytes!("../init.json");
+pub const FONT_DATA : &[u8] = include_bytes!("../OpenSans-Semibold.ttf");
 pub const BUTTON_WIDTH: f64 = 80.0;
 pub const BUTTON_HEIGHT: f64 = 25.0;
 pub const NORMAL_BIG_IMAGE_LIMIT : f64 = 0.5;
@@ -12,3 +12,49 @@ pub const TRANSPARENCY: u8 = 60;
 pub const OFFSET_Y : f64 = 30.0;
 pub const OFFSET_X : f64 = 7.0;
 pub const WINDOW_MULTIPLIER: f64 = 1.1;
+pub const CAPTURE_BACKEND_VAR: &str = "PDS_CAPTURE_BACKEND";
+pub const HISTORY_LIMIT: usize = 50;
+pub const PROJECT_EXTENSION: &str = "pdsp";
+pub const PROJECT_VERSION: u32 = 2;
+pub const BASE_DPI: f32 = 96.0;
+pub const DEFAULT_QUALITY: f64 = 80.0;
+// 0 (slowest, smallest) to 10 (fastest)
+pub const AVIF_SPEED: u8 = 6;
+pub const DEFAULT_FILE_TEMPLATE: &str = "Screen{counter}";
+pub const SETTINGS_FILE: &str = "settings.json";
+pub const SETTINGS_VERSION: u32 = 4;
+pub const CONFIG_DIR_NAME: &str = "pds_project";
+pub const DEFAULT_PROFILE: &str = "default";
+// wait before a capture without overlay, while the application windows are being closed
+pub const HIDE_DELAY_MS: u64 = 300;
+// monitor layouts whose last selected region is remembered
+pub const LAST_REGIONS_LIMIT: usize = 8;
+// longest timer delay in seconds, and the refresh interval of the countdown
+pub const MAX_DELAY: f64 = 3600.0;
+pub const COUNTDOWN_TICK_MS: u64 = 100;
+pub const COUNTDOWN_WINDOW_WIDTH: f64 = 280.0;
+pub const COUNTDOWN_WINDOW_HEIGHT: f64 = 170.0;
+pub const DEFAULT_TIMELAPSE_TEMPLATE: &str = "Timelapse{counter}-{frame}";
+// shortest interval between two timelapse shots, in seconds
+pub const MIN_TIMELAPSE_INTERVAL: f64 = 0.1;
+pub const TIMELAPSE_STOP_POLL_MS: u64 = 50;
+// animated recording limits, and the neuquant sampling factor (1 best quality, 30 fastest)
+pub const MAX_RECORD_FPS: f64 = 30.0;
+pub const MAX_RECORD_DURATION: f64 = 60.0;
+pub const MAX_RECORD_BYTES: u64 = 2 << 30;
+pub const MIN_GIF_COLORS: u16 = 8;
+pub const GIF_SAMPLE_FACTOR: i32 = 10;
+// block size of the pixelate tool and strength of the blur tool: the settings are in screen pixels, the annotations
+// (converted by tool_width) in pixels of the capture, and both are kept in these bounds
+pub const MIN_REDACT_STRENGTH: f64 = 2.0;
+pub const MAX_REDACT_STRENGTH: f64 = 64.0;
+// radius of the step badges relative to the text size
+pub const STEP_RADIUS_FACTOR: f64 = 0.9;
+// stroke width of the shapes, thinner strokes are drawn as one pixel lines
+pub const MIN_STROKE_WIDTH: f64 = 1.0;
+pub const MAX_STROKE_WIDTH: f64 = 20.0;
+pub const HAIRLINE_WIDTH: f64 = 2.0;
+// distance in screen pixels within which a click picks an annotation
+pub const SELECT_TOLERANCE: f64 = 4.0;
+// corner radius of the rounded rectangles, in screen pixels
+pub const MAX_CORNER_RADIUS: f64 = 100.0;
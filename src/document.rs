@@ -0,0 +1,882 @@
+// ANNOTATION DOCUMENT
+// the annotations are kept as objects layered over the original capture, and they are
+// rasterised only when the image is shown, saved or copied
+
+use std::f64::consts::PI;
+use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
+use image::imageops::{self, FilterType, overlay};
+use druid::Data;
+use imageproc::drawing::{draw_filled_circle_mut, draw_line_segment_mut, draw_polygon_mut, draw_text_mut, text_size};
+use rusttype::Font;
+use serde::{Serialize, Deserialize};
+use crate::Annotation;
+use crate::constants::{FONT_DATA, HAIRLINE_WIDTH, MAX_REDACT_STRENGTH, MIN_REDACT_STRENGTH, STEP_RADIUS_FACTOR, TRANSPARENCY};
+
+// x, y, width, height in pixels of the original capture
+pub type CropRect = (u32,u32,u32,u32);
+
+#[derive(Clone, Copy, Data, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub enum LineStyle {
+    #[default]
+    Solid,
+    Dashed,
+    Dotted,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct AnnotationObject {
+    pub id: u32,
+    pub kind: Annotation,
+    // geometry in pixels of the original capture: first and last point of the drag for the shapes,
+    // every point for the free line, the insertion point for the text
+    pub points: Vec<(f64,f64)>,
+    pub color: (u8,u8,u8,u8),
+    // width of the highlighter, stroke width of the shapes, block size of the pixelate and strength of the blur
+    pub width: f64,
+    // outline and inside of the shapes, None is hollow
+    #[serde(default)]
+    pub line_style: LineStyle,
+    #[serde(default)]
+    pub fill: Option<(u8,u8,u8,u8)>,
+    // radius of the corners of the rounded rectangle
+    #[serde(default)]
+    pub corner_radius: f64,
+    // text of the text annotation, number of the step badge
+    pub text: String,
+    pub text_size: f64,
+    // drawing order, higher is on top
+    pub z: u32,
+}
+
+impl AnnotationObject {
+    pub fn new(kind: Annotation, points: Vec<(f64,f64)>, color: (u8,u8,u8,u8)) -> Self {
+        AnnotationObject { id: 0, kind, points, color, width: 1.0, line_style: LineStyle::Solid, fill: None, corner_radius: 0.0, text: String::new(), text_size: 10.0, z: 0 }
+    }
+
+    fn contains(&self, point: (f64,f64), tolerance: f64) -> bool {
+        if self.points.is_empty() {
+            return false;
+        }
+        let (min_x, min_y, max_x, max_y) = self.bounds();
+        point.0 >= min_x - tolerance && point.0 <= max_x + tolerance && point.1 >= min_y - tolerance && point.1 <= max_y + tolerance
+    }
+
+    pub fn first_point(&self) -> (f64,f64) {
+        self.points[0]
+    }
+
+    pub fn last_point(&self) -> (f64,f64) {
+        self.points[self.points.len() - 1]
+    }
+
+    // rect (min_x, min_y, max_x, max_y) covered by the annotation, used to pick it in the editor
+    pub fn bounds(&self) -> (f64,f64,f64,f64) {
+        let p1 = self.last_point();
+        match self.kind {
+            Annotation::Text => {
+                let font: Font<'static> = Font::try_from_bytes(FONT_DATA).unwrap();
+                let (width, height) = text_size(rusttype::Scale::uniform(self.text_size as f32), &font, &self.text);
+                (p1.0, p1.1, p1.0 + width as f64, p1.1 + (height as f64).max(self.text_size))
+            }
+            Annotation::Step => {
+                let radius = self.text_size * STEP_RADIUS_FACTOR;
+                (p1.0 - radius, p1.1 - radius, p1.0 + radius, p1.1 + radius)
+            }
+            _ => {
+                // the highlighter width is on each side of the segment, the stroke is centered on the outline
+                let margin = match self.kind {
+                    Annotation::Highlighter => self.width,
+                    ref kind if kind.is_shape() => self.width / 2.0,
+                    _ => 0.0,
+                };
+                let (min_x, min_y) = self.points.iter().fold((f64::INFINITY, f64::INFINITY), |min, p| (min.0.min(p.0), min.1.min(p.1)));
+                let (max_x, max_y) = self.points.iter().fold((f64::NEG_INFINITY, f64::NEG_INFINITY), |max, p| (max.0.max(p.0), max.1.max(p.1)));
+                (min_x - margin, min_y - margin, max_x + margin, max_y + margin)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Document {
+    // crop applied to the original capture, None for the whole capture
+    pub crop: Option<CropRect>,
+    // kept sorted by z
+    annotations: Vec<AnnotationObject>,
+    next_id: u32,
+}
+
+impl Document {
+    pub fn annotations(&self) -> &[AnnotationObject] {
+        &self.annotations
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.annotations.is_empty()
+    }
+
+    // add the annotation on top of the others, returning its id
+    pub fn add(&mut self, mut object: AnnotationObject) -> u32 {
+        object.id = self.next_id;
+        object.z = self.top_z() + 1;
+        self.next_id += 1;
+        self.annotations.push(object);
+        self.renumber_steps();
+        self.next_id - 1
+    }
+
+    // the step badges are numbered 1, 2, 3.. in the order they were placed, also after a removal
+    fn renumber_steps(&mut self) {
+        let mut steps: Vec<&mut AnnotationObject> = self.annotations.iter_mut().filter(|a| a.kind == Annotation::Step).collect();
+        steps.sort_by_key(|a| a.id);
+        for (index, step) in steps.into_iter().enumerate() {
+            step.text = (index + 1).to_string();
+        }
+    }
+
+    pub fn get(&self, id: u32) -> Option<&AnnotationObject> {
+        self.annotations.iter().find(|a| a.id == id)
+    }
+
+    pub fn remove(&mut self, id: u32) -> Option<AnnotationObject> {
+        let index = self.annotations.iter().position(|a| a.id == id)?;
+        let object = self.annotations.remove(index);
+        self.renumber_steps();
+        Some(object)
+    }
+
+    // remove the annotation on top
+    pub fn remove_last(&mut self) -> Option<AnnotationObject> {
+        let object = self.annotations.pop();
+        self.renumber_steps();
+        object
+    }
+
+    pub fn move_by(&mut self, id: u32, dx: f64, dy: f64) {
+        if let Some(object) = self.annotations.iter_mut().find(|a| a.id == id) {
+            for point in object.points.iter_mut() {
+                point.0 += dx;
+                point.1 += dy;
+            }
+        }
+    }
+
+    pub fn restyle(&mut self, id: u32, color: (u8,u8,u8,u8), width: f64) {
+        if let Some(object) = self.annotations.iter_mut().find(|a| a.id == id) {
+            object.color = color;
+            object.width = width;
+        }
+    }
+
+    // the projects before version 2 stored the highlighter width also in the shapes, that were drawn 1 pixel wide
+    pub fn reset_shape_widths(&mut self) {
+        for object in self.annotations.iter_mut().filter(|a| a.kind.is_shape()) {
+            object.width = 1.0;
+        }
+    }
+
+    pub fn bring_to_front(&mut self, id: u32) {
+        let top = self.top_z();
+        if let Some(object) = self.annotations.iter_mut().find(|a| a.id == id) {
+            object.z = top + 1;
+        }
+        self.annotations.sort_by_key(|a| a.z);
+    }
+
+    // the others are numbered again above it, a lower z than 0 does not exist
+    pub fn send_to_back(&mut self, id: u32) {
+        if let Some(index) = self.annotations.iter().position(|a| a.id == id) {
+            let object = self.annotations.remove(index);
+            self.annotations.insert(0, object);
+            for (z, object) in self.annotations.iter_mut().enumerate() {
+                object.z = z as u32;
+            }
+        }
+    }
+
+    // the topmost annotation whose bounds, grown by the tolerance, contain the point
+    pub fn hit_test(&self, point: (f64,f64), tolerance: f64) -> Option<u32> {
+        self.annotations.iter().rev()
+            .find(|a| a.contains(point, tolerance))
+            .map(|a| a.id)
+    }
+
+    // the topmost step badge under the point, also when another annotation covers it
+    pub fn step_at(&self, point: (f64,f64), tolerance: f64) -> Option<u32> {
+        self.annotations.iter().rev()
+            .find(|a| a.kind == Annotation::Step && a.contains(point, tolerance))
+            .map(|a| a.id)
+    }
+
+    fn top_z(&self) -> u32 {
+        self.annotations.iter().map(|a| a.z).max().unwrap_or(0)
+    }
+
+    // top left corner of the visible area in pixels of the original capture
+    pub fn origin(&self) -> (f64,f64) {
+        match self.crop {
+            Some((x, y, _, _)) => (x as f64, y as f64),
+            None => (0.0, 0.0),
+        }
+    }
+
+    // crop again, the rect is relative to the currently visible area
+    pub fn crop_to(&mut self, x: u32, y: u32, width: u32, height: u32) {
+        let (origin_x, origin_y) = self.origin();
+        self.crop = Some((origin_x as u32 + x, origin_y as u32 + y, width, height));
+    }
+
+    // annotations up to the topmost redaction, that must be rasterised to destroy the pixels under it,
+    // and the ones above it, that can be kept as vectors
+    pub fn split_at_redaction(&self) -> (&[AnnotationObject], &[AnnotationObject]) {
+        let end = self.annotations.iter().rposition(|a| a.kind.is_redaction()).map_or(0, |index| index + 1);
+        self.annotations.split_at(end)
+    }
+
+    // rasterise the annotations over the original capture and apply the crop
+    pub fn flatten(&self, original: &DynamicImage) -> DynamicImage {
+        self.flatten_objects(original, &self.annotations)
+    }
+
+    // as flatten, with only some of the annotations
+    pub fn flatten_objects(&self, original: &DynamicImage, objects: &[AnnotationObject]) -> DynamicImage {
+        let mut canvas = original.to_rgba8();
+        for object in objects.iter() {
+            draw_annotation(&mut canvas, object);
+        }
+        let image = DynamicImage::from(canvas);
+        match self.crop {
+            Some((x, y, width, height)) => image.crop_imm(x, y, width, height),
+            None => image,
+        }
+    }
+}
+
+// segments of the arrow: main line and the two lines of the tip
+pub fn arrow_segments(p0: (f64,f64), p1: (f64,f64)) -> [((f64,f64),(f64,f64)); 3] {
+    //direzione = endX - startX , endY - startY
+    let direction = (p1.0 - p0.0, p1.1 - p0.1);
+    //lunghezza = ipotenusa teorema di pitagora
+    let arrow_length = (direction.0.powi(2) + direction.1.powi(2)).sqrt();
+    // angolo tra asseX e freccia
+    let angle = direction.1.atan2(direction.0);
+    // lunghezza punta della freccia [settata ad un terzo]
+    let arrow_tip = arrow_length/3.0;
+
+    // Calcola punti della punta della freccia
+    let arrow_x1 = p1.0 - (direction.0 / arrow_length);
+    let arrow_y1 = p1.1 - (direction.1 / arrow_length);
+
+    let arrow_l0_p1 = (arrow_x1 - arrow_tip * (angle + PI / 6.0).cos(), arrow_y1 - arrow_tip * (angle + PI / 6.0).sin());
+    let arrow_l1_p1 = (arrow_x1 - arrow_tip * (angle - PI / 6.0).cos(), arrow_y1 - arrow_tip * (angle - PI / 6.0).sin());
+
+    [(p0, p1), (p1, arrow_l0_p1), (p1, arrow_l1_p1)]
+}
+
+// the four vertices of the highlighter rectangle around the segment
+pub fn highlighter_polygon(p0: (f64,f64), p1: (f64,f64), width: f64) -> [(f64,f64); 4] {
+    // Calculate the slope of the line
+    let slope = (p1.1 - p0.1) / (p1.0 - p0.0);
+
+    // Calculate the angle of the line with respect to the horizontal axis
+    let angle = slope.atan();
+
+    // Calculate the change in x and y coordinates for the margin
+    let delta_x = width * (angle + PI / 2.0).cos();
+    let delta_y = width * (angle + PI / 2.0).sin();
+
+    [(p0.0 + delta_x, p0.1 + delta_y), (p0.0 - delta_x, p0.1 - delta_y),
+     (p1.0 - delta_x, p1.1 - delta_y), (p1.0 + delta_x, p1.1 + delta_y)]
+}
+
+// what is painted for a shape, in pixels of the capture. The rasterisation, the preview of the edit window
+// and the svg export all draw this same geometry, so the shape looks the same everywhere
+pub struct ShapeGeometry {
+    // polygon of the inside
+    pub fill: Option<Vec<(f64,f64)>>,
+    // dashes of the outline, each one a polyline with round joins and caps; a dash of one point is a dot
+    pub pieces: Vec<Vec<(f64,f64)>>,
+    pub width: f64,
+}
+
+fn distance(a: (f64,f64), b: (f64,f64)) -> f64 {
+    ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt()
+}
+
+// the outline polylines of a shape, closed ones end on their first point
+fn shape_outline(object: &AnnotationObject) -> Vec<Vec<(f64,f64)>> {
+    let (p0, p1) = (object.first_point(), object.last_point());
+    let (min_x, min_y) = (p0.0.min(p1.0), p0.1.min(p1.1));
+    let (max_x, max_y) = (p0.0.max(p1.0), p0.1.max(p1.1));
+    match object.kind {
+        Annotation::Circle => {
+            // the drag box is inscribed in the circle
+            let center = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+            let radius = ((max_x - min_x).powi(2) + (max_y - min_y).powi(2)).sqrt() / 2.0;
+            // about one vertex every 2 pixels
+            let steps = (PI * radius).clamp(24.0, 720.0) as usize;
+            vec![(0..=steps).map(|step| {
+                let angle = 2.0 * PI * step as f64 / steps as f64;
+                (center.0 + radius * angle.cos(), center.1 + radius * angle.sin())
+            }).collect()]
+        }
+        Annotation::Ellipse => {
+            let center = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+            let (radius_x, radius_y) = ((max_x - min_x) / 2.0, (max_y - min_y) / 2.0);
+            let steps = (PI * (radius_x + radius_y) / 2.0).clamp(24.0, 720.0) as usize;
+            vec![(0..=steps).map(|step| {
+                let angle = 2.0 * PI * step as f64 / steps as f64;
+                (center.0 + radius_x * angle.cos(), center.1 + radius_y * angle.sin())
+            }).collect()]
+        }
+        Annotation::Rectangle => vec![vec![(min_x, min_y), (max_x, min_y), (max_x, max_y), (min_x, max_y), (min_x, min_y)]],
+        Annotation::RoundedRectangle => {
+            // the corners cannot be larger than half the shorter side
+            let radius = object.corner_radius.clamp(0.0, (max_x - min_x).min(max_y - min_y) / 2.0);
+            let steps = (PI * radius / 4.0).clamp(1.0, 180.0) as usize;
+            // center of each corner arc and its starting angle, clockwise from the top left
+            let corners = [((min_x + radius, min_y + radius), PI), ((max_x - radius, min_y + radius), 1.5 * PI),
+                           ((max_x - radius, max_y - radius), 0.0), ((min_x + radius, max_y - radius), 0.5 * PI)];
+            let mut outline: Vec<(f64,f64)> = corners.iter().flat_map(|(center, start)| (0..=steps).map(move |step| {
+                let angle = start + PI / 2.0 * step as f64 / steps as f64;
+                (center.0 + radius * angle.cos(), center.1 + radius * angle.sin())
+            })).collect();
+            outline.dedup();
+            outline.push(outline[0]);
+            vec![outline]
+        }
+        Annotation::Line => vec![vec![p0, p1]],
+        Annotation::Cross => vec![vec![p0, p1], vec![(p0.0, p1.1), (p1.0, p0.1)]],
+        Annotation::Arrow => arrow_segments(p0, p1).iter().map(|(start, end)| vec![*start, *end]).collect(),
+        _ => vec![],
+    }
+}
+
+// split a polyline in the dashes of the line style, the lengths grow with the width
+fn dash_polyline(points: &[(f64,f64)], style: LineStyle, width: f64) -> Vec<Vec<(f64,f64)>> {
+    let unit = width.max(1.0);
+    let (on, off) = match style {
+        LineStyle::Solid => return vec![points.to_vec()],
+        LineStyle::Dashed => (3.0 * unit, 3.0 * unit),
+        // a dash of length 0 is a dot, as wide as the line
+        LineStyle::Dotted => (0.0, 2.0 * unit),
+    };
+    let mut pieces = vec![];
+    let mut current = vec![points[0]];
+    let mut drawing = true;
+    // length left of the current dash or gap
+    let mut left = on;
+    for pair in points.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        let length = distance(a, b);
+        let mut done = 0.0;
+        while length - done > left {
+            done += left;
+            let point = (a.0 + (b.0 - a.0) * done / length, a.1 + (b.1 - a.1) * done / length);
+            if drawing {
+                current.push(point);
+                pieces.push(std::mem::take(&mut current));
+            } else {
+                current = vec![point];
+            }
+            drawing = !drawing;
+            left = if drawing { on } else { off };
+        }
+        left -= length - done;
+        if drawing {
+            current.push(b);
+        }
+    }
+    if drawing {
+        pieces.push(current);
+    }
+    for piece in pieces.iter_mut() {
+        piece.dedup();
+    }
+    pieces
+}
+
+pub fn shape_geometry(object: &AnnotationObject) -> ShapeGeometry {
+    let outline = shape_outline(object);
+    let fill = match (object.fill, outline.first()) {
+        (Some(_), Some(polygon)) if object.kind.is_closed_shape() => Some(polygon[..polygon.len() - 1].to_vec()),
+        _ => None,
+    };
+    let pieces = outline.iter()
+        .flat_map(|polyline| dash_polyline(polyline, object.line_style, object.width))
+        .filter(|piece| !piece.is_empty())
+        .collect();
+    ShapeGeometry { fill, pieces, width: object.width }
+}
+
+// rectangle of the given width around the segment, with the orientation of the kurbo circles, so that
+// the quads and the round joins of a stroke fill one area with the nonzero rule
+pub fn segment_quad(a: (f64,f64), b: (f64,f64), width: f64) -> Option<[(f64,f64); 4]> {
+    let length = distance(a, b);
+    if length < f64::EPSILON {
+        return None;
+    }
+    let normal = (-(b.1 - a.1) / length * width / 2.0, (b.0 - a.0) / length * width / 2.0);
+    let mut quad = [(a.0 + normal.0, a.1 + normal.1), (b.0 + normal.0, b.1 + normal.1),
+                    (b.0 - normal.0, b.1 - normal.1), (a.0 - normal.0, a.1 - normal.1)];
+    let area: f64 = (0..4).map(|i| quad[i].0 * quad[(i + 1) % 4].1 - quad[(i + 1) % 4].0 * quad[i].1).sum();
+    if area < 0.0 {
+        quad.reverse();
+    }
+    Some(quad)
+}
+
+fn to_i32_polygon(points: &[(f64,f64)], offset: (f64,f64)) -> Vec<imageproc::point::Point<i32>> {
+    let mut polygon: Vec<imageproc::point::Point<i32>> = points.iter()
+        .map(|p| imageproc::point::Point::new((p.0 - offset.0).round() as i32, (p.1 - offset.1).round() as i32))
+        .collect();
+    polygon.dedup();
+    // draw_polygon_mut does not accept a closed polygon
+    while polygon.len() > 1 && polygon[0] == polygon[polygon.len() - 1] {
+        polygon.pop();
+    }
+    polygon
+}
+
+// draw on a transparent layer over the bounding box of the points, then blend it over the canvas: the parts
+// drawn more than once are blended only once, as in the preview
+fn blend_layer(canvas: &mut RgbaImage, points: &[(f64,f64)], margin: f64, draw: impl FnOnce(&mut RgbaImage, (f64,f64))) {
+    let (mut min, mut max) = ((f64::MAX, f64::MAX), (f64::MIN, f64::MIN));
+    for point in points {
+        min = (min.0.min(point.0), min.1.min(point.1));
+        max = (max.0.max(point.0), max.1.max(point.1));
+    }
+    let x0 = (min.0 - margin).floor().max(0.0);
+    let y0 = (min.1 - margin).floor().max(0.0);
+    let x1 = (max.0 + margin).ceil().min(canvas.width() as f64);
+    let y1 = (max.1 + margin).ceil().min(canvas.height() as f64);
+    if x1 <= x0 || y1 <= y0 {
+        return;
+    }
+    let mut layer = ImageBuffer::from_pixel((x1 - x0) as u32, (y1 - y0) as u32, Rgba([0, 0, 0, 0]));
+    draw(&mut layer, (x0, y0));
+    overlay(canvas, &layer, x0 as i64, y0 as i64);
+}
+
+fn draw_shape(canvas: &mut RgbaImage, object: &AnnotationObject) {
+    let geometry = shape_geometry(object);
+    if let (Some(polygon), Some(fill)) = (&geometry.fill, object.fill) {
+        blend_layer(canvas, polygon, 1.0, |layer, offset| {
+            let polygon = to_i32_polygon(polygon, offset);
+            if polygon.len() > 2 {
+                draw_polygon_mut(layer, &polygon, Rgba([fill.0, fill.1, fill.2, fill.3]));
+            }
+        });
+    }
+
+    let color = Rgba([object.color.0, object.color.1, object.color.2, object.color.3]);
+    let width = geometry.width;
+    let points: Vec<(f64,f64)> = geometry.pieces.iter().flatten().copied().collect();
+    blend_layer(canvas, &points, width, |layer, offset| {
+        let local = |p: (f64,f64)| (p.0 - offset.0, p.1 - offset.1);
+        for piece in geometry.pieces.iter() {
+            if width < HAIRLINE_WIDTH {
+                let (x, y) = local(piece[0]);
+                if piece.len() == 1 && x >= 0.0 && y >= 0.0 && (x as u32) < layer.width() && (y as u32) < layer.height() {
+                    layer.put_pixel(x as u32, y as u32, color);
+                }
+                for segment in piece.windows(2) {
+                    draw_line_segment_mut(layer, to_f32(local(segment[0])), to_f32(local(segment[1])), color);
+                }
+            } else {
+                for segment in piece.windows(2) {
+                    if let Some(quad) = segment_quad(segment[0], segment[1], width) {
+                        let polygon = to_i32_polygon(&quad, offset);
+                        if polygon.len() > 2 {
+                            draw_polygon_mut(layer, &polygon, color);
+                        }
+                    }
+                }
+                // round joins and caps
+                for point in piece.iter() {
+                    let (x, y) = local(*point);
+                    draw_filled_circle_mut(layer, (x.round() as i32, y.round() as i32), (width / 2.0).round() as i32, color);
+                }
+            }
+        }
+    });
+}
+
+// radius of a step badge, and the top left corner of its number as placed by draw_text_mut, so that the
+// digits (not the whole line height) are centered in the circle
+pub fn step_badge_layout(center: (f64,f64), text_size: f64, number: &str) -> (f64, (f64,f64)) {
+    let font: Font<'static> = Font::try_from_bytes(FONT_DATA).unwrap();
+    let scale = rusttype::Scale::uniform(text_size as f32);
+    let ascent = font.v_metrics(scale).ascent;
+    let bounds = font.layout(number, scale, rusttype::point(0.0, ascent))
+        .filter_map(|glyph| glyph.pixel_bounding_box())
+        .reduce(|a, b| rusttype::Rect { min: rusttype::point(a.min.x.min(b.min.x), a.min.y.min(b.min.y)),
+                                        max: rusttype::point(a.max.x.max(b.max.x), a.max.y.max(b.max.y)) });
+    let (text_x, text_y) = match bounds {
+        Some(rect) => ((rect.min.x + rect.max.x) as f64 / 2.0, (rect.min.y + rect.max.y) as f64 / 2.0),
+        None => (0.0, 0.0),
+    };
+    (text_size * STEP_RADIUS_FACTOR, (center.0 - text_x, center.1 - text_y))
+}
+
+// black or white, the one readable over the color
+pub fn contrast_color(color: (u8,u8,u8,u8)) -> (u8,u8,u8,u8) {
+    let luminance = 0.299 * color.0 as f64 + 0.587 * color.1 as f64 + 0.114 * color.2 as f64;
+    if luminance > 160.0 { (0, 0, 0, 255) } else { (255, 255, 255, 255) }
+}
+
+fn to_f32(point: (f64,f64)) -> (f32,f32) {
+    (point.0 as f32, point.1 as f32)
+}
+
+// pixels of the canvas covered by the rect, None if outside
+fn canvas_rect(canvas: &RgbaImage, min: (f64,f64), max: (f64,f64)) -> Option<(u32,u32,u32,u32)> {
+    let (x0, y0) = (min.0.max(0.0).floor() as u32, min.1.max(0.0).floor() as u32);
+    let (x1, y1) = (max.0.min(canvas.width() as f64).ceil() as u32, max.1.min(canvas.height() as f64).ceil() as u32);
+    if x1 <= x0 || y1 <= y0 {
+        return None;
+    }
+    Some((x0, y0, x1 - x0, y1 - y0))
+}
+
+// start and end of the blocks of a row or column: the last block also takes the pixels left over, a smaller
+// one (down to a single pixel) would keep the original colors
+fn pixelate_blocks(start: u32, length: u32, block: u32) -> Vec<(u32,u32)> {
+    let count = (length / block).max(1);
+    (0..count)
+        .map(|index| (start + index * block, if index + 1 == count { start + length } else { start + (index + 1) * block }))
+        .collect()
+}
+
+// every block gets the average color of its pixels
+fn pixelate(canvas: &mut RgbaImage, (x, y, width, height): (u32,u32,u32,u32), block: u32) {
+    for (block_y, y_end) in pixelate_blocks(y, height, block) {
+        for (block_x, x_end) in pixelate_blocks(x, width, block) {
+            let mut sum = [0u64; 4];
+            for py in block_y..y_end {
+                for px in block_x..x_end {
+                    for (total, channel) in sum.iter_mut().zip(canvas.get_pixel(px, py).0) {
+                        *total += channel as u64;
+                    }
+                }
+            }
+            let count = ((x_end - block_x) * (y_end - block_y)) as u64;
+            let average = Rgba(sum.map(|total| (total / count) as u8));
+            for py in block_y..y_end {
+                for px in block_x..x_end {
+                    canvas.put_pixel(px, py, average);
+                }
+            }
+        }
+    }
+}
+
+// the region is downscaled before the blur, so the details are lost and not only spread
+// (a plain gaussian blur can be partly reversed)
+fn blur(canvas: &mut RgbaImage, (x, y, width, height): (u32,u32,u32,u32), strength: f64) {
+    let region = imageops::crop_imm(&*canvas, x, y, width, height).to_image();
+    let small = imageops::resize(&region, (width as f64 / strength).ceil() as u32, (height as f64 / strength).ceil() as u32, FilterType::Triangle);
+    let large = imageops::resize(&small, width, height, FilterType::Triangle);
+    imageops::replace(canvas, &imageops::blur(&large, (strength / 2.0) as f32), x as i64, y as i64);
+}
+
+pub fn draw_annotation(canvas: &mut RgbaImage, object: &AnnotationObject) {
+    if object.points.is_empty() {
+        return;
+    }
+    let color = Rgba([object.color.0, object.color.1, object.color.2, object.color.3]);
+    let (p0, p1) = (object.first_point(), object.last_point());
+    let (min_x, min_y) = (p0.0.min(p1.0), p0.1.min(p1.1));
+    let (max_x, max_y) = (p0.0.max(p1.0), p0.1.max(p1.1));
+
+    match object.kind {
+        Annotation::None | Annotation::Select => {}
+        Annotation::Circle | Annotation::Rectangle | Annotation::Line | Annotation::Cross | Annotation::Arrow
+        | Annotation::Ellipse | Annotation::RoundedRectangle => {
+            draw_shape(canvas, object);
+        }
+        Annotation::FreeLine => {
+            for segment in object.points.windows(2) {
+                draw_line_segment_mut(canvas, to_f32(segment[0]), to_f32(segment[1]), color);
+            }
+        }
+        Annotation::Highlighter => {
+            let poly: Vec<imageproc::point::Point<i32>> = highlighter_polygon(p0, p1, object.width).iter()
+                .map(|p| imageproc::point::Point::new(p.0 as i32, p.1 as i32))
+                .collect();
+            // a degenerate polygon cannot be drawn
+            if poly[0] != poly[poly.len() - 1] {
+                let mut transparent_image = ImageBuffer::from_pixel(canvas.width(), canvas.height(), Rgba([0, 0, 0, 0]));
+                draw_polygon_mut(&mut transparent_image, &poly, Rgba([object.color.0, object.color.1, object.color.2, TRANSPARENCY]));
+                overlay(canvas, &transparent_image, 0, 0);
+            }
+        }
+        Annotation::Text => {
+            let font: Font<'static> = Font::try_from_bytes(FONT_DATA).unwrap();
+            draw_text_mut(canvas, color, p1.0 as i32, p1.1 as i32,
+                          rusttype::Scale::uniform(object.text_size as f32), &font, object.text.as_str());
+        }
+        Annotation::Step => {
+            let font: Font<'static> = Font::try_from_bytes(FONT_DATA).unwrap();
+            let (radius, origin) = step_badge_layout(p1, object.text_size, &object.text);
+            let text_color = contrast_color(object.color);
+            // a long number can be wider than the badge
+            let margin = radius.max(object.text_size * object.text.chars().count() as f64) + 1.0;
+            blend_layer(canvas, &[p1], margin, |layer, offset| {
+                draw_filled_circle_mut(layer, ((p1.0 - offset.0) as i32, (p1.1 - offset.1) as i32), radius as i32, color);
+                draw_text_mut(layer, Rgba([text_color.0, text_color.1, text_color.2, text_color.3]),
+                              (origin.0 - offset.0) as i32, (origin.1 - offset.1) as i32,
+                              rusttype::Scale::uniform(object.text_size as f32), &font, object.text.as_str());
+            });
+        }
+        Annotation::Pixelate | Annotation::Blur | Annotation::Redact => {
+            if let Some(rect) = canvas_rect(canvas, (min_x, min_y), (max_x, max_y)) {
+                let strength = object.width.clamp(MIN_REDACT_STRENGTH, MAX_REDACT_STRENGTH);
+                match object.kind {
+                    Annotation::Pixelate => pixelate(canvas, rect, strength.round() as u32),
+                    Annotation::Blur => blur(canvas, rect, strength),
+                    // always opaque, a transparent color would let the content show through
+                    _ => {
+                        let (x, y, width, height) = rect;
+                        let solid = Rgba([object.color.0, object.color.1, object.color.2, 255]);
+                        for py in y..y + height {
+                            for px in x..x + width {
+                                canvas.put_pixel(px, py, solid);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(document: &Document) -> Vec<u32> {
+        document.annotations().iter().map(|a| a.id).collect()
+    }
+
+    #[test]
+    fn hit_test_finds_the_topmost() {
+        let mut document = Document::default();
+        let big = document.add(AnnotationObject::new(Annotation::Rectangle, vec![(0.0, 0.0), (100.0, 100.0)], (0, 0, 0, 255)));
+        let small = document.add(AnnotationObject::new(Annotation::Circle, vec![(40.0, 40.0), (60.0, 60.0)], (0, 0, 0, 255)));
+        assert_eq!(document.hit_test((50.0, 50.0), 0.0), Some(small));
+        assert_eq!(document.hit_test((10.0, 10.0), 0.0), Some(big));
+        // the tolerance grows the bounds
+        assert_eq!(document.hit_test((103.0, 50.0), 0.0), None);
+        assert_eq!(document.hit_test((103.0, 50.0), 4.0), Some(big));
+    }
+
+    #[test]
+    fn move_and_restyle() {
+        let mut document = Document::default();
+        let line = document.add(AnnotationObject::new(Annotation::Line, vec![(0.0, 0.0), (10.0, 20.0)], (0, 0, 0, 255)));
+        let other = document.add(AnnotationObject::new(Annotation::Line, vec![(0.0, 0.0), (10.0, 20.0)], (0, 0, 0, 255)));
+        document.move_by(line, 5.0, -2.5);
+        document.restyle(line, (255, 0, 0, 128), 6.0);
+        let moved = document.get(line).unwrap();
+        assert_eq!(moved.points, vec![(5.0, -2.5), (15.0, 17.5)]);
+        assert_eq!((moved.color, moved.width), ((255, 0, 0, 128), 6.0));
+        let untouched = document.get(other).unwrap();
+        assert_eq!((untouched.points[0], untouched.color, untouched.width), ((0.0, 0.0), (0, 0, 0, 255), 1.0));
+    }
+
+    #[test]
+    fn reorder() {
+        let mut document = Document::default();
+        let objects: Vec<u32> = (0..3).map(|_| document.add(AnnotationObject::new(Annotation::Line, vec![(0.0, 0.0)], (0, 0, 0, 255)))).collect();
+        document.bring_to_front(objects[0]);
+        assert_eq!(ids(&document), [objects[1], objects[2], objects[0]]);
+        document.send_to_back(objects[0]);
+        assert_eq!(ids(&document), objects);
+        // the bottom one is already at z 0, the target starts above it
+        assert_eq!(document.annotations()[0].z, 0);
+        document.send_to_back(objects[2]);
+        assert_eq!(ids(&document), [objects[2], objects[0], objects[1]]);
+        let z: Vec<u32> = document.annotations().iter().map(|a| a.z).collect();
+        assert!(z.windows(2).all(|pair| pair[0] < pair[1]));
+        // a new annotation goes on top
+        let new = document.add(AnnotationObject::new(Annotation::Line, vec![(0.0, 0.0)], (0, 0, 0, 255)));
+        assert_eq!(ids(&document).last(), Some(&new));
+    }
+
+    // every pixel differs from its neighbours, as the details a redaction must hide
+    fn noise(width: u32, height: u32) -> DynamicImage {
+        let mut seed: u32 = 12345;
+        DynamicImage::from(RgbaImage::from_fn(width, height, |_, _| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            let [r, g, b, _] = seed.to_be_bytes();
+            Rgba([r, g, b, 255])
+        }))
+    }
+
+    // no pixel inside the rect keeps its value, the ones outside are untouched
+    fn assert_redacted(original: &DynamicImage, redacted: &DynamicImage, (x, y, width, height): (u32,u32,u32,u32)) {
+        let (original, redacted) = (original.to_rgba8(), redacted.to_rgba8());
+        for (px, py, pixel) in original.enumerate_pixels() {
+            let inside = (x..x + width).contains(&px) && (y..y + height).contains(&py);
+            assert_eq!(redacted.get_pixel(px, py) == pixel, !inside, "pixel {},{}", px, py);
+        }
+    }
+
+    #[test]
+    fn redactions_replace_every_pixel() {
+        let original = noise(64, 48);
+        let rect = (10, 6, 37, 29);
+        for (kind, strength) in [(Annotation::Pixelate, MIN_REDACT_STRENGTH), (Annotation::Pixelate, 12.0),
+                                 (Annotation::Blur, MIN_REDACT_STRENGTH), (Annotation::Blur, 10.0), (Annotation::Redact, 1.0)] {
+            let mut redaction = AnnotationObject::new(kind, vec![(10.0, 6.0), (47.0, 35.0)], (1, 2, 3, 255));
+            redaction.width = strength;
+            let mut document = Document::default();
+            document.add(redaction);
+            assert_redacted(&original, &document.flatten(&original), rect);
+        }
+    }
+
+    #[test]
+    fn redaction_under_the_crop() {
+        let original = noise(64, 48);
+        let mut redaction = AnnotationObject::new(Annotation::Blur, vec![(0.0, 0.0), (64.0, 48.0)], (1, 2, 3, 255));
+        redaction.width = 4.0;
+        let mut document = Document::default();
+        document.add(redaction);
+        document.crop_to(8, 8, 20, 20);
+        let cropped = original.crop_imm(8, 8, 20, 20);
+        assert_redacted(&cropped, &document.flatten(&original), (0, 0, 20, 20));
+    }
+
+    fn step_numbers(document: &Document) -> Vec<(u32, String)> {
+        document.annotations().iter().filter(|a| a.kind == Annotation::Step).map(|a| (a.id, a.text.clone())).collect()
+    }
+
+    #[test]
+    fn steps_are_renumbered_after_a_removal_in_the_middle() {
+        let mut document = Document::default();
+        let ids: Vec<u32> = (0..4)
+            .map(|index| document.add(AnnotationObject::new(Annotation::Step, vec![(index as f64 * 50.0, 10.0)], (255, 0, 0, 255))))
+            .collect();
+        assert_eq!(step_numbers(&document).iter().map(|(_, text)| text.as_str()).collect::<Vec<_>>(), ["1", "2", "3", "4"]);
+        document.remove(ids[1]);
+        assert_eq!(step_numbers(&document), vec![(ids[0], "1".to_string()), (ids[2], "2".to_string()), (ids[3], "3".to_string())]);
+        document.remove_last();
+        assert_eq!(step_numbers(&document), vec![(ids[0], "1".to_string()), (ids[2], "2".to_string())]);
+    }
+
+    #[test]
+    fn steps_mixed_with_other_annotations() {
+        let mut document = Document::default();
+        let first = document.add(AnnotationObject::new(Annotation::Step, vec![(10.0, 10.0)], (255, 0, 0, 255)));
+        let line = document.add(AnnotationObject::new(Annotation::Line, vec![(0.0, 0.0), (100.0, 100.0)], (0, 0, 0, 255)));
+        let second = document.add(AnnotationObject::new(Annotation::Step, vec![(60.0, 60.0)], (255, 0, 0, 255)));
+        let rectangle = document.add(AnnotationObject::new(Annotation::Rectangle, vec![(50.0, 50.0), (80.0, 80.0)], (0, 0, 0, 255)));
+        let third = document.add(AnnotationObject::new(Annotation::Step, vec![(90.0, 10.0)], (255, 0, 0, 255)));
+        // the order of placement, not the drawing order
+        document.send_to_back(third);
+        assert_eq!(step_numbers(&document), vec![(third, "3".to_string()), (first, "1".to_string()), (second, "2".to_string())]);
+        // the other annotations do not change the numbers
+        document.remove(line);
+        document.remove(first);
+        assert_eq!(step_numbers(&document), vec![(third, "2".to_string()), (second, "1".to_string())]);
+        assert!(document.get(rectangle).is_some_and(|a| a.text.is_empty()));
+    }
+
+    #[test]
+    fn step_under_another_annotation() {
+        let mut document = Document::default();
+        let badge = document.add(AnnotationObject::new(Annotation::Step, vec![(60.0, 60.0)], (255, 0, 0, 255)));
+        let rectangle = document.add(AnnotationObject::new(Annotation::Rectangle, vec![(50.0, 50.0), (80.0, 80.0)], (0, 0, 0, 255)));
+        assert_eq!(document.hit_test((60.0, 60.0), 0.0), Some(rectangle));
+        assert_eq!(document.step_at((60.0, 60.0), 0.0), Some(badge));
+        assert_eq!(document.step_at((200.0, 200.0), 0.0), None);
+    }
+
+    #[test]
+    fn step_badge_is_blended() {
+        let mut canvas = RgbaImage::from_pixel(100, 100, Rgba([255, 0, 0, 255]));
+        let mut badge = AnnotationObject::new(Annotation::Step, vec![(50.0, 50.0)], (0, 0, 255, 128));
+        badge.text = "12".to_string();
+        badge.text_size = 40.0;
+        draw_annotation(&mut canvas, &badge);
+        // inside the circle, left of the number: half red and half blue, as in the preview
+        let [r, g, b, a] = canvas.get_pixel(22, 50).0;
+        assert!((120..=135).contains(&r) && g == 0 && (120..=135).contains(&b) && a > 250, "{:?}", (r, g, b, a));
+        assert_eq!(canvas.get_pixel(2, 2), &Rgba([255, 0, 0, 255]));
+    }
+
+    fn rounded(pieces: Vec<Vec<(f64,f64)>>) -> Vec<Vec<(f64,f64)>> {
+        pieces.into_iter()
+            .map(|piece| piece.into_iter().map(|(x, y)| ((x * 1000.0).round() / 1000.0, (y * 1000.0).round() / 1000.0)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn dashes_are_three_widths_long() {
+        let line = [(0.0, 0.0), (30.0, 0.0)];
+        assert_eq!(rounded(dash_polyline(&line, LineStyle::Dashed, 2.0)),
+                   vec![vec![(0.0, 0.0), (6.0, 0.0)], vec![(12.0, 0.0), (18.0, 0.0)], vec![(24.0, 0.0), (30.0, 0.0)]]);
+        // thinner than a pixel, the lengths of a 1 pixel line
+        assert_eq!(rounded(dash_polyline(&[(0.0, 0.0), (0.0, 8.0)], LineStyle::Dashed, 0.5)),
+                   vec![vec![(0.0, 0.0), (0.0, 3.0)], vec![(0.0, 6.0), (0.0, 8.0)]]);
+        assert_eq!(dash_polyline(&line, LineStyle::Solid, 2.0), vec![line.to_vec()]);
+    }
+
+    #[test]
+    fn dashes_continue_around_the_corners() {
+        let corner = [(0.0, 0.0), (4.0, 0.0), (4.0, 10.0)];
+        assert_eq!(rounded(dash_polyline(&corner, LineStyle::Dashed, 1.0)),
+                   vec![vec![(0.0, 0.0), (3.0, 0.0)], vec![(4.0, 2.0), (4.0, 5.0)], vec![(4.0, 8.0), (4.0, 10.0)]]);
+    }
+
+    #[test]
+    fn ellipse_outline() {
+        // dragged from the bottom right corner
+        let ellipse = AnnotationObject::new(Annotation::Ellipse, vec![(110.0, 70.0), (10.0, 30.0)], (0, 0, 0, 255));
+        let outline = rounded(shape_outline(&ellipse)).remove(0);
+        assert_eq!(outline.first(), outline.last());
+        // on the ellipse inscribed in the drag box, touching its sides
+        for (x, y) in outline.iter() {
+            assert!((((x - 60.0) / 50.0).powi(2) + ((y - 50.0) / 20.0).powi(2) - 1.0).abs() < 1e-4, "{},{}", x, y);
+        }
+        let (min_x, max_x) = outline.iter().fold((f64::MAX, f64::MIN), |(min, max), p| (min.min(p.0), max.max(p.0)));
+        let (min_y, max_y) = outline.iter().fold((f64::MAX, f64::MIN), |(min, max), p| (min.min(p.1), max.max(p.1)));
+        for (bound, side) in [(min_x, 10.0), (min_y, 30.0), (max_x, 110.0), (max_y, 70.0)] {
+            assert!((bound - side).abs() < 0.1, "{} {}", bound, side);
+        }
+    }
+
+    #[test]
+    fn rounded_rectangle_outline() {
+        let mut rectangle = AnnotationObject::new(Annotation::RoundedRectangle, vec![(0.0, 0.0), (100.0, 40.0)], (0, 0, 0, 255));
+        rectangle.corner_radius = 10.0;
+        let outline = shape_outline(&rectangle).remove(0);
+        assert_eq!(outline.first(), outline.last());
+        assert!(outline.iter().all(|(x, y)| (0.0..=100.0).contains(x) && (0.0..=40.0).contains(y)));
+        // the straight sides start after the corners, the corner of the box is cut
+        let on_top: Vec<f64> = outline.iter().filter(|p| p.1 == 0.0).map(|p| (p.0 * 1000.0).round() / 1000.0).collect();
+        assert_eq!(on_top, vec![10.0, 90.0]);
+        assert!(outline.iter().all(|(x, y)| x.hypot(*y) >= 10.0 * std::f64::consts::SQRT_2 - 10.0 - 1e-9));
+
+        // larger than half the shorter side, the ends are half circles
+        rectangle.corner_radius = 50.0;
+        let outline = shape_outline(&rectangle).remove(0);
+        assert!(outline.iter().all(|(x, y)| (0.0..=100.0).contains(x) && (0.0..=40.0).contains(y)));
+        let on_top: Vec<f64> = outline.iter().filter(|p| p.1 == 0.0).map(|p| (p.0 * 1000.0).round() / 1000.0).collect();
+        assert_eq!(on_top, vec![20.0, 80.0]);
+
+        // without a radius, the rectangle
+        rectangle.corner_radius = 0.0;
+        let plain = AnnotationObject::new(Annotation::Rectangle, vec![(0.0, 0.0), (100.0, 40.0)], (0, 0, 0, 255));
+        assert_eq!(shape_outline(&rectangle), shape_outline(&plain));
+    }
+
+    #[test]
+    fn dots_are_two_widths_apart() {
+        let dots = rounded(dash_polyline(&[(0.0, 0.0), (21.0, 0.0)], LineStyle::Dotted, 2.5));
+        assert_eq!(dots, vec![vec![(0.0, 0.0)], vec![(5.0, 0.0)], vec![(10.0, 0.0)], vec![(15.0, 0.0)], vec![(20.0, 0.0)]]);
+    }
+}
+
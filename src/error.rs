@@ -0,0 +1,140 @@
+// ERRORS
+// the fallible operations return an Error describing what was being done and why it failed,
+// shown in the error window (or printed in command line mode) instead of closing the application
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    Capture(String),
+    Io(std::io::Error),
+    Image(image::ImageError),
+    Clipboard(arboard::Error),
+    Dialog(native_dialog::Error),
+    // errors of the export, project and settings functions
+    Message(String),
+    // what was being done when the error happened
+    Context(String, Box<Error>),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Capture(message) => write!(f, "screen capture failed: {}", message),
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Image(error) => write!(f, "{}", error),
+            Error::Clipboard(error) => write!(f, "clipboard not available: {}", error),
+            Error::Dialog(error) => write!(f, "file dialog not available: {}", error),
+            Error::Message(message) => write!(f, "{}", message),
+            Error::Context(context, source) => write!(f, "{}: {}", context, source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            Error::Image(error) => Some(error),
+            Error::Clipboard(error) => Some(error),
+            Error::Dialog(error) => Some(error),
+            Error::Context(_, source) => Some(source.as_ref()),
+            Error::Capture(_) | Error::Message(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(error: image::ImageError) -> Self {
+        Error::Image(error)
+    }
+}
+
+impl From<arboard::Error> for Error {
+    fn from(error: arboard::Error) -> Self {
+        Error::Clipboard(error)
+    }
+}
+
+impl From<native_dialog::Error> for Error {
+    fn from(error: native_dialog::Error) -> Self {
+        Error::Dialog(error)
+    }
+}
+
+// the animation encoders have their own errors, kept as messages
+impl From<gif::EncodingError> for Error {
+    fn from(error: gif::EncodingError) -> Self {
+        Error::Message(error.to_string())
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(error: png::EncodingError) -> Self {
+        Error::Message(error.to_string())
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Message(message)
+    }
+}
+
+// add to an error what was being done, e.g. `fs::write(..).context("Cannot save the capture")`
+pub trait Context<T> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> Context<T> for std::result::Result<T, E> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T> {
+        self.map_err(|error| Error::Context(context.into(), Box::new(error.into())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+    use std::io;
+
+    #[test]
+    fn display() {
+        assert_eq!(Error::Capture("no monitor".to_string()).to_string(), "screen capture failed: no monitor");
+        assert_eq!(Error::Message("invalid page".to_string()).to_string(), "invalid page");
+        let error: Error = io::Error::new(io::ErrorKind::NotFound, "missing file").into();
+        assert_eq!(error.to_string(), "missing file");
+        assert!(matches!(error, Error::Io(_)));
+    }
+
+    #[test]
+    fn context_is_chained() {
+        let result: std::result::Result<(), io::Error> = Err(io::Error::new(io::ErrorKind::PermissionDenied, "denied"));
+        let error = result.context("Cannot write capture.png").context("Cannot save").unwrap_err();
+        assert_eq!(error.to_string(), "Cannot save: Cannot write capture.png: denied");
+
+        // each context is the source of the one added after it, down to the io error
+        let inner = error.source().unwrap();
+        assert_eq!(inner.to_string(), "Cannot write capture.png: denied");
+        let io_error = inner.source().unwrap();
+        assert!(matches!(io_error.downcast_ref::<Error>(), Some(Error::Io(_))));
+        let source = io_error.source().unwrap();
+        assert_eq!(source.downcast_ref::<io::Error>().unwrap().kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn context_of_a_message() {
+        let result: std::result::Result<(), String> = Err("the image is empty".to_string());
+        let error = result.context(format!("Cannot write {}", "out.pdf")).unwrap_err();
+        assert_eq!(error.to_string(), "Cannot write out.pdf: the image is empty");
+        assert!(error.source().unwrap().source().is_none());
+    }
+}
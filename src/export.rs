@@ -0,0 +1,251 @@
+// EXPORT OF ANNOTATED SCREENSHOTS IN VECTOR AND DOCUMENT FORMATS
+
+use std::fmt::Write;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use image::DynamicImage;
+use printpdf::{Image, ImageTransform, Mm, PdfDocument};
+use rusttype::{Font, Scale};
+use crate::Annotation;
+use crate::constants::{APP_NAME, FONT_DATA, TRANSPARENCY};
+use crate::document::{AnnotationObject, contrast_color, Document, highlighter_polygon, shape_geometry, step_badge_layout};
+use crate::utilities::image_to_buffer;
+
+fn svg_paint(color: (u8,u8,u8,u8)) -> String {
+    format!("rgb({},{},{})", color.0, color.1, color.2)
+}
+
+fn svg_opacity(alpha: u8) -> String {
+    format!("{:.3}", alpha as f64 / 255.0)
+}
+
+fn svg_points(points: &[(f64,f64)]) -> String {
+    points.iter().map(|p| format!("{:.2},{:.2}", p.0, p.1)).collect::<Vec<_>>().join(" ")
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// the geometry of the rasterised shape: fill polygon, dashes with round joins and caps, dots as circles
+fn svg_shape(svg: &mut String, object: &AnnotationObject) {
+    let geometry = shape_geometry(object);
+    if let (Some(polygon), Some(fill)) = (&geometry.fill, object.fill) {
+        let _ = writeln!(svg, r#"    <polygon points="{}" fill="{}" fill-opacity="{}"/>"#, svg_points(polygon), svg_paint(fill), svg_opacity(fill.3));
+    }
+    let _ = writeln!(svg, r#"    <g fill="none" stroke="{}" stroke-opacity="{}" stroke-width="{:.2}" stroke-linecap="round" stroke-linejoin="round">"#,
+                     svg_paint(object.color), svg_opacity(object.color.3), geometry.width);
+    for piece in geometry.pieces.iter() {
+        if piece.len() == 1 {
+            let _ = writeln!(svg, r#"      <circle cx="{:.2}" cy="{:.2}" r="{:.2}" fill="{}" stroke="none"/>"#,
+                             piece[0].0, piece[0].1, geometry.width / 2.0, svg_paint(object.color));
+        } else {
+            let _ = writeln!(svg, r#"      <polyline points="{}"/>"#, svg_points(piece));
+        }
+    }
+    let _ = writeln!(svg, "    </g>");
+}
+
+// vector elements of one annotation, with the same geometry used to rasterise it
+fn svg_annotation(svg: &mut String, object: &AnnotationObject) {
+    if object.points.is_empty() {
+        return;
+    }
+    let stroke = format!(r#"fill="none" stroke="{}" stroke-opacity="{}" stroke-width="1""#, svg_paint(object.color), svg_opacity(object.color.3));
+    let (p0, p1) = (object.first_point(), object.last_point());
+
+    match object.kind {
+        Annotation::None | Annotation::Select => {}
+        Annotation::Circle | Annotation::Rectangle | Annotation::Line | Annotation::Cross | Annotation::Arrow
+        | Annotation::Ellipse | Annotation::RoundedRectangle => svg_shape(svg, object),
+        Annotation::FreeLine => {
+            let _ = writeln!(svg, r#"    <polyline points="{}" stroke-linejoin="round" {}/>"#, svg_points(&object.points), stroke);
+        }
+        Annotation::Highlighter => {
+            let _ = writeln!(svg, r#"    <polygon points="{}" fill="{}" fill-opacity="{}"/>"#,
+                             svg_points(&highlighter_polygon(p0, p1, object.width)), svg_paint(object.color), svg_opacity(TRANSPARENCY));
+        }
+        Annotation::Text => {
+            // the raster text is placed by its top, the svg one by the baseline
+            let font: Font<'static> = Font::try_from_bytes(FONT_DATA).unwrap();
+            let ascent = font.v_metrics(Scale::uniform(object.text_size as f32)).ascent as f64;
+            let _ = writeln!(svg, r#"    <text x="{:.2}" y="{:.2}" font-family="Open Sans, sans-serif" font-weight="600" font-size="{:.2}" fill="{}" fill-opacity="{}" xml:space="preserve">{}</text>"#,
+                             p1.0, p1.1 + ascent, object.text_size, svg_paint(object.color), svg_opacity(object.color.3), escape_xml(&object.text));
+        }
+        Annotation::Step => {
+            let (radius, origin) = step_badge_layout(p1, object.text_size, &object.text);
+            let font: Font<'static> = Font::try_from_bytes(FONT_DATA).unwrap();
+            let ascent = font.v_metrics(Scale::uniform(object.text_size as f32)).ascent as f64;
+            let _ = writeln!(svg, r#"    <circle cx="{:.2}" cy="{:.2}" r="{:.2}" fill="{}" fill-opacity="{}"/>"#,
+                             p1.0, p1.1, radius, svg_paint(object.color), svg_opacity(object.color.3));
+            let _ = writeln!(svg, r#"    <text x="{:.2}" y="{:.2}" font-family="Open Sans, sans-serif" font-weight="600" font-size="{:.2}" fill="{}">{}</text>"#,
+                             origin.0, origin.1 + ascent, object.text_size, svg_paint(contrast_color(object.color)), escape_xml(&object.text));
+        }
+        // rasterised in the embedded image
+        Annotation::Pixelate | Annotation::Blur | Annotation::Redact => {}
+    }
+}
+
+// svg with the cropped capture embedded as png and the annotations as vector elements. The redactions, and
+// the annotations under them, are rasterised in the embedded png, so the original pixels are not in the file
+pub fn document_to_svg(original: &DynamicImage, document: &Document) -> Result<String, String> {
+    let (rasterised, vectors) = document.split_at_redaction();
+    let background = document.flatten_objects(original, rasterised);
+    let (width, height) = (background.width(), background.height());
+    let (origin_x, origin_y) = document.origin();
+
+    let mut svg = String::new();
+    let _ = writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{}" height="{}" viewBox="0 0 {} {}">"#, width, height, width, height);
+    let _ = writeln!(svg, r#"  <image x="0" y="0" width="{}" height="{}" xlink:href="data:image/png;base64,{}"/>"#,
+                     width, height, STANDARD.encode(image_to_buffer(background).map_err(|e| e.to_string())?));
+    // annotations are in coordinates of the original capture
+    let _ = writeln!(svg, r#"  <g transform="translate({:.2},{:.2})">"#, -origin_x, -origin_y);
+    for object in vectors {
+        svg_annotation(&mut svg, object);
+    }
+    let _ = writeln!(svg, "  </g>");
+    let _ = writeln!(svg, "</svg>");
+    Ok(svg)
+}
+
+// pdf with one page for each image, every page has the size of its image at the given dpi
+pub fn images_to_pdf(pages: &[(DynamicImage, f32)]) -> Result<Vec<u8>, String> {
+    let page_size = |image: &DynamicImage, dpi: f32| (Mm(image.width() as f32 / dpi * 25.4), Mm(image.height() as f32 / dpi * 25.4));
+    let (first_image, first_dpi) = pages.first().ok_or("No image to export".to_string())?;
+
+    let (width, height) = page_size(first_image, *first_dpi);
+    let (document, first_page, first_layer) = PdfDocument::new(APP_NAME, width, height, "Capture");
+    let mut layers = vec![(first_page, first_layer)];
+    for (image, dpi) in pages.iter().skip(1) {
+        let (width, height) = page_size(image, *dpi);
+        layers.push(document.add_page(width, height, "Capture"));
+    }
+
+    for ((image, dpi), (page, layer)) in pages.iter().zip(layers) {
+        // the captures are opaque, without alpha the pdf is smaller
+        let rgb_image = DynamicImage::from(image.to_rgb8());
+        Image::from_dynamic_image(&rgb_image).add_to_layer(
+            document.get_page(page).get_layer(layer),
+            ImageTransform { dpi: Some(*dpi), ..Default::default() });
+    }
+
+    document.save_to_bytes().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+    use crate::document::arrow_segments;
+
+    // the png embedded in the svg
+    fn embedded_image(svg: &str) -> DynamicImage {
+        let start = svg.find("base64,").unwrap() + "base64,".len();
+        let end = start + svg[start..].find('"').unwrap();
+        image::load_from_memory(&STANDARD.decode(&svg[start..end]).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn svg_vector_elements() {
+        let mut document = Document::default();
+        let mut line = AnnotationObject::new(Annotation::Line, vec![(10.0, 10.0), (50.0, 30.0)], (255, 0, 0, 255));
+        line.width = 3.0;
+        document.add(line);
+        // dragged from the bottom right corner
+        let mut rectangle = AnnotationObject::new(Annotation::Rectangle, vec![(60.0, 50.0), (20.0, 40.0)], (0, 0, 255, 128));
+        rectangle.fill = Some((0, 255, 0, 255));
+        document.add(rectangle);
+        document.add(AnnotationObject::new(Annotation::Arrow, vec![(0.0, 70.0), (40.0, 70.0)], (0, 0, 0, 255)));
+        let mut text = AnnotationObject::new(Annotation::Text, vec![(5.0, 60.0)], (0, 0, 0, 255));
+        text.text = "a < b".to_string();
+        text.text_size = 20.0;
+        document.add(text);
+
+        let svg = document_to_svg(&DynamicImage::new_rgba8(100, 80), &document).unwrap();
+        assert!(svg.contains(r#"width="100" height="80" viewBox="0 0 100 80""#));
+        assert!(svg.contains(r#"<g transform="translate(-0.00,-0.00)">"#));
+        assert!(svg.contains(r#"stroke="rgb(255,0,0)" stroke-opacity="1.000" stroke-width="3.00""#));
+        assert!(svg.contains(r#"<polyline points="10.00,10.00 50.00,30.00"/>"#));
+        assert!(svg.contains(r#"stroke="rgb(0,0,255)" stroke-opacity="0.502" stroke-width="1.00""#));
+        assert!(svg.contains(r#"<polygon points="20.00,40.00 60.00,40.00 60.00,50.00 20.00,50.00" fill="rgb(0,255,0)" fill-opacity="1.000"/>"#));
+        assert!(svg.contains(r#"<polyline points="20.00,40.00 60.00,40.00 60.00,50.00 20.00,50.00 20.00,40.00"/>"#));
+        // the shaft and the two sides of the head
+        for (start, end) in arrow_segments((0.0, 70.0), (40.0, 70.0)) {
+            assert!(svg.contains(&format!(r#"<polyline points="{}"/>"#, svg_points(&[start, end]))));
+        }
+        assert_eq!(svg.matches("<polyline").count(), 5);
+        assert!(svg.contains(r#"<text x="5.00""#));
+        assert!(svg.contains(r#"font-size="20.00" fill="rgb(0,0,0)""#));
+        assert!(svg.contains(">a &lt; b</text>"));
+    }
+
+    #[test]
+    fn svg_crop_offsets_the_annotations() {
+        let mut document = Document::default();
+        document.add(AnnotationObject::new(Annotation::Line, vec![(15.0, 10.0), (45.0, 30.0)], (255, 0, 0, 255)));
+        document.crop_to(10, 5, 40, 30);
+        let svg = document_to_svg(&DynamicImage::new_rgba8(100, 80), &document).unwrap();
+        assert!(svg.contains(r#"width="40" height="30" viewBox="0 0 40 30""#));
+        // the annotations keep the coordinates of the capture, the group moves them by the crop
+        assert!(svg.contains(r#"<g transform="translate(-10.00,-5.00)">"#));
+        assert!(svg.contains(r#"<polyline points="15.00,10.00 45.00,30.00"/>"#));
+        assert_eq!(embedded_image(&svg).width(), 40);
+    }
+
+    // the media boxes of the pages, in points
+    fn page_sizes(pdf: &[u8]) -> Vec<(f64,f64)> {
+        let text = String::from_utf8_lossy(pdf);
+        text.match_indices("/MediaBox[").map(|(index, _)| {
+            let start = index + "/MediaBox[".len();
+            let values: Vec<f64> = text[start..start + text[start..].find(']').unwrap()]
+                .split_whitespace().map(|value| value.parse().unwrap()).collect();
+            (values[2], values[3])
+        }).collect()
+    }
+
+    #[test]
+    fn pdf_pages() {
+        let pages = [(DynamicImage::new_rgb8(720, 360), 72.0), (DynamicImage::new_rgba8(300, 600), 150.0), (DynamicImage::new_rgb8(96, 96), 96.0)];
+        let pdf = images_to_pdf(&pages).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+        // one page for each image, its size in points (1/72 inch) from the pixels and the dpi
+        let sizes = page_sizes(&pdf);
+        assert_eq!(sizes.len(), 3);
+        for ((width, height), expected) in sizes.iter().zip([(720.0, 360.0), (144.0, 288.0), (72.0, 72.0)]) {
+            assert!((width - expected.0).abs() < 0.1 && (height - expected.1).abs() < 0.1, "{}x{}", width, height);
+        }
+    }
+
+    #[test]
+    fn pdf_without_images() {
+        assert!(images_to_pdf(&[]).is_err());
+    }
+
+    #[test]
+    fn svg_image_is_redacted() {
+        // every pixel differs from its neighbours, a blurred or pixelated one cannot keep its value
+        let mut seed: u32 = 12345;
+        let original = DynamicImage::from(RgbaImage::from_fn(64, 48, |_, _| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            let [r, g, b, _] = seed.to_be_bytes();
+            Rgba([r, g, b, 255])
+        }));
+        for kind in [Annotation::Pixelate, Annotation::Blur, Annotation::Redact] {
+            let mut redaction = AnnotationObject::new(kind.clone(), vec![(5.0, 5.0), (35.0, 25.0)], (1, 2, 3, 255));
+            redaction.width = 6.0;
+            let mut document = Document::default();
+            document.add(redaction);
+            // kept as a vector over the image
+            document.add(AnnotationObject::new(Annotation::Line, vec![(0.0, 0.0), (60.0, 40.0)], (255, 0, 0, 255)));
+            let svg = document_to_svg(&original, &document).unwrap();
+            assert!(svg.contains("rgb(255,0,0)"));
+            let embedded = embedded_image(&svg).to_rgba8();
+            for (x, y, pixel) in original.to_rgba8().enumerate_pixels() {
+                let inside = (5..35).contains(&x) && (5..25).contains(&y);
+                assert_eq!(embedded.get_pixel(x, y) == pixel, !inside, "{:?} pixel {},{}", kind, x, y);
+            }
+        }
+    }
+}
+
@@ -0,0 +1,445 @@
+// GLOBAL HOTKEY
+// the hotkeys of the capture actions are grabbed for the whole desktop, so they start a capture also
+// when another application has the focus: on Linux with X11 key grabs (it works under Xvfb too), listened
+// in a separate thread that notifies the application with the GLOBAL_HOTKEY command. When a grab is not
+// possible (other platforms, no X server, hotkey taken by another application) the hotkey works only
+// inside the application windows, as handled by the Enter controller
+
+use std::collections::BTreeMap;
+use std::sync::mpsc::Sender;
+use std::sync::{Mutex, OnceLock};
+use druid::{ExtEventSink, Selector};
+use crate::hotkey::{Action, Hotkey};
+
+// sent by the listener thread when the hotkey of an action is pressed
+pub const GLOBAL_HOTKEY: Selector<Action> = Selector::new("pds_project.global-hotkey");
+
+// actions whose hotkey is grabbed
+static ACTIVE: Mutex<Vec<Action>> = Mutex::new(Vec::new());
+static UPDATES: OnceLock<Mutex<Sender<Bindings>>> = OnceLock::new();
+
+// hotkeys of the global actions, sent to the listener thread
+type Bindings = Vec<(Action, Hotkey)>;
+
+// true if the hotkey of the action is grabbed for the whole desktop
+pub fn is_active(action: Action) -> bool {
+    ACTIVE.lock().is_ok_and(|active| active.contains(&action))
+}
+
+// prepare xlib for the listener thread, before the windows are created
+pub fn init() {
+    platform::init();
+}
+
+fn global_bindings(hotkeys: &BTreeMap<Action, Hotkey>) -> Bindings {
+    hotkeys.iter()
+        .filter(|(action, _)| action.is_global())
+        .map(|(action, hotkey)| (*action, hotkey.clone()))
+        .collect()
+}
+
+// start listening for the hotkeys, on error the in-window hotkeys are still available
+pub fn start(sink: ExtEventSink, hotkeys: &BTreeMap<Action, Hotkey>) -> Result<(), String> {
+    let sender = platform::start(sink, global_bindings(hotkeys))?;
+    let _ = UPDATES.set(Mutex::new(sender));
+    Ok(())
+}
+
+// grab the new hotkeys after they have been changed in the settings
+pub fn update(hotkeys: &BTreeMap<Action, Hotkey>) {
+    if let Some(updates) = UPDATES.get() {
+        if let Ok(sender) = updates.lock() {
+            let _ = sender.send(global_bindings(hotkeys));
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::ffi::CString;
+    use std::os::raw::{c_int, c_uchar, c_uint};
+    use std::ptr;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+    use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+    use std::thread;
+    use std::time::Duration;
+    use druid::{ExtEventSink, Target};
+    use x11_dl::xlib;
+    use crate::hotkey::{Action, Hotkey};
+    use super::{Bindings, ACTIVE, GLOBAL_HOTKEY};
+
+    type ErrorHandler = Option<unsafe extern "C" fn(*mut xlib::Display, *mut xlib::XErrorEvent) -> c_int>;
+
+    // the grab fails asynchronously (BadAccess if another client has the same grab)
+    static GRAB_FAILED: AtomicBool = AtomicBool::new(false);
+    // connection of the listener, and the error handler replaced while grabbing: the errors of the
+    // other connections (e.g. the one of the windows) and of the other requests are passed to it
+    static GRAB_DISPLAY: AtomicPtr<xlib::Display> = AtomicPtr::new(ptr::null_mut());
+    static PREVIOUS_HANDLER: Mutex<ErrorHandler> = Mutex::new(None);
+    const X_GRAB_KEY: c_uchar = 33;
+
+    unsafe extern "C" fn on_x_error(display: *mut xlib::Display, event: *mut xlib::XErrorEvent) -> c_int {
+        if display == GRAB_DISPLAY.load(Ordering::SeqCst) && (*event).request_code == X_GRAB_KEY {
+            GRAB_FAILED.store(true, Ordering::SeqCst);
+            return 0;
+        }
+        let previous = PREVIOUS_HANDLER.lock().map(|handler| *handler).unwrap_or(None);
+        match previous {
+            Some(previous) => previous(display, event),
+            None => 0,
+        }
+    }
+
+    // xlib is used by the windows and by the listener thread, XInitThreads must come before any other call.
+    // The library is kept loaded, so the setting is not lost
+    pub fn init() {
+        if let Ok(xlib) = xlib::Xlib::open() {
+            unsafe {
+                (xlib.XInitThreads)();
+            }
+            Box::leak(Box::new(xlib));
+        }
+    }
+
+    // the normalized names of the hotkeys differ from the X11 keysym names for some keys
+    fn keysym_name(key: &str) -> String {
+        match key {
+            "Space" => "space".to_string(),
+            "Enter" => "Return".to_string(),
+            "PageUp" => "Prior".to_string(),
+            "PageDown" => "Next".to_string(),
+            "Backspace" => "BackSpace".to_string(),
+            single if single.chars().count() == 1 => single.to_lowercase(),
+            other => other.to_string(),
+        }
+    }
+
+    // modifiers that take part in the match, Caps Lock and Num Lock are ignored
+    const MODIFIERS_MASK: c_uint = xlib::ControlMask | xlib::ShiftMask | xlib::Mod1Mask | xlib::Mod4Mask;
+
+    fn modifier_mask(hotkey: &Hotkey) -> c_uint {
+        let mut modifiers: c_uint = 0;
+        if hotkey.ctrl { modifiers |= xlib::ControlMask; }
+        if hotkey.shift { modifiers |= xlib::ShiftMask; }
+        if hotkey.alt { modifiers |= xlib::Mod1Mask; }
+        if hotkey.super_key { modifiers |= xlib::Mod4Mask; }
+        modifiers
+    }
+
+    struct Grab {
+        action: Action,
+        keycode: c_int,
+        modifiers: c_uint,
+    }
+
+    // the action of a press of a grabbed key, once until the key is released: the auto repeat of a held
+    // key and the keys with other modifiers give None. Caps Lock and Num Lock in the state are ignored
+    fn key_pressed(grabs: &[Grab], held: &mut Vec<c_int>, keycode: c_int, state: c_uint) -> Option<Action> {
+        if held.contains(&keycode) {
+            return None;
+        }
+        let grab = grabs.iter().find(|grab| grab.keycode == keycode && grab.modifiers == state & MODIFIERS_MASK)?;
+        held.push(keycode);
+        Some(grab.action)
+    }
+
+    fn key_released(held: &mut Vec<c_int>, keycode: c_int) {
+        held.retain(|held| *held != keycode);
+    }
+
+    struct Listener {
+        xlib: xlib::Xlib,
+        display: *mut xlib::Display,
+        root: xlib::Window,
+        grabs: Vec<Grab>,
+        // keycodes of the grabbed keys held down, their auto repeat does not fire again
+        held: Vec<c_int>,
+        // the server does not send a release for each repeat
+        detectable_repeat: bool,
+    }
+
+    impl Listener {
+        fn open() -> Result<Listener, String> {
+            let xlib = xlib::Xlib::open().map_err(|e| format!("libX11 not available: {}", e))?;
+            unsafe {
+                let display = (xlib.XOpenDisplay)(ptr::null());
+                if display.is_null() {
+                    return Err("cannot connect to the X server".to_string());
+                }
+                let mut supported = 0;
+                let detectable_repeat = (xlib.XkbSetDetectableAutoRepeat)(display, xlib::True, &mut supported) != 0 && supported != 0;
+                let root = (xlib.XDefaultRootWindow)(display);
+                Ok(Listener { xlib, display, root, grabs: vec![], held: vec![], detectable_repeat })
+            }
+        }
+
+        // the grab is repeated with Caps Lock and Num Lock, which would change the modifier state
+        fn lock_variants(modifiers: c_uint) -> [c_uint; 4] {
+            [modifiers, modifiers | xlib::LockMask, modifiers | xlib::Mod2Mask, modifiers | xlib::LockMask | xlib::Mod2Mask]
+        }
+
+        fn ungrab_key(&self, grab: &Grab) {
+            unsafe {
+                for modifiers in Listener::lock_variants(grab.modifiers) {
+                    (self.xlib.XUngrabKey)(self.display, grab.keycode, modifiers, self.root);
+                }
+                (self.xlib.XSync)(self.display, xlib::False);
+            }
+        }
+
+        fn ungrab_all(&mut self) {
+            for grab in std::mem::take(&mut self.grabs) {
+                self.ungrab_key(&grab);
+            }
+            self.held.clear();
+            if let Ok(mut active) = ACTIVE.lock() {
+                active.clear();
+            }
+        }
+
+        fn grab(&mut self, action: Action, hotkey: &Hotkey) -> Result<(), String> {
+            let name = CString::new(keysym_name(&hotkey.key)).map_err(|e| e.to_string())?;
+
+            let grab = unsafe {
+                let keysym = (self.xlib.XStringToKeysym)(name.as_ptr());
+                let keycode = if keysym == 0 { 0 } else { (self.xlib.XKeysymToKeycode)(self.display, keysym) };
+                if keycode == 0 {
+                    return Err(format!("the key {} is not on the keyboard", hotkey.key));
+                }
+
+                let modifiers = modifier_mask(hotkey);
+
+                // the handler is installed only until the errors of the grab have been received
+                GRAB_FAILED.store(false, Ordering::SeqCst);
+                GRAB_DISPLAY.store(self.display, Ordering::SeqCst);
+                let previous = (self.xlib.XSetErrorHandler)(Some(on_x_error));
+                if let Ok(mut handler) = PREVIOUS_HANDLER.lock() {
+                    *handler = previous;
+                }
+                for variant in Listener::lock_variants(modifiers) {
+                    (self.xlib.XGrabKey)(self.display, keycode as c_int, variant, self.root, xlib::False,
+                                         xlib::GrabModeAsync, xlib::GrabModeAsync);
+                }
+                (self.xlib.XSync)(self.display, xlib::False);
+                (self.xlib.XSetErrorHandler)(previous);
+                Grab { action, keycode: keycode as c_int, modifiers }
+            };
+
+            if GRAB_FAILED.load(Ordering::SeqCst) {
+                self.ungrab_key(&grab);
+                return Err(format!("{} is already used by another application", hotkey));
+            }
+            self.grabs.push(grab);
+            if let Ok(mut active) = ACTIVE.lock() {
+                active.push(action);
+            }
+            Ok(())
+        }
+
+        // a failed grab does not prevent the others
+        fn grab_all(&mut self, bindings: &[(Action, Hotkey)]) {
+            self.ungrab_all();
+            for (action, hotkey) in bindings {
+                if let Err(error) = self.grab(*action, hotkey) {
+                    eprintln!("Global hotkey of \"{}\" not available, it works only in the application windows: {}", action.label(), error);
+                }
+            }
+        }
+
+        // without detectable auto repeat, a repeat is a release followed by a press with the same time
+        unsafe fn is_repeat(&self, release: &xlib::XKeyEvent) -> bool {
+            if (self.xlib.XPending)(self.display) == 0 {
+                return false;
+            }
+            let mut next: xlib::XEvent = std::mem::zeroed();
+            (self.xlib.XPeekEvent)(self.display, &mut next);
+            next.get_type() == xlib::KeyPress && next.key.keycode == release.keycode && next.key.time == release.time
+        }
+
+        // the actions whose hotkey has been pressed since the last call, once while the keys are held
+        fn pressed(&mut self) -> Vec<Action> {
+            let mut pressed = vec![];
+            unsafe {
+                while (self.xlib.XPending)(self.display) > 0 {
+                    let mut event: xlib::XEvent = std::mem::zeroed();
+                    (self.xlib.XNextEvent)(self.display, &mut event);
+                    // only the grabbed keys are delivered
+                    let key = event.key;
+                    let keycode = key.keycode as c_int;
+                    match event.get_type() {
+                        xlib::KeyPress => pressed.extend(key_pressed(&self.grabs, &mut self.held, keycode, key.state)),
+                        xlib::KeyRelease if self.detectable_repeat || !self.is_repeat(&key) => key_released(&mut self.held, keycode),
+                        _ => {}
+                    }
+                }
+            }
+            pressed
+        }
+    }
+
+    impl Drop for Listener {
+        fn drop(&mut self) {
+            self.ungrab_all();
+            unsafe {
+                (self.xlib.XCloseDisplay)(self.display);
+            }
+        }
+    }
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+    fn listen(mut listener: Listener, sink: ExtEventSink, updates: Receiver<Bindings>) {
+        loop {
+            match updates.try_recv() {
+                Ok(bindings) => listener.grab_all(&bindings),
+                Err(TryRecvError::Disconnected) => return,
+                Err(TryRecvError::Empty) => {}
+            }
+
+            for action in listener.pressed() {
+                // the application has been closed
+                if sink.submit_command(GLOBAL_HOTKEY, action, Target::Auto).is_err() {
+                    return;
+                }
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    pub fn start(sink: ExtEventSink, bindings: Bindings) -> Result<Sender<Bindings>, String> {
+        let (sender, updates) = channel();
+        let (connected_sender, connected) = channel();
+        thread::spawn(move || {
+            // the connection is used only by this thread
+            let mut listener = match Listener::open() {
+                Ok(listener) => listener,
+                Err(error) => {
+                    let _ = connected_sender.send(Err(error));
+                    return;
+                }
+            };
+            let _ = connected_sender.send(Ok(()));
+            // if a grab fails the thread keeps listening, a different hotkey can be set later
+            listener.grab_all(&bindings);
+            listen(listener, sink, updates);
+        });
+        match connected.recv() {
+            Ok(Ok(())) => Ok(sender),
+            Ok(Err(error)) => Err(error),
+            Err(_) => Err("the hotkey listener stopped".to_string()),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use x11_dl::xtest;
+
+        #[test]
+        fn keysym_names() {
+            assert_eq!(keysym_name("S"), "s");
+            assert_eq!(keysym_name("5"), "5");
+            assert_eq!(keysym_name("Space"), "space");
+            assert_eq!(keysym_name("Enter"), "Return");
+            assert_eq!(keysym_name("PageUp"), "Prior");
+            assert_eq!(keysym_name("PageDown"), "Next");
+            assert_eq!(keysym_name("Backspace"), "BackSpace");
+            assert_eq!(keysym_name("F12"), "F12");
+            assert_eq!(keysym_name("Print"), "Print");
+        }
+
+        #[test]
+        fn modifiers() {
+            let hotkey: Hotkey = "Ctrl+Shift+S".parse().unwrap();
+            assert_eq!(modifier_mask(&hotkey), xlib::ControlMask | xlib::ShiftMask);
+            let hotkey: Hotkey = "Super+Alt+Print".parse().unwrap();
+            assert_eq!(modifier_mask(&hotkey), xlib::Mod4Mask | xlib::Mod1Mask);
+            let hotkey: Hotkey = "F12".parse().unwrap();
+            assert_eq!(modifier_mask(&hotkey), 0);
+        }
+
+        #[test]
+        fn lock_keys_do_not_change_the_match() {
+            let modifiers = xlib::ControlMask | xlib::ShiftMask;
+            for variant in Listener::lock_variants(modifiers) {
+                assert_eq!(variant & MODIFIERS_MASK, modifiers);
+            }
+            let variants = Listener::lock_variants(modifiers);
+            assert!(variants.contains(&(modifiers | xlib::LockMask | xlib::Mod2Mask)));
+        }
+
+        #[test]
+        fn held_keys_fire_once() {
+            let grabs = [Grab { action: Action::CaptureMonitor, keycode: 96, modifiers: xlib::ControlMask },
+                         Grab { action: Action::CaptureRegion, keycode: 39, modifiers: xlib::ControlMask | xlib::ShiftMask }];
+            let mut held = vec![];
+            assert_eq!(key_pressed(&grabs, &mut held, 96, xlib::ControlMask), Some(Action::CaptureMonitor));
+            // the auto repeat while held, also of the other hotkey on the same key
+            assert_eq!(key_pressed(&grabs, &mut held, 96, xlib::ControlMask), None);
+            assert_eq!(key_pressed(&grabs, &mut held, 96, xlib::ControlMask | xlib::ShiftMask), None);
+            // another hotkey held at the same time
+            assert_eq!(key_pressed(&grabs, &mut held, 39, xlib::ControlMask | xlib::ShiftMask), Some(Action::CaptureRegion));
+            key_released(&mut held, 96);
+            assert_eq!(held, vec![39]);
+            assert_eq!(key_pressed(&grabs, &mut held, 96, xlib::ControlMask), Some(Action::CaptureMonitor));
+        }
+
+        #[test]
+        fn presses_match_the_grabbed_modifiers() {
+            let grabs = [Grab { action: Action::CaptureMonitor, keycode: 96, modifiers: xlib::ControlMask }];
+            let mut held = vec![];
+            // other modifiers, or a key without a grab, do not fire and are not held
+            assert_eq!(key_pressed(&grabs, &mut held, 96, xlib::ControlMask | xlib::Mod1Mask), None);
+            assert_eq!(key_pressed(&grabs, &mut held, 96, 0), None);
+            assert_eq!(key_pressed(&grabs, &mut held, 97, xlib::ControlMask), None);
+            assert!(held.is_empty());
+            // each lock variant of the grab matches
+            for state in Listener::lock_variants(xlib::ControlMask) {
+                assert_eq!(key_pressed(&grabs, &mut held, 96, state), Some(Action::CaptureMonitor));
+                key_released(&mut held, 96);
+            }
+        }
+
+        // e.g. with `Xvfb :99 &` and DISPLAY=:99: cargo test -- --ignored
+        #[test]
+        #[ignore = "needs an X server with XTest"]
+        fn held_hotkey_fires_once() {
+            let mut listener = Listener::open().expect("no X server");
+            let xtst = xtest::Xf86vmode::open().expect("libXtst not available");
+            listener.grab_all(&[(Action::CaptureMonitor, "F12".parse().unwrap())]);
+            let keycode = listener.grabs.first().map(|grab| grab.keycode as c_uint)
+                .expect("F12 is grabbed by another client of the server");
+            let xlib = xlib::Xlib::open().unwrap();
+            unsafe {
+                let display = (xlib.XOpenDisplay)(ptr::null());
+                assert!(!display.is_null());
+                let mut send = |keys: &[bool]| {
+                    for press in keys {
+                        (xtst.XTestFakeKeyEvent)(display, keycode, if *press { xlib::True } else { xlib::False }, 0);
+                    }
+                    (xlib.XSync)(display, xlib::False);
+                    thread::sleep(Duration::from_millis(200));
+                    listener.pressed()
+                };
+                // held: the server repeats the press
+                assert_eq!(send(&[true, true, true, false]), vec![Action::CaptureMonitor]);
+                assert_eq!(send(&[true, false]), vec![Action::CaptureMonitor]);
+                (xlib.XCloseDisplay)(display);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use std::sync::mpsc::Sender;
+    use druid::ExtEventSink;
+    use super::Bindings;
+
+    pub fn init() {}
+
+    pub fn start(_sink: ExtEventSink, _bindings: Bindings) -> Result<Sender<Bindings>, String> {
+        Err("global hotkeys are available only on Linux with X11".to_string())
+    }
+}
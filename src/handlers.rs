@@ -1,59 +1,82 @@
-use std::fs::File;
-use std::time::Duration;
-use druid::{AppDelegate, commands, DelegateCtx, Env, Event, EventCtx, Widget};
+use std::time::{Duration, Instant};
+use druid::{AppDelegate, commands, DelegateCtx, Env, Event, EventCtx, KbKey, Selector, TimerToken, Widget, WindowHandle, WindowId};
 use druid::widget::Controller;
-use serde_json::to_writer;
-use crate::{Annotation, GrabData};
-use crate::main_gui_building::start_screening;
+use crate::GrabData;
+use crate::constants::{COUNTDOWN_TICK_MS, HIDE_DELAY_MS, MAX_DELAY};
+use crate::global_hotkey::GLOBAL_HOTKEY;
+use crate::hotkey::{action_for, check_hotkey, editor_binding_for, Action, Hotkey};
+use crate::recording::{RECORDING_PROGRESS, RecordingProgress};
+use crate::timelapse::{TIMELAPSE_PROGRESS, TimelapseProgress};
+use crate::main_gui_building::{cancel_timer, capture_rect_directly, redo_edit, run_action, start_screening, undo_edit};
 
-pub struct Delegate;
+// RUN_ACTION is sent to the most recent window, which closes itself to show the overlay or the capture
+pub const RUN_ACTION: Selector<Action> = Selector::new("pds_project.run-action");
+
+#[derive(Default)]
+pub struct Delegate {
+    // open windows, in opening order
+    windows: Vec<WindowId>,
+}
 
 impl AppDelegate<GrabData> for Delegate {
     fn command(
         &mut self,
-        _ctx: &mut DelegateCtx,
+        ctx: &mut DelegateCtx,
         _target: druid::Target,
         cmd: &druid::Command,
         data: &mut GrabData,
         _env: &druid::Env,
     ) -> druid::Handled {
-        if cmd.is(commands::CLOSE_WINDOW) {
-            // Handle the window close event
-            // create a data copy to save into json, without actually modifying data (NEEDED FOR LINUX)
-            let json_data = GrabData {
-                screenshot_number: data.screenshot_number,
-                image_data_old: vec![],
-                image_data_new: vec![],
-                save_path: data.save_path.clone(),
-                save_format: data.save_format.clone(),
-                press: false,
-                first_screen: true,
-                scale_factors: (1.0, 1.0),
-                image_size: data.image_size,
-                positions: vec![],
-                offsets: (0.0, 0.0),
-                hotkey: data.hotkey.clone(),
-                hotkey_new: vec![],
-                hotkey_pressed: vec![],
-                set_hot_key: false,
-                delay: data.delay.clone(),
-                input_hotkey_error: (false,"Invalid Input: Wrong Hotkey.".to_string()),
-                trigger_ui: false,
-                annotation: Annotation::None,
-                color: data.color,
-                text_annotation: "".to_string(),
-                text_size: data.text_size,
-                highlighter_width: data.highlighter_width,
-                timer_requested: false,
-                image_copied: false,
+        if let Some(action) = cmd.get(GLOBAL_HOTKEY) {
+            // only one window has to run the action
+            if let Some(window) = self.windows.last() {
+                ctx.submit_command(RUN_ACTION.with(*action).to(*window));
+            }
+            return druid::Handled::Yes;
+        }
+        if let Some(progress) = cmd.get(TIMELAPSE_PROGRESS) {
+            data.timelapse_status = match progress {
+                TimelapseProgress::Saved(frame, path) => format!("Frame {} saved: {}", frame, path.display()),
+                TimelapseProgress::Failed(frame, error) => format!("Frame {} failed: {}", frame, error),
+                TimelapseProgress::Finished(frames) => {
+                    data.timelapse_running = false;
+                    data.timelapse_stop = None;
+                    format!("Timelapse finished, {} frames taken", frames)
+                }
+            };
+            return druid::Handled::Yes;
+        }
+        if let Some(progress) = cmd.get(RECORDING_PROGRESS) {
+            data.recording_status = match progress {
+                RecordingProgress::Frame(frame, total) => format!("Recording frame {} of {}", frame, total),
+                RecordingProgress::Encoding(frames) => format!("Encoding {} frames..", frames),
+                RecordingProgress::Saved(path) => format!("Recording saved: {}", path.display()),
+                RecordingProgress::Failed(error) => format!("Recording failed: {}", error),
             };
-            let file = File::create("settings.json").unwrap();
-            to_writer(file, &json_data).unwrap();
+            if matches!(progress, RecordingProgress::Saved(_) | RecordingProgress::Failed(_)) {
+                data.recording_running = false;
+                data.recording_stop = None;
+            }
+            return druid::Handled::Yes;
+        }
+        if cmd.is(commands::CLOSE_WINDOW) {
+            // Handle the window close event, only the settings are persisted
+            if let Err(error) = data.settings.save() {
+                eprintln!("{}", error);
+            }
             // the event keep processing and the window is closed
             return druid::Handled::No;
         }
         druid::Handled::No
     }
+
+    fn window_added(&mut self, id: WindowId, _handle: WindowHandle, _data: &mut GrabData, _env: &Env, _ctx: &mut DelegateCtx) {
+        self.windows.push(id);
+    }
+
+    fn window_removed(&mut self, id: WindowId, _data: &mut GrabData, _env: &Env, _ctx: &mut DelegateCtx) {
+        self.windows.retain(|window| *window != id);
+    }
 }
 
 pub struct Enter;
@@ -64,55 +87,54 @@ impl<W: Widget<GrabData>> Controller<GrabData, W> for Enter {
         match event {
             Event::WindowConnected => {
                 ctx.request_focus();
-                if data.timer_requested {
-                    //ctx.window().hide();
-                    ctx.request_timer(Duration::from_secs(data.delay as u64));
-                    data.timer_requested = false;
+                if data.pending_capture.is_some() {
+                    // give the windows of the application the time to disappear from the screen
+                    ctx.request_timer(Duration::from_millis(HIDE_DELAY_MS));
                 }
             }
-            Event::KeyUp(event) => {
-                if data.hotkey.contains(&event.key.to_string()) {
-                    data.hotkey_pressed = vec![];
+            Event::KeyDown(key_event) if data.editing_hotkey.is_none() && editor_binding_for(key_event).is_some() => {
+                // Ctrl+Z undo, Ctrl+Shift+Z redo the edits of the current capture
+                if editor_binding_for(key_event) == Some("Redo") {
+                    redo_edit(ctx, data);
+                } else {
+                    undo_edit(ctx, data);
                 }
             }
-            Event::KeyDown(key_event) => {
-                if data.set_hot_key {
-                    // capture and set the hotkey for screen grabbing
-                    data.trigger_ui = !data.trigger_ui;
-                    // avoid to add many times the same character if is being pressed
-                    if data.hotkey_new.is_empty() {
-                        data.hotkey_new.push(key_event.key.to_string());
-                    } else if data.hotkey_new.len()>2{
-                        data.input_hotkey_error.0 = true;
-                        data.input_hotkey_error.1 = "Max 3 keys, confirm please".to_string();
-                    } else if data.hotkey_new.len() >= 1 && !data.hotkey_new.contains(&key_event.key.to_string())  {
-                        data.hotkey_new.push(key_event.key.to_string());
-                        data.input_hotkey_error = (false,"".to_string());
-                    }else{
-                        data.input_hotkey_error.0 = true;
-                        data.input_hotkey_error.1 = "Only distinct keys".to_string();
-                    }
-                } else {
-
-                    // check key of hotkey not yet pressed
-                    if data.hotkey.contains(&key_event.key.to_string())   {
-
-                        if !data.hotkey_pressed.contains(&key_event.key.to_string()) {
-                            data.hotkey_pressed.push(key_event.key.to_string());
+            Event::KeyDown(key_event) if data.editing_hotkey.is_some() => {
+                // record the new hotkey of the action, the modifiers alone wait for the key
+                data.trigger_ui = !data.trigger_ui;
+                let action = data.editing_hotkey.expect("the guard checks that a hotkey is being recorded");
+                match Hotkey::from_key_event(key_event) {
+                    None => {}
+                    Some(Ok(hotkey)) => match check_hotkey(&hotkey, action, &data.settings.hotkeys) {
+                        Ok(()) => {
+                            data.hotkey_new = Some(hotkey);
+                            data.input_hotkey_error = (false, "".to_string());
                         }
-                    }else {
-                        data.hotkey_pressed = vec![];
-                    }
-                    // if the pressed keys corresponds to the hotkey combination, acquire the screen
-                    if data.hotkey_pressed.len() == data.hotkey.len() {
-                        // acquire screen
-                        start_screening(ctx,data);
-                        data.hotkey_pressed = vec![];
+                        Err(error) => {
+                            data.hotkey_new = None;
+                            data.input_hotkey_error = (true, error);
+                        }
+                    },
+                    Some(Err(error)) => {
+                        data.hotkey_new = None;
+                        data.input_hotkey_error = (true, error);
                     }
                 }
             }
+            Event::KeyDown(key_event) if !key_event.repeat => {
+                if let Some(action) = action_for(&data.settings.hotkeys, key_event) {
+                    run_action(ctx, data, action);
+                }
+            }
+            Event::Command(command) if command.is(RUN_ACTION) && data.editing_hotkey.is_none() => {
+                // global hotkey pressed, the action runs also if the window is not focused
+                run_action(ctx, data, *command.get_unchecked(RUN_ACTION));
+            }
             Event::Timer(_) => {
-                start_screening(ctx,data);
+                if let Some(rect) = data.pending_capture.take() {
+                    capture_rect_directly(ctx, data, rect);
+                }
             }
             _ => {} // Handle other cases if needed
         }
@@ -134,4 +156,89 @@ impl<W: Widget<GrabData>> Controller<GrabData, W> for Enter {
     fn update(&mut self, child: &mut W, ctx: &mut druid::UpdateCtx, old_data: &GrabData, data: &GrabData, env: &Env) {
         child.update(ctx, old_data, data, env)
     }
-}
\ No newline at end of file
+}
+
+// the countdown window of the timed capture: the remaining time is shown with a tenth of second precision,
+// Escape (or the Cancel button) stops it, at the end the region capture starts
+#[derive(Default)]
+pub struct Countdown {
+    deadline: Option<Instant>,
+    tick: Option<TimerToken>,
+}
+
+#[derive(Debug, PartialEq)]
+enum CountdownTick {
+    // seconds left, shown in the window, and the delay of the next tick
+    Wait(f64, Duration),
+    Capture,
+    Cancelled,
+}
+
+// what a tick at `now` does, the deadline is None once the countdown is cancelled
+fn countdown_tick(deadline: Option<Instant>, now: Instant) -> CountdownTick {
+    match deadline.map(|deadline| deadline.saturating_duration_since(now)) {
+        None => CountdownTick::Cancelled,
+        Some(remaining) if remaining.is_zero() => CountdownTick::Capture,
+        Some(remaining) => CountdownTick::Wait(remaining.as_secs_f64(), Duration::from_millis(COUNTDOWN_TICK_MS).min(remaining)),
+    }
+}
+
+impl<W: Widget<GrabData>> Controller<GrabData, W> for Countdown {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &druid::Event, data: &mut GrabData, env: &Env) {
+        match event {
+            Event::WindowConnected => {
+                ctx.request_focus();
+                let delay = data.settings.delay.clamp(0.0, MAX_DELAY);
+                self.deadline = Some(Instant::now() + Duration::from_secs_f64(delay));
+                data.countdown = delay;
+                self.tick = Some(ctx.request_timer(Duration::from_millis(COUNTDOWN_TICK_MS).min(Duration::from_secs_f64(delay))));
+            }
+            Event::Timer(token) if Some(*token) == self.tick => {
+                match countdown_tick(self.deadline, Instant::now()) {
+                    CountdownTick::Wait(seconds, next) => {
+                        data.countdown = seconds;
+                        self.tick = Some(ctx.request_timer(next));
+                    }
+                    CountdownTick::Capture => {
+                        self.deadline = None;
+                        self.tick = None;
+                        data.countdown = 0.0;
+                        start_screening(ctx, data);
+                    }
+                    CountdownTick::Cancelled => self.tick = None,
+                }
+            }
+            Event::KeyDown(key_event) if key_event.key == KbKey::Escape => {
+                self.deadline = None;
+                self.tick = None;
+                cancel_timer(ctx, data);
+            }
+            _ => {}
+        }
+
+        child.event(ctx, event, data, env)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn countdown_ticks() {
+        let now = Instant::now();
+        let deadline = now + Duration::from_secs_f64(2.35);
+        assert_eq!(countdown_tick(Some(deadline), now), CountdownTick::Wait(2.35, Duration::from_millis(COUNTDOWN_TICK_MS)));
+        // under a tick the next one comes at the deadline
+        let remaining = Duration::from_millis(30);
+        assert_eq!(countdown_tick(Some(deadline), deadline - remaining), CountdownTick::Wait(0.03, remaining));
+        assert_eq!(countdown_tick(Some(deadline), deadline), CountdownTick::Capture);
+        // a late tick
+        assert_eq!(countdown_tick(Some(deadline), deadline + Duration::from_secs(1)), CountdownTick::Capture);
+    }
+
+    #[test]
+    fn cancelled_countdown_does_not_capture() {
+        assert_eq!(countdown_tick(None, Instant::now()), CountdownTick::Cancelled);
+    }
+}
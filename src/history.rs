@@ -0,0 +1,87 @@
+// UNDO / REDO HISTORY
+// every approved edit (crop or annotation) stores the previous document, up to HISTORY_LIMIT steps
+
+use crate::constants::HISTORY_LIMIT;
+use crate::document::Document;
+
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    undo_stack: Vec<Document>,
+    redo_stack: Vec<Document>,
+}
+
+impl History {
+    // store the document before a new edit, the redo steps are lost
+    pub fn record(&mut self, previous: Document) {
+        self.undo_stack.push(previous);
+        if self.undo_stack.len() > HISTORY_LIMIT {
+            self.undo_stack.remove(0);
+        }
+        self.redo_stack.clear();
+    }
+
+    // return the document to restore, keeping the current one for the redo
+    pub fn undo(&mut self, current: &Document) -> Option<Document> {
+        let previous = self.undo_stack.pop()?;
+        self.redo_stack.push(current.clone());
+        Some(previous)
+    }
+
+    pub fn redo(&mut self, current: &Document) -> Option<Document> {
+        let next = self.redo_stack.pop()?;
+        self.undo_stack.push(current.clone());
+        Some(next)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(step: u32) -> Document {
+        let mut document = Document::default();
+        document.crop = Some((step, 0, 10, 10));
+        document
+    }
+
+    #[test]
+    fn oldest_step_dropped_over_limit() {
+        let mut history = History::default();
+        for step in 0..=HISTORY_LIMIT as u32 {
+            history.record(document(step));
+        }
+        let mut current = document(HISTORY_LIMIT as u32 + 1);
+        for step in (1..=HISTORY_LIMIT as u32).rev() {
+            current = history.undo(&current).unwrap();
+            assert_eq!(current, document(step));
+        }
+        // the first document is over the limit
+        assert!(!history.can_undo());
+        assert_eq!(history.undo(&current), None);
+    }
+
+    #[test]
+    fn new_edit_clears_redo() {
+        let mut history = History::default();
+        history.record(document(0));
+        let previous = history.undo(&document(1)).unwrap();
+        assert_eq!(previous, document(0));
+        assert!(history.can_redo());
+        assert_eq!(history.redo(&previous), Some(document(1)));
+        history.record(document(1));
+        assert!(!history.can_redo());
+    }
+}
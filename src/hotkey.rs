@@ -0,0 +1,325 @@
+// HOTKEYS
+// a hotkey is a set of modifiers and one key, written as "Ctrl+Shift+S" or "Super+Print" in the settings
+// and in the interface; the parsing normalizes the names (e.g. "control+shift+s" is "Ctrl+Shift+S").
+// Every action of the application can be bound to its own hotkey, the bindings are kept in the settings
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use druid::{Data, KeyEvent};
+use serde::{Serialize, Deserialize};
+
+// what a hotkey does, the capture actions are also grabbed for the whole desktop
+#[derive(Clone, Copy, Data, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    CaptureDesktop,
+    CaptureMonitor,
+    CaptureRegion,
+    RepeatRegion,
+    DelayedCapture,
+    CopyToClipboard,
+    Save,
+}
+
+impl Action {
+    pub const ALL: [Action; 7] = [Action::CaptureDesktop, Action::CaptureMonitor, Action::CaptureRegion, Action::RepeatRegion,
+        Action::DelayedCapture, Action::CopyToClipboard, Action::Save];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::CaptureDesktop => "Capture the whole desktop",
+            Action::CaptureMonitor => "Capture the current monitor",
+            Action::CaptureRegion => "Capture a region",
+            Action::RepeatRegion => "Capture the last region again",
+            Action::DelayedCapture => "Capture with the timer",
+            Action::CopyToClipboard => "Copy to clipboard",
+            Action::Save => "Save",
+        }
+    }
+
+    // copy and save work on the capture being edited, only in the application windows
+    pub fn is_global(&self) -> bool {
+        !matches!(self, Action::CopyToClipboard | Action::Save)
+    }
+}
+
+// every action is bound at first, the other captures on Ctrl+Alt: most Ctrl+Shift letters are taken by the browsers
+pub fn default_hotkeys() -> BTreeMap<Action, Hotkey> {
+    BTreeMap::from([
+        (Action::CaptureDesktop, "Ctrl+Alt+D".parse().unwrap()),
+        (Action::CaptureMonitor, "Ctrl+Alt+M".parse().unwrap()),
+        (Action::CaptureRegion, Hotkey::default()),
+        (Action::RepeatRegion, "Ctrl+Alt+R".parse().unwrap()),
+        (Action::DelayedCapture, "Ctrl+Alt+W".parse().unwrap()),
+        (Action::CopyToClipboard, "Ctrl+Shift+C".parse().unwrap()),
+        (Action::Save, "Ctrl+S".parse().unwrap()),
+    ])
+}
+
+// the editor shortcut of the pressed keys
+pub fn editor_binding_for(event: &KeyEvent) -> Option<&'static str> {
+    match Hotkey::from_key_event(event) {
+        Some(Ok(hotkey)) => hotkey.editor_binding(),
+        _ => None,
+    }
+}
+
+// the action bound to the pressed keys
+pub fn action_for(hotkeys: &BTreeMap<Action, Hotkey>, event: &KeyEvent) -> Option<Action> {
+    hotkeys.iter().find(|(_, hotkey)| hotkey.matches(event)).map(|(action, _)| *action)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Hotkey {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub super_key: bool,
+    // normalized name: uppercase letter, digit or named key (Print, Space, F1..)
+    pub key: String,
+}
+
+// shortcuts of the editor, a hotkey cannot take them
+pub const EDITOR_BINDINGS: [(&str, &str); 2] = [("Undo", "Ctrl+Z"), ("Redo", "Ctrl+Shift+Z")];
+
+const NAMED_KEYS: [(&str, &[&str]); 17] = [
+    ("Print", &["print", "printscreen", "prtsc", "prtscr"]),
+    ("Space", &["space", " "]),
+    ("Enter", &["enter", "return"]),
+    ("Escape", &["escape", "esc"]),
+    ("Tab", &["tab"]),
+    ("Backspace", &["backspace"]),
+    ("Delete", &["delete", "del"]),
+    ("Insert", &["insert", "ins"]),
+    ("Home", &["home"]),
+    ("End", &["end"]),
+    ("PageUp", &["pageup", "pgup"]),
+    ("PageDown", &["pagedown", "pgdn"]),
+    ("Left", &["left", "arrowleft"]),
+    ("Right", &["right", "arrowright"]),
+    ("Up", &["up", "arrowup"]),
+    ("Down", &["down", "arrowdown"]),
+    ("Pause", &["pause"]),
+];
+
+// normalized name of a key, None if it is not supported
+fn normalize_key(name: &str) -> Option<String> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_alphanumeric() {
+            return Some(c.to_ascii_uppercase().to_string());
+        }
+    }
+    let lowercase = name.to_ascii_lowercase();
+    if let Some(number) = lowercase.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+        return if (1..=24).contains(&number) { Some(format!("F{}", number)) } else { None };
+    }
+    NAMED_KEYS.iter()
+        .find(|(_, aliases)| aliases.contains(&lowercase.as_str()))
+        .map(|(name, _)| name.to_string())
+}
+
+fn is_modifier_key(name: &str) -> bool {
+    matches!(name, "Control" | "Shift" | "Alt" | "AltGraph" | "Meta" | "Super" | "Hyper" | "CapsLock" | "NumLock")
+}
+
+impl Hotkey {
+    // keys that type a character need a modifier, or the hotkey would fire while writing
+    pub fn validate(&self) -> Result<(), String> {
+        let types_character = self.key.chars().count() == 1 || self.key == "Space";
+        if types_character && !self.ctrl && !self.alt && !self.super_key {
+            return Err(format!("{} would fire while typing, add Ctrl, Alt or Super", self));
+        }
+        Ok(())
+    }
+
+    // the hotkey of a key press, None while only modifiers are pressed
+    pub fn from_key_event(event: &KeyEvent) -> Option<Result<Hotkey, String>> {
+        let key_name = event.key.to_string();
+        if is_modifier_key(&key_name) {
+            return None;
+        }
+        // letters and digits from the physical key, the character changes with Shift
+        let code = event.code.to_string();
+        let name = code.strip_prefix("Key").or(code.strip_prefix("Digit")).map(str::to_string).unwrap_or(key_name);
+        let key = match normalize_key(&name) {
+            Some(key) => key,
+            None => return Some(Err(format!("The key {} is not supported", name))),
+        };
+        Some(Ok(Hotkey { ctrl: event.mods.ctrl(), alt: event.mods.alt(), shift: event.mods.shift(), super_key: event.mods.meta(), key }))
+    }
+
+    pub fn matches(&self, event: &KeyEvent) -> bool {
+        matches!(Hotkey::from_key_event(event), Some(Ok(ref hotkey)) if hotkey == self)
+    }
+
+    // the first binding using the same keys, as "Ctrl+Z is already used by Undo"
+    pub fn find_conflict(&self, bindings: &[(String, Hotkey)]) -> Option<String> {
+        bindings.iter()
+            .find(|(_, hotkey)| hotkey == self)
+            .map(|(action, _)| format!("{} is already used by {}", self, action))
+    }
+
+    // the editor shortcut using exactly these keys, e.g. "Undo" for Ctrl+Z but not for Ctrl+Alt+Z
+    pub fn editor_binding(&self) -> Option<&'static str> {
+        EDITOR_BINDINGS.iter()
+            .find(|(_, hotkey)| hotkey.parse::<Hotkey>().is_ok_and(|hotkey| hotkey == *self))
+            .map(|(action, _)| *action)
+    }
+
+    pub fn editor_bindings() -> Vec<(String, Hotkey)> {
+        EDITOR_BINDINGS.iter()
+            .map(|(action, hotkey)| (action.to_string(), hotkey.parse().unwrap()))
+            .collect()
+    }
+}
+
+// the new hotkey of an action must be valid and not used by the editor or by another action
+pub fn check_hotkey(hotkey: &Hotkey, action: Action, hotkeys: &BTreeMap<Action, Hotkey>) -> Result<(), String> {
+    hotkey.validate()?;
+    let mut bindings = Hotkey::editor_bindings();
+    bindings.extend(hotkeys.iter()
+        .filter(|(other, _)| **other != action)
+        .map(|(other, hotkey)| (other.label().to_string(), hotkey.clone())));
+    match hotkey.find_conflict(&bindings) {
+        Some(conflict) => Err(conflict),
+        None => Ok(()),
+    }
+}
+
+impl Default for Hotkey {
+    fn default() -> Self {
+        Hotkey { ctrl: true, alt: false, shift: true, super_key: false, key: "S".to_string() }
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (pressed, name) in [(self.ctrl, "Ctrl"), (self.alt, "Alt"), (self.shift, "Shift"), (self.super_key, "Super")] {
+            if pressed {
+                write!(f, "{}+", name)?;
+            }
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+impl FromStr for Hotkey {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut hotkey = Hotkey { ctrl: false, alt: false, shift: false, super_key: false, key: String::new() };
+        if text.trim().is_empty() {
+            return Err("The hotkey is empty".to_string());
+        }
+        for part in text.split('+').map(str::trim) {
+            let modifier = match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => Some(&mut hotkey.ctrl),
+                "alt" | "option" => Some(&mut hotkey.alt),
+                "shift" => Some(&mut hotkey.shift),
+                "super" | "win" | "meta" | "cmd" | "command" => Some(&mut hotkey.super_key),
+                _ => None,
+            };
+            match modifier {
+                Some(pressed) if *pressed => return Err(format!("{} is repeated", part)),
+                Some(pressed) => *pressed = true,
+                None => {
+                    let key = normalize_key(part).ok_or(format!("Unknown key: {}", part))?;
+                    if !hotkey.key.is_empty() {
+                        return Err(format!("Only one key can be used besides the modifiers, not {} and {}", hotkey.key, key));
+                    }
+                    hotkey.key = key;
+                }
+            }
+        }
+        if hotkey.key.is_empty() {
+            return Err("A key is missing after the modifiers".to_string());
+        }
+        hotkey.validate()?;
+        Ok(hotkey)
+    }
+}
+
+impl TryFrom<String> for Hotkey {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+impl From<Hotkey> for String {
+    fn from(hotkey: Hotkey) -> Self {
+        hotkey.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn written_in_any_case_and_order() {
+        let hotkey: Hotkey = "ctrl+shift+c".parse().unwrap();
+        assert_eq!(hotkey, "Ctrl+Shift+C".parse().unwrap());
+        assert_eq!(hotkey, " Shift + Control + c ".parse().unwrap());
+        assert_eq!(hotkey.to_string(), "Ctrl+Shift+C");
+        assert_eq!("super+prtsc".parse::<Hotkey>().unwrap().to_string(), "Super+Print");
+        assert_eq!("cmd+alt+pgup".parse::<Hotkey>().unwrap().to_string(), "Alt+Super+PageUp");
+        assert_eq!("f12".parse::<Hotkey>().unwrap().to_string(), "F12");
+    }
+
+    #[test]
+    fn invalid_hotkeys() {
+        assert!("".parse::<Hotkey>().is_err());
+        assert!("Ctrl+Shift".parse::<Hotkey>().is_err());
+        assert!("Ctrl+Ctrl+S".parse::<Hotkey>().is_err());
+        assert!("Ctrl+A+B".parse::<Hotkey>().is_err());
+        assert!("Ctrl+F25".parse::<Hotkey>().is_err());
+        assert!("Ctrl+Foo".parse::<Hotkey>().is_err());
+        // they would fire while typing
+        assert!("S".parse::<Hotkey>().is_err());
+        assert!("Shift+Space".parse::<Hotkey>().is_err());
+        assert!("Alt+Space".parse::<Hotkey>().is_ok());
+    }
+
+    #[test]
+    fn editor_shortcuts_need_the_exact_keys() {
+        assert_eq!("Ctrl+Z".parse::<Hotkey>().unwrap().editor_binding(), Some("Undo"));
+        assert_eq!("Ctrl+Shift+Z".parse::<Hotkey>().unwrap().editor_binding(), Some("Redo"));
+        for other in ["Ctrl+Alt+Z", "Ctrl+Super+Z", "Ctrl+Alt+Shift+Z", "Alt+Z", "Ctrl+Y"] {
+            assert_eq!(other.parse::<Hotkey>().unwrap().editor_binding(), None, "{}", other);
+        }
+    }
+
+    #[test]
+    fn conflicts() {
+        let hotkeys = default_hotkeys();
+        // the editor shortcuts, written in another case
+        let undo: Hotkey = "ctrl+z".parse().unwrap();
+        assert_eq!(check_hotkey(&undo, Action::CaptureDesktop, &hotkeys), Err("Ctrl+Z is already used by Undo".to_string()));
+        // the hotkey of another action
+        let copy: Hotkey = "shift+ctrl+c".parse().unwrap();
+        assert_eq!(check_hotkey(&copy, Action::CaptureDesktop, &hotkeys),
+                   Err(format!("Ctrl+Shift+C is already used by {}", Action::CopyToClipboard.label())));
+        // the action keeps its own hotkey
+        assert_eq!(check_hotkey(&copy, Action::CopyToClipboard, &hotkeys), Ok(()));
+        let free: Hotkey = "Ctrl+Alt+F".parse().unwrap();
+        assert_eq!(check_hotkey(&free, Action::CaptureDesktop, &hotkeys), Ok(()));
+        assert!(check_hotkey(&Hotkey { ctrl: false, alt: false, shift: true, super_key: false, key: "D".to_string() },
+                             Action::CaptureDesktop, &hotkeys).is_err());
+    }
+
+    #[test]
+    fn every_action_has_a_default() {
+        let hotkeys = default_hotkeys();
+        for action in Action::ALL {
+            let hotkey = hotkeys.get(&action).unwrap_or_else(|| panic!("{} has no default hotkey", action.label()));
+            // valid and not used by the editor or by another action
+            assert_eq!(check_hotkey(hotkey, action, &hotkeys), Ok(()));
+        }
+        assert_eq!(hotkeys.len(), Action::ALL.len());
+    }
+}
@@ -1,19 +1,66 @@
-use druid::{BoxConstraints, Color, Cursor, Env, Event, EventCtx, ImageBuf, LayoutCtx, LifeCycle, LifeCycleCtx, Rect, RenderContext, Size, UpdateCtx, Widget, WindowDesc};
+use druid::{BoxConstraints, Color, Cursor, Env, Event, EventCtx, ImageBuf, LayoutCtx, LifeCycle, LifeCycleCtx, Point, Rect, RenderContext, Size, UpdateCtx, Widget, WindowDesc};
 use druid::piet::{ImageFormat};
 use druid::widget::{Flex, Image, SizedBox, Label};
-use image::{DynamicImage, ImageBuffer, Rgba};
-use image::imageops::overlay;
-use imageproc::drawing::{draw_hollow_circle, draw_hollow_rect, draw_line_segment, draw_polygon};
 use crate::{constants, GrabData, Annotation};
 use constants::{BUTTON_HEIGHT,BUTTON_WIDTH};
-use crate::main_gui_building::{create_edit_window, create_save_cancel_clipboard_buttons, create_selection_window};
+use crate::main_gui_building::{create_edit_window, create_error_window, create_save_cancel_clipboard_buttons, create_selection_window};
 use druid::kurbo::Line;
-use crate::constants::{APP_NAME, BORDER_WIDTH, TRANSPARENCY};
-use druid::kurbo::{BezPath, Circle};
-use crate::utilities::{compute_offsets, make_rectangle_from_points, load_image, compute_circle_center_radius, compute_arrow_points, image_to_buffer, compute_highlighter_points, resize_image, screen_all};
+use crate::constants::{APP_NAME, BORDER_WIDTH, HAIRLINE_WIDTH, SELECT_TOLERANCE, STEP_RADIUS_FACTOR, TRANSPARENCY};
+use crate::document::{AnnotationObject, segment_quad, shape_geometry};
+use druid::kurbo::{BezPath, Circle, Shape};
+use crate::utilities::{compute_offsets, constrain_to_square, first_monitor_rect, make_rectangle_from_points, compute_highlighter_points, make_annotation_object, render_image, resize_image, screen_selection, to_image_point, to_widget_point};
 
 pub struct ScreenshotWidget;
 
+// the shape as it will be rasterised: the same geometry, converted in coordinates of the widget
+fn paint_shape(paint_ctx: &mut druid::PaintCtx, data: &GrabData, object: &AnnotationObject) {
+    let geometry = shape_geometry(object);
+    let to_widget = |point: (f64,f64)| Point::from(to_widget_point(data, point));
+
+    if let (Some(polygon), Some(fill)) = (&geometry.fill, object.fill) {
+        let mut path = BezPath::new();
+        path.move_to(to_widget(polygon[0]));
+        for point in polygon.iter().skip(1) {
+            path.line_to(to_widget(*point));
+        }
+        path.close_path();
+        paint_ctx.fill(path, &Color::rgba8(fill.0, fill.1, fill.2, fill.3));
+    }
+
+    let color = Color::rgba8(object.color.0, object.color.1, object.color.2, object.color.3);
+    if geometry.width < HAIRLINE_WIDTH {
+        for piece in geometry.pieces.iter() {
+            if piece.len() == 1 {
+                paint_ctx.fill(Circle::new(to_widget(piece[0]), BORDER_WIDTH / 2.0), &color);
+            }
+            for segment in piece.windows(2) {
+                paint_ctx.stroke(Line::new(to_widget(segment[0]), to_widget(segment[1])), &color, BORDER_WIDTH);
+            }
+        }
+    } else {
+        // one path, so that the overlapping quads and joins are painted once
+        let width = geometry.width / data.scale_factors.0;
+        let mut path = BezPath::new();
+        for piece in geometry.pieces.iter() {
+            let piece: Vec<(f64,f64)> = piece.iter().map(|point| to_widget_point(data, *point)).collect();
+            for segment in piece.windows(2) {
+                if let Some(quad) = segment_quad(segment[0], segment[1], width) {
+                    path.move_to(quad[0]);
+                    for corner in quad.iter().skip(1) {
+                        path.line_to(*corner);
+                    }
+                    path.close_path();
+                }
+            }
+            // round joins and caps
+            for point in piece.iter() {
+                path.extend(Circle::new(*point, width / 2.0).path_elements(0.1));
+            }
+        }
+        paint_ctx.fill(path, &color);
+    }
+}
+
 impl Widget<GrabData> for ScreenshotWidget {
     fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut GrabData, _env: &Env) {
         let min_x;
@@ -26,10 +73,31 @@ impl Widget<GrabData> for ScreenshotWidget {
                 compute_offsets(ctx, data);
                 data.press = true;
             }
+            // a right click removes the step badge under the mouse, the next ones are renumbered
+            if data.annotation == Annotation::Step && mouse_event.button.is_right() {
+                if !data.first_screen {
+                    // the window may have been resized since the last left click
+                    compute_offsets(ctx, data);
+                    let point = to_image_point(data, (mouse_event.window_pos.x,mouse_event.window_pos.y));
+                    if let Some(id) = data.draft.step_at(point, SELECT_TOLERANCE * data.scale_factors.0) {
+                        data.draft.remove(id);
+                        ctx.request_paint();
+                    }
+                }
+                return;
+            }
             //if annotation text, simply take the point where the mouse is pressed (take no point when mouse moves)
-            if data.annotation == Annotation::Text {
+            //the step badge is placed by a click, so it also needs this point
+            if data.annotation == Annotation::Text || data.annotation == Annotation::Step {
                 data.positions.push((mouse_event.window_pos.x,mouse_event.window_pos.y));
             }
+            // pick the annotation under the mouse, the drag starts from this point
+            if data.annotation == Annotation::Select && !data.first_screen {
+                let position = (mouse_event.window_pos.x,mouse_event.window_pos.y);
+                data.positions.push(position);
+                data.selected = data.draft.hit_test(to_image_point(data, position), SELECT_TOLERANCE * data.scale_factors.0);
+                ctx.request_paint();
+            }
         }
         /*if let Event::WindowConnected = event {
             data.scale_factor = ctx.window().get_size().height / ctx.window().get_size().width;
@@ -41,9 +109,14 @@ impl Widget<GrabData> for ScreenshotWidget {
             if data.annotation == Annotation::Text {
                 ctx.set_cursor(&Cursor::IBeam);
             } else {
-                ctx.set_cursor(&Cursor::Crosshair);
+                ctx.set_cursor(if data.annotation == Annotation::Select { &Cursor::Arrow } else { &Cursor::Crosshair });
                 if data.press {
-                    data.positions.push((mouse_event.window_pos.x,mouse_event.window_pos.y));
+                    let mut position = (mouse_event.window_pos.x,mouse_event.window_pos.y);
+                    // Shift draws a perfect circle or square
+                    if mouse_event.mods.shift() && data.annotation.can_constrain() && !data.positions.is_empty() {
+                        position = constrain_to_square(data.positions[0], position);
+                    }
+                    data.positions.push(position);
                 }
 
             }
@@ -74,19 +147,24 @@ impl Widget<GrabData> for ScreenshotWidget {
                     let buffer = image.to_png(None).unwrap();
 
                     data.image_data_old = buffer;*/
-                    screen_all(min_x,min_y,max_x,max_y,data);
+                    if let Err(error) = screen_selection(min_x,min_y,max_x,max_y,data) {
+                        data.positions = vec![];
+                        return create_error_window(ctx, data, &error);
+                    }
                     // empty positions
                     data.positions = vec![];
                 }
 
-                let mut dynamic_image = load_image(data);
-                let mut cropped_annotated_image = dynamic_image.clone();
                 if !data.first_screen {
 
                     match data.annotation {
                         Annotation::None => {
                             if min_x < 0 || min_y < 0 || ((max_x - min_x) as f64 * data.scale_factors.0) as u32 <= 0
                                 || ((max_y - min_y) as f64 * data.scale_factors.1) as u32 <=0 {
+                                let dynamic_image = match render_image(data) {
+                                    Ok(image) => image,
+                                    Err(error) => return create_error_window(ctx, data, &error),
+                                };
                                 let rgba_image = dynamic_image.to_rgba8();
                                 let buffer = ImageBuf::from_raw(
                                     rgba_image.clone().into_raw(),
@@ -94,8 +172,14 @@ impl Widget<GrabData> for ScreenshotWidget {
                                     rgba_image.clone().width() as usize,
                                     rgba_image.clone().height() as usize,
                                 );
-                                let rect = druid::Screen::get_monitors()[0].virtual_rect();
-                                let (image_width,image_height) = resize_image(dynamic_image,data);
+                                let rect = match first_monitor_rect() {
+                                    Ok(rect) => rect,
+                                    Err(error) => return create_error_window(ctx, data, &error),
+                                };
+                                let (image_width,image_height) = match resize_image(dynamic_image,data) {
+                                    Ok(size) => size,
+                                    Err(error) => return create_error_window(ctx, data, &error),
+                                };
 
                                 ctx.window().close();
                                 ctx.new_window(WindowDesc::new(Flex::column().with_child(Label::new("Cannot Crop: Image too Small. \nChoose if save the image as it is or undo:"))
@@ -109,184 +193,46 @@ impl Widget<GrabData> for ScreenshotWidget {
                                 return;
                             }
 
-                            cropped_annotated_image = dynamic_image.crop(
+                            // the crop is kept in the document, the original capture is not modified
+                            data.draft.crop_to(
                                 ((min_x as f64 - data.offsets.0) * data.scale_factors.0) as u32,
                                 ((min_y as f64 - data.offsets.1) * data.scale_factors.1) as u32,
                                 (((max_x as f64- data.offsets.0) - (min_x as f64 - data.offsets.0)) * data.scale_factors.0) as u32,
                                 (((max_y as f64- data.offsets.1) - (min_y as f64 - data.offsets.1)) * data.scale_factors.1) as u32
                             );
-
-                            /*if cropped_annotated_image.width() >= (screen.display_info.width as f64 * LIMIT_PROPORTION) as u32 || cropped_annotated_image.height() >= (screen.display_info.height as f64 * LIMIT_PROPORTION) as u32 {
-                                data.scale_factor = SCALE_FACTOR;
-                            } else {
-                                data.scale_factor = 1.0;
-                            }*/
-                            // cropped_annotated_image = cropped_annotated_image.resize((cropped_annotated_image.width() as f64 * data.scale_factor) as u32, (cropped_annotated_image.height() as f64 * data.scale_factor) as u32, FilterType::Nearest);
-
-                        },
-                        Annotation::Circle => {
-                            // compute the center and the radius
-                            let (center_x, center_y) = compute_circle_center_radius(data, min_x, min_y, max_x, max_y);
-                            let image = load_image(data);
-                            let radius = (((data.scale_factors.0 * (max_x - min_x) as f64).powi(2) + (data.scale_factors.1 * (max_y - min_y) as f64).powi(2)) as f64).sqrt()/ 2.0;
-
-                            cropped_annotated_image = DynamicImage::from(draw_hollow_circle(&image, ((center_x * data.scale_factors.0) as i32, (center_y * data.scale_factors.1) as i32), radius as i32, Rgba([data.color.0,
-                                data.color.1, data.color.2, data.color.3])));
-
-                        },
-                        Annotation::Line => {
-                            // draw line
-                            let image = load_image(data);
-
-                            // draw line with first and last position, then clear the vector
-                            let p0 = (data.positions[0].0 , data.positions[0].1 );
-                            let p1 = (data.positions[data.positions.len()-1].0 ,
-                                      data.positions[data.positions.len()-1].1 );
-
-                            cropped_annotated_image = DynamicImage::from(
-                                draw_line_segment(&image,
-                                                  (((p0.0 - data.offsets.0) * data.scale_factors.0) as f32, ((p0.1 - data.offsets.1) * data.scale_factors.1) as f32),
-                                                  (((p1.0 - data.offsets.0) * data.scale_factors.0) as f32, ((p1.1 - data.offsets.1) * data.scale_factors.1) as f32),
-                                                  Rgba([data.color.0, data.color.1, data.color.2, data.color.3])));
-
-                        },
-                        Annotation::Cross => {
-                            // draw cross through two lines
-                            let image = load_image(data);
-
-                            let line0_p0 = (data.positions[0].0 - data.offsets.0, data.positions[0].1 - data.offsets.1);
-                            let line0_p1 = (data.positions[data.positions.len()-1].0 - data.offsets.0,
-                                            data.positions[data.positions.len()-1].1 - data.offsets.1);
-
-                            // draw line with first and last position, then clear the vector
-                            cropped_annotated_image = DynamicImage::from(
-                                draw_line_segment(&image,
-                                                  ((line0_p0.0 * data.scale_factors.0) as f32, (line0_p0.1 * data.scale_factors.1) as f32),
-                                                  ((line0_p1.0 * data.scale_factors.0) as f32, (line0_p1.1 * data.scale_factors.1) as f32),
-                                                  Rgba([data.color.0, data.color.1, data.color.2, data.color.3])));
-
-                            let line1_p0 = (data.positions[0].0 - data.offsets.0, data.positions[data.positions.len()-1].1 - data.offsets.1);
-                            let line1_p1 = (data.positions[data.positions.len()-1].0 - data.offsets.0,data.positions[0].1 - data.offsets.1);
-
-                            // draw line with first and last position, then clear the vector
-                            cropped_annotated_image = DynamicImage::from(
-                                draw_line_segment(&cropped_annotated_image,
-                                                  ((line1_p0.0 * data.scale_factors.0) as f32, (line1_p0.1 * data.scale_factors.1) as f32),
-                                                  ((line1_p1.0 * data.scale_factors.0) as f32, (line1_p1.1 * data.scale_factors.1) as f32),
-                                                  Rgba([data.color.0, data.color.1, data.color.2, data.color.3])));
-
                         },
-                        Annotation::Rectangle => {
-                            // draw rectangle
-                            let image = load_image(data);
-
-                            let rectangle = imageproc::rect::Rect::at(((min_x as f64 - data.offsets.0) * data.scale_factors.0) as i32, ((min_y as f64 - data.offsets.1) * data.scale_factors.1) as i32).of_size((((max_x as f64 - data.offsets.0) - (min_x as f64 - data.offsets.0)) * data.scale_factors.0 ) as u32,( ((max_y as f64 - data.offsets.1) - (min_y as f64 - data.offsets.1)) * data.scale_factors.1) as u32);
-                            cropped_annotated_image = DynamicImage::from(
-                                draw_hollow_rect(&image,rectangle,Rgba([data.color.0, data.color.1, data.color.2, data.color.3])));
-
-                        },
-                        Annotation::FreeLine => {
-                            // draw free line
-                            cropped_annotated_image = load_image(data);
-
-                            // draw line with first and last position, then clear the vector
-                            for pos_index in 0..(data.positions.len()-1) {
-                                let line_p0 = (data.positions[pos_index].0, data.positions[pos_index].1);
-                                let line_p1 = (data.positions[pos_index+1].0, data.positions[pos_index+1].1);
-
-                                cropped_annotated_image = DynamicImage::from(
-                                    draw_line_segment(&cropped_annotated_image,
-                                                       (((line_p0.0 - data.offsets.0) * data.scale_factors.0) as f32, ((line_p0.1 - data.offsets.1) * data.scale_factors.1) as f32),
-                                                      (((line_p1.0 - data.offsets.0) * data.scale_factors.0) as f32, ((line_p1.1 - data.offsets.1) * data.scale_factors.1) as f32),
-                                                      Rgba([data.color.0, data.color.1, data.color.2, data.color.3])));
-                            }
-
+                        Annotation::Text => {
+                            // done in add_text button handler in main_gui_building
                         },
-                        Annotation::Highlighter => {
-                            // draw highliter
-                            cropped_annotated_image = load_image(data);
-                            let mut transparent_image =  ImageBuffer::from_pixel(
-                                cropped_annotated_image.width(),
-                                cropped_annotated_image.height(),
-                                Rgba([0, 0, 0, 0]));
-
-                            // get the highliter points
-                            let result = compute_highlighter_points(data);
-
-                            match result {
-                                Some((rect_point1,rect_point2,rect_point3,rect_point4)) => {
-                                    let poly = &[imageproc::point::Point::new((rect_point1.x * data.scale_factors.0) as i32,(rect_point1.y * data.scale_factors.1) as i32),
-                                        imageproc::point::Point::new((rect_point2.x * data.scale_factors.0) as i32,(rect_point2.y * data.scale_factors.1) as i32),
-                                        imageproc::point::Point::new((rect_point3.x * data.scale_factors.0) as i32,(rect_point3.y * data.scale_factors.1) as i32),
-                                        imageproc::point::Point::new((rect_point4.x * data.scale_factors.0) as i32,(rect_point4.y * data.scale_factors.1) as i32)];
-
-                                    transparent_image = draw_polygon(&transparent_image, poly, Rgba([data.color.0, data.color.1, data.color.2, TRANSPARENCY]));
-
-                                    overlay(&mut cropped_annotated_image, &transparent_image, 0, 0);
+                        Annotation::Select => {
+                            // move the selected annotation by the drag
+                            if let Some(id) = data.selected {
+                                let start = to_image_point(data, data.positions[0]);
+                                let end = to_image_point(data, data.positions[data.positions.len()-1]);
+                                if start != end {
+                                    data.draft.move_by(id, end.0 - start.0, end.1 - start.1);
                                 }
-                                None => {}
                             }
-
-                        },
-                        Annotation::Arrow => {
-                            let image = load_image(data);
-
-                            let result = compute_arrow_points(data);
-                            match result {
-                                Some(((main_line_p0,main_line_p1),(arrow_l0_p0, arrow_l0_p1),(arrow_l1_p0, arrow_l1_p1))) => {
-                                    // draw line of arrow
-                                    cropped_annotated_image = DynamicImage::from(
-                                        draw_line_segment(&image,
-                                                          ((main_line_p0.x * data.scale_factors.0) as f32, (main_line_p0.y * data.scale_factors.1) as f32),
-                                                          ((main_line_p1.x * data.scale_factors.0) as f32, (main_line_p1.y * data.scale_factors.1) as f32),
-                                                          Rgba([data.color.0, data.color.1, data.color.2, data.color.3])));
-
-                                    // segmento 1 punta freccia
-                                    cropped_annotated_image = DynamicImage::from(
-                                        draw_line_segment(&cropped_annotated_image,
-                                                          ((arrow_l0_p0.x * data.scale_factors.0) as f32, (arrow_l0_p0.y * data.scale_factors.1) as f32),
-                                                          ((arrow_l0_p1.x * data.scale_factors.0) as f32, (arrow_l0_p1.y * data.scale_factors.1) as f32),
-                                                          Rgba([data.color.0, data.color.1, data.color.2, data.color.3])));
-                                    // segmento 2 punta freccia
-                                    cropped_annotated_image = DynamicImage::from(
-                                        draw_line_segment(&cropped_annotated_image,
-                                                          ((arrow_l1_p0.x * data.scale_factors.0) as f32, (arrow_l1_p0.y * data.scale_factors.1) as f32),
-                                                          ((arrow_l1_p1.x * data.scale_factors.0) as f32, (arrow_l1_p1.y * data.scale_factors.1) as f32),
-                                                          Rgba([data.color.0, data.color.1, data.color.2, data.color.3])));
-
-                                }
-                                None => {}
-                            }
-                        },
-                        Annotation::Text => {
-                            // done in add_text button handler in main_gui_building
                         },
+                        _ => {
+                            // add the shape to the draft document, it is rasterised only when shown or saved
+                            let object = make_annotation_object(data);
+                            data.draft.add(object);
+                        }
                     }
 
                     if data.annotation != Annotation::Text {
                         // clear the position
                         data.positions = vec![];
-                        //data.annotation = Annotation::None;
-                        // save the modified version of the image
-                        data.image_data_new = image_to_buffer(cropped_annotated_image);
                     }
 
                 } else {
-                    /*if dynamic_image.width() >= (screen.display_info.width as f64 * LIMIT_PROPORTION) as u32 || dynamic_image.height() >= (screen.display_info.height as f64 * LIMIT_PROPORTION) as u32 {
-                        data.scale_factor = SCALE_FACTOR;
-                    } else {
-                        data.scale_factor = 1.0;
-                    }*/
-
-                    // dynamic_image = dynamic_image.resize((dynamic_image.width() as f64 * data.scale_factor) as u32, (dynamic_image.height() as f64 * data.scale_factor) as u32, FilterType::Nearest);
-
-                    data.image_data_old = image_to_buffer(dynamic_image);
-
                     data.first_screen = false;
                 }
 
                 if data.annotation != Annotation::Text {
-                    if data.image_data_new.is_empty() {
+                    // the select tool stays active, also when nothing was changed
+                    if data.draft == data.document && data.annotation != Annotation::Select {
                         create_selection_window(ctx,data);
                     } else {
                         create_edit_window(ctx,data);
@@ -309,10 +255,10 @@ impl Widget<GrabData> for ScreenshotWidget {
 
     fn paint(&mut self, paint_ctx: &mut druid::PaintCtx, data: & GrabData, _env: &druid::Env) {
         // border color of the current selected color for all the paintings except the rectangle preview
-        let mut border_color = Color::rgb8(data.color.0, data.color.1, data.color.2); // White border color
+        let mut border_color = Color::rgb8(data.settings.color.0, data.settings.color.1, data.settings.color.2); // White border color
 
         match data.annotation {
-            Annotation::None | Annotation::Rectangle => {
+            Annotation::None | Annotation::Pixelate | Annotation::Blur | Annotation::Redact => {
                 let result = make_rectangle_from_points(data);
                 match result {
                     Some((x0,y0,x1,y1)) => {
@@ -322,59 +268,24 @@ impl Widget<GrabData> for ScreenshotWidget {
                         // Create a shape representing the rectangle in the widget's coordinate system
                         let rect_shape = Rect::new(x0 - data.offsets.0, y0 - data.offsets.1,
                                                    x1 - data.offsets.0, y1 - data.offsets.1);
-                        if data.annotation == Annotation::None {
-                            // override in white color, only for selection other cases the selected color at the beginning
+                        if matches!(data.annotation, Annotation::None | Annotation::Pixelate | Annotation::Blur) {
+                            // override in white color, only for selection and the redactions without a color, other cases the selected color at the beginning
                             border_color = Color::rgb8(255, 255, 255); // White border color
                         }
-                        paint_ctx.stroke(rect_shape, &border_color, BORDER_WIDTH);
+                        if data.annotation == Annotation::Redact {
+                            // the solid redaction is opaque
+                            paint_ctx.fill(rect_shape, &border_color);
+                        } else {
+                            paint_ctx.stroke(rect_shape, &border_color, BORDER_WIDTH);
+                        }
                     }
                     None => { }
                 }
             }
-            Annotation::Circle => {
-                let result = make_rectangle_from_points(data);
-                match result {
-                    Some((min_x,min_y,max_x,max_y)) => {
-                        // compute the center and the radius
-                        let (center_x,center_y) = compute_circle_center_radius(data,min_x as i32, min_y as i32,max_x as i32,max_y as i32);
-                        let radius = ((((max_x - min_x)).powi(2) + ((max_y - min_y)).powi(2))).sqrt()/ 2.0;
-
-                        // Create a shape representing the circle in the widget's coordinate system
-                        let circle_shape = Circle::new((center_x,center_y),radius);
-
-                        paint_ctx.stroke(circle_shape, &border_color, BORDER_WIDTH);
-                    }
-                    None => {}
-                }
-            }
-            Annotation::Line => {
+            Annotation::Circle | Annotation::Rectangle | Annotation::Line | Annotation::Cross | Annotation::Arrow
+            | Annotation::Ellipse | Annotation::RoundedRectangle => {
                 if !data.positions.is_empty() {
-                    let p0 = (data.positions[0].0 - data.offsets.0, data.positions[0].1 - data.offsets.1);
-                    let p1 = (data.positions[data.positions.len()-1].0 - data.offsets.0,
-                              data.positions[data.positions.len()-1].1 - data.offsets.1);
-
-                    let line_shape = Line::new(p0,p1);
-
-                    // Create a line in the widget's coordinate system
-                    paint_ctx.stroke(line_shape, &border_color, BORDER_WIDTH);
-                }
-            }
-            Annotation::Cross => {
-                if !data.positions.is_empty() {
-                    let line0_p0 = (data.positions[0].0 - data.offsets.0, data.positions[0].1 - data.offsets.1);
-                    let line0_p1 = (data.positions[data.positions.len()-1].0 - data.offsets.0,
-                                    data.positions[data.positions.len()-1].1 - data.offsets.1);
-
-                    let line0_shape = Line::new(line0_p0,line0_p1);
-
-                    let line1_p0 = (data.positions[0].0 - data.offsets.0, data.positions[data.positions.len()-1].1 - data.offsets.1);
-                    let line1_p1 = (data.positions[data.positions.len()-1].0 - data.offsets.0,data.positions[0].1 - data.offsets.1);
-
-                    let line1_shape = Line::new(line1_p0,line1_p1);
-
-                    // Create a cross in the widget's coordinate system
-                    paint_ctx.stroke(line0_shape, &border_color, BORDER_WIDTH);
-                    paint_ctx.stroke(line1_shape, &border_color, BORDER_WIDTH);
+                    paint_shape(paint_ctx, data, &make_annotation_object(data));
                 }
             }
             Annotation::FreeLine => {
@@ -401,45 +312,50 @@ impl Widget<GrabData> for ScreenshotWidget {
                         path.line_to(rect_point3);
                         path.line_to(rect_point4);
 
-                        border_color = Color::rgba8(data.color.0, data.color.1, data.color.2, TRANSPARENCY);
+                        border_color = Color::rgba8(data.settings.color.0, data.settings.color.1, data.settings.color.2, TRANSPARENCY);
 
                         paint_ctx.fill(path, &border_color);
                     }
                     None => {}
                 }
             }
-            Annotation::Arrow => {
-                let result = compute_arrow_points(data);
-                match result {
-                    Some(((main_line_p0,main_line_p1),(arrow_l0_p0, arrow_l0_p1),(arrow_l1_p0, arrow_l1_p1))) => {
-                        let line_shape = Line::new(main_line_p0,main_line_p1);
-                        // Create a line in the widget's coordinate system
-                        paint_ctx.stroke(line_shape, &border_color, BORDER_WIDTH);
-
-                        // segmento 1 punta freccia
-                        let punta1_shape = Line::new(arrow_l0_p0, arrow_l0_p1);
-                        // Create a line in the widget's coordinate system
-                        paint_ctx.stroke(punta1_shape, &border_color, BORDER_WIDTH);
-
-                        // segmento 2 punta freccia
-                        let punta2_shape = Line::new(arrow_l1_p0, arrow_l1_p1);
-                        // Create a line in the widget's coordinate system
-                        paint_ctx.stroke(punta2_shape, &border_color, BORDER_WIDTH);
-                    }
-                    None => {}
-                }
-            }
             Annotation::Text => {
                 if !data.positions.is_empty() {
                     // take the only point to draw the text line from it
                     // the last point if we click many times, so len-1
                     let (min_x,min_y) = (data.positions[data.positions.len()-1].0 - data.offsets.0,
                                          data.positions[data.positions.len()-1].1 - data.offsets.1);
-                    let line_shape = Line::new((min_x,min_y),(min_x, min_y + data.text_size));
+                    let line_shape = Line::new((min_x,min_y),(min_x, min_y + data.settings.text_size));
 
                     paint_ctx.stroke(line_shape, &border_color, BORDER_WIDTH);
                 }
             }
+            Annotation::Select => {
+                // frame of the selected annotation, following the drag
+                if let Some(object) = data.selected.and_then(|id| data.draft.get(id)) {
+                    let (min_x, min_y, max_x, max_y) = object.bounds();
+                    let (x0, y0) = to_widget_point(data, (min_x, min_y));
+                    let (x1, y1) = to_widget_point(data, (max_x, max_y));
+                    let (dx, dy) = match (data.positions.first(), data.positions.last()) {
+                        (Some(first), Some(last)) => (last.0 - first.0, last.1 - first.1),
+                        _ => (0.0, 0.0),
+                    };
+                    let frame = Rect::new(x0 + dx, y0 + dy, x1 + dx, y1 + dy).inflate(SELECT_TOLERANCE, SELECT_TOLERANCE);
+                    // visible over both dark and light content
+                    paint_ctx.stroke(frame, &Color::BLACK, BORDER_WIDTH * 3.0);
+                    paint_ctx.stroke(frame, &Color::WHITE, BORDER_WIDTH);
+                }
+            }
+            Annotation::Step => {
+                if !data.positions.is_empty() {
+                    // the badge under the mouse, its size converted from pixels of the capture
+                    let center = (data.positions[data.positions.len()-1].0 - data.offsets.0,
+                                  data.positions[data.positions.len()-1].1 - data.offsets.1);
+                    let circle_shape = Circle::new(center, data.settings.text_size * STEP_RADIUS_FACTOR / data.scale_factors.0);
+
+                    paint_ctx.fill(circle_shape, &border_color);
+                }
+            }
         }
     }
 }
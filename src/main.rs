@@ -4,18 +4,34 @@ mod constants;
 mod main_gui_building;
 mod handlers;
 mod utilities;
+mod capture;
+mod cli;
+mod document;
+mod history;
+mod project;
+mod export;
+mod naming;
+mod settings;
+mod error;
+mod global_hotkey;
+mod hotkey;
+mod timelapse;
+mod recording;
 
-use std::fs::File;
-use std::io::Write;
-use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 use druid::Lens;
 use druid::{Data, WindowDesc, AppLauncher, PlatformError};
 use serde::{Serialize,Deserialize};
-use serde_json::from_reader;
 use crate::main_gui_building::build_ui;
 use crate::handlers::Delegate;
 use constants::{MAIN_WINDOW_WIDTH,MAIN_WINDOW_HEIGHT};
-use crate::constants::{APP_NAME, INIT_FILE};
+use crate::constants::APP_NAME;
+use crate::cli::{run_cli, take_config_option};
+use crate::document::Document;
+use crate::history::History;
+use crate::hotkey::{Action, Hotkey};
+use crate::settings::{set_config_path, Settings};
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 enum Annotation {
@@ -27,66 +43,160 @@ enum Annotation {
     FreeLine,
     Highlighter,
     Arrow,
-    Text
+    Text,
+    // picks an annotation of the draft to move, restyle, reorder or delete it
+    Select,
+    // bounded by the drag box
+    Ellipse,
+    RoundedRectangle,
+    // numbered circle placed with a click, the number follows the order of the steps
+    Step,
+    // redaction of the dragged rectangle, the pixels under it are replaced in every export
+    Pixelate,
+    Blur,
+    Redact
 }
 
-#[derive(Clone, Data, Serialize, Deserialize, Debug, Lens)]
+impl Annotation {
+    fn is_redaction(&self) -> bool {
+        matches!(self, Annotation::Pixelate | Annotation::Blur | Annotation::Redact)
+    }
+
+    // tools with a stroke width and line style
+    fn is_shape(&self) -> bool {
+        matches!(self, Annotation::Circle | Annotation::Rectangle | Annotation::Line | Annotation::Cross | Annotation::Arrow
+            | Annotation::Ellipse | Annotation::RoundedRectangle)
+    }
+
+    // shapes that can be filled
+    fn is_closed_shape(&self) -> bool {
+        matches!(self, Annotation::Circle | Annotation::Rectangle | Annotation::Ellipse | Annotation::RoundedRectangle)
+    }
+
+    // Shift makes the drag box a square: a circle for the ellipse, a square for the rectangles
+    fn can_constrain(&self) -> bool {
+        matches!(self, Annotation::Rectangle | Annotation::Ellipse | Annotation::RoundedRectangle)
+    }
+}
+
+// runtime state of the application, the persisted preferences are in settings
+#[derive(Clone, Data, Debug, Lens)]
 pub struct GrabData {
-    screenshot_number: u32,
+    settings: Settings,
     #[data(ignore)]
     image_data_old: Vec<u8>,
+    // approved annotations and crop, and the copy being modified in the edit window
     #[data(ignore)]
-    image_data_new: Vec<u8>,
+    document: Document,
     #[data(ignore)]
-    save_path: Box<Path>,
-    save_format: String,
+    draft: Document,
+    #[data(ignore)]
+    history: History,
     press: bool,
     first_screen: bool,
     scale_factors: (f64,f64),
     image_size: (f64,f64),
     #[data(ignore)]
     positions: Vec<(f64,f64)>,
-    offsets: (f64,f64),
-    #[data(ignore)]
-    hotkey: Vec<String>,
+    // id of the annotation picked with the select tool
     #[data(ignore)]
-    hotkey_new: Vec<String>,
+    selected: Option<u32>,
+    offsets: (f64,f64),
+    // hotkey recorded in the settings window, not yet saved
     #[data(ignore)]
-    hotkey_pressed:  Vec<String>,
-    set_hot_key: bool,
-    delay: f64,
+    hotkey_new: Option<Hotkey>,
+    // action whose hotkey is being recorded in the hotkeys window
+    editing_hotkey: Option<Action>,
     input_hotkey_error: (bool,String),
     trigger_ui: bool,
     #[data(ignore)]
     annotation: Annotation,
-    color: (u8,u8,u8,u8),
     text_annotation: String,
-    text_size : f64,
-    highlighter_width: f64,
-    timer_requested: bool,
-    image_copied: bool
+    // seconds left in the countdown window
+    countdown: f64,
+    image_copied: bool,
+    // desktop rect (physical pixels) captured without the overlay once the windows are hidden
+    #[data(ignore)]
+    pending_capture: Option<(i32,i32,u32,u32)>,
+    // name typed in the settings window to create a profile
+    new_profile_name: String,
+    // name typed in the presets window to save the last region
+    new_preset_name: String,
+    // set to stop the running timelapse
+    #[data(ignore)]
+    timelapse_stop: Option<Arc<AtomicBool>>,
+    timelapse_running: bool,
+    timelapse_status: String,
+    // set to stop the animated recording, the captured frames are encoded
+    #[data(ignore)]
+    recording_stop: Option<Arc<AtomicBool>>,
+    recording_running: bool,
+    recording_status: String,
 }
 
-fn main() -> Result<(), PlatformError> {
-    // if settings does not exists, create it from the init hardcoded file
-    let result = File::open("settings.json");
-    let data: GrabData;
-    match result  {
-        Ok(settings) => {
-            // file exists, use it
-            data = from_reader(settings).unwrap();
+impl GrabData {
+    fn new(settings: Settings) -> Self {
+        GrabData {
+            settings,
+            image_data_old: vec![],
+            document: Document::default(),
+            draft: Document::default(),
+            history: History::default(),
+            press: false,
+            first_screen: true,
+            scale_factors: (1.0, 1.0),
+            image_size: (0.0, 0.0),
+            positions: vec![],
+            selected: None,
+            offsets: (0.0, 0.0),
+            hotkey_new: None,
+            editing_hotkey: None,
+            input_hotkey_error: (false, "Invalid Input: Wrong Hotkey.".to_string()),
+            trigger_ui: false,
+            annotation: Annotation::None,
+            text_annotation: String::new(),
+            countdown: 0.0,
+            image_copied: false,
+            pending_capture: None,
+            new_profile_name: String::new(),
+            new_preset_name: String::new(),
+            timelapse_stop: None,
+            timelapse_running: false,
+            timelapse_status: String::new(),
+            recording_stop: None,
+            recording_running: false,
+            recording_status: String::new(),
         }
-        Err(_) => {
-            // file not exists, initialize data and create settings.json from init.json file
-            let mut settings = File::create("settings.json").unwrap();
-            data = serde_json::from_slice(INIT_FILE).unwrap();
-            settings.write_all(serde_json::to_string(&data).unwrap().as_bytes()).unwrap();
+    }
+}
+
+fn main() -> Result<(), PlatformError> {
+    // with arguments run the headless command line mode, without opening any window
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    // the settings file can be chosen both for the command line and the graphical mode
+    match take_config_option(&mut args) {
+        Ok(Some(path)) => set_config_path(path),
+        Ok(None) => {}
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            std::process::exit(2);
         }
     }
+    if !args.is_empty() {
+        std::process::exit(run_cli(&args));
+    }
+
+    let data = GrabData::new(Settings::load());
+    global_hotkey::init();
 
     let main_window = WindowDesc::new(build_ui())
         .title(APP_NAME)
         .window_size((MAIN_WINDOW_WIDTH, MAIN_WINDOW_HEIGHT)).resizable(false);
 
-    AppLauncher::with_window(main_window).delegate(Delegate).launch(data)
+    let launcher = AppLauncher::with_window(main_window).delegate(Delegate::default());
+    // without the global hotkey, it still works when a window of the application has the focus
+    if let Err(error) = global_hotkey::start(launcher.get_external_handle(), &data.settings.hotkeys) {
+        eprintln!("Global hotkey not available, it works only in the application windows: {}", error);
+    }
+    launcher.launch(data)
 }
@@ -1,26 +1,40 @@
 use std::fs;
-use std::borrow::Cow;
-use std::fs::File;
-use druid::widget::{Button, Flex, Image, Label, SizedBox, Spinner, TextBox, ZStack};
-use druid::{Color, Env, EventCtx, FontDescriptor, ImageBuf, Point, Size, Widget, WidgetExt, WindowDesc};
+use std::sync::atomic::Ordering;
+use druid::widget::{Button, Checkbox, Either, Flex, Image, Label, LineBreaking, RadioGroup, SizedBox, TextBox, ZStack};
+use druid::{Color, Env, EventCtx, FontDescriptor, ImageBuf, Key, LensExt, Point, Size, Widget, WidgetExt, WindowDesc};
+use druid::text::ParseFormatter;
 use druid::piet::ImageFormat;
 use druid_widget_nursery::DropdownSelect;
-use image::{DynamicImage, EncodableLayout, load_from_memory_with_format, Rgba};
-use imageproc::drawing::draw_text;
-use serde_json::{from_reader, to_writer};
-use crate::constants::{BUTTON_HEIGHT, BUTTON_WIDTH, MAIN_WINDOW_WIDTH, MAIN_WINDOW_HEIGHT, OPACITY, WINDOW_MULTIPLIER, APP_NAME};
+use crate::constants::{BUTTON_HEIGHT, BUTTON_WIDTH, MAIN_WINDOW_WIDTH, MAIN_WINDOW_HEIGHT, OPACITY, WINDOW_MULTIPLIER, APP_NAME, PROJECT_EXTENSION, BASE_DPI, COUNTDOWN_WINDOW_WIDTH, COUNTDOWN_WINDOW_HEIGHT, MAX_CORNER_RADIUS, MAX_DELAY, MAX_RECORD_DURATION, MAX_RECORD_FPS, MAX_REDACT_STRENGTH, MAX_STROKE_WIDTH, MIN_GIF_COLORS, MIN_REDACT_STRENGTH, MIN_STROKE_WIDTH, MIN_TIMELAPSE_INTERVAL};
 use crate::{Annotation, GrabData};
-use crate::utilities::{compute_screening_coordinates, image_to_buffer, load_image, resize_image};
+use crate::utilities::{capture_file_name, compute_overlay_coordinates, compute_screening_coordinates, copy_to_clipboard, current_monitor, export_capture, first_monitor_rect, flatten_image, last_region, make_annotation_object, preset_from_rect, preset_rect, render_image, resize_image, screen_all, tool_width};
+use crate::capture::default_backend;
+use crate::project::{load_project, save_project};
+use crate::export::images_to_pdf;
+use crate::naming::unique_path;
+use image::DynamicImage;
 use crate::image_screen::ScreenshotWidget;
-use crate::handlers::Enter;
+use crate::handlers::{Countdown, Enter};
+use crate::global_hotkey;
+use crate::hotkey::{check_hotkey, Action};
+use crate::settings::{shape_style_lens, Settings, ShapeStyle};
+use crate::document::LineStyle;
+use crate::recording::{self, AnimationFormat, RecordingJob};
+use crate::timelapse::{self, TimelapseJob};
+use crate::error::{Context, Error, Result};
 use crate::utilities::reset_data;
 use native_dialog::{FileDialog};
-use rusttype::Font;
+
+// annotation color of the settings, for the widgets painted with it
+const CURRENT_COLOR: Key<Color> = Key::new("pds_project.current-color");
 
 pub fn start_screening(ctx: &mut EventCtx, data: &mut GrabData) {
     // reset completely data in order to take a screenshot from scratch
     reset_data(data);
-    let (x_min,y_min,x_max,y_max) = compute_screening_coordinates(data);
+    let (x_min,y_min,x_max,y_max) = match compute_overlay_coordinates(&*default_backend(), data) {
+        Ok(coordinates) => coordinates,
+        Err(message) => return create_error_window(ctx, data, &Error::Capture(message)),
+    };
     ctx.window().close();
     ctx.new_window(
         WindowDesc::new(
@@ -34,33 +48,355 @@ pub fn start_screening(ctx: &mut EventCtx, data: &mut GrabData) {
 
 }
 
+// run the action bound to a hotkey
+pub fn run_action(ctx: &mut EventCtx, data: &mut GrabData, action: Action) {
+    let has_capture = !data.image_data_old.is_empty() && !data.first_screen;
+    let backend = default_backend();
+    let rect = match action {
+        Action::CaptureRegion => return start_screening(ctx, data),
+        Action::DelayedCapture => return start_timer(ctx, data),
+        Action::CopyToClipboard if has_capture => return copy_capture(ctx, data),
+        Action::Save if has_capture => return save_and_close(ctx, data),
+        Action::CopyToClipboard | Action::Save => return,
+        Action::CaptureDesktop => compute_screening_coordinates(&*backend)
+            .map(|(x_min, y_min, x_max, y_max)| (x_min, y_min, (x_max - x_min) as u32, (y_max - y_min) as u32)),
+        Action::CaptureMonitor => current_monitor(ctx, &*backend, data)
+            .map(|monitor| (monitor.x, monitor.y, monitor.width, monitor.height)),
+        Action::RepeatRegion => last_region(&*backend, data),
+    };
+    match rect {
+        Ok(rect) => hide_and_capture(ctx, data, rect),
+        Err(message) => create_error_window(ctx, data, &Error::Capture(message)),
+    }
+}
+
+// close the windows, the capture is taken by the Enter controller of an invisible window when they are gone
+fn hide_and_capture(ctx: &mut EventCtx, data: &mut GrabData, rect: (i32,i32,u32,u32)) {
+    data.pending_capture = Some(rect);
+    ctx.window().close();
+    ctx.new_window(WindowDesc::new(SizedBox::<GrabData>::empty().controller(Enter))
+        .title(APP_NAME)
+        .show_titlebar(false)
+        .transparent(true)
+        .resizable(false)
+        .window_size((1.0, 1.0)));
+}
+
+// capture a rect of the desktop (physical pixels) without the selection overlay, and show it
+pub fn capture_rect_directly(ctx: &mut EventCtx, data: &mut GrabData, (x, y, width, height): (i32,i32,u32,u32)) {
+    reset_data(data);
+    match screen_all(x, y, width, height, data) {
+        Ok(()) => {
+            data.first_screen = false;
+            create_selection_window(ctx, data);
+        }
+        Err(error) => create_error_window(ctx, data, &error),
+    }
+}
+
 fn create_monitor_buttons() -> Flex<GrabData> {
-    //let screens = Screen::all().unwrap();
-    let monitor_buttons = Flex::row();
-        let btn = Button::new( "📷 Take a Screenshot ".to_owned() ).on_click(
+    let mut monitor_buttons = Flex::column();
+    let all_btn = Button::new( "📷 Take a Screenshot (all monitors)".to_owned() ).on_click(
+        move |_ctx, _data: &mut GrabData ,_env| {
+            _data.settings.selected_monitor = None;
+            start_screening(_ctx, _data);
+        });
+    monitor_buttons.add_child(all_btn);
+
+    // one button for each detected monitor, the overlay will cover only that one
+    let monitors = default_backend().monitors().unwrap_or_default();
+    for (index, monitor) in monitors.iter().enumerate() {
+        let btn = Button::new(format!("🖥 {} ({}x{} at {},{})", monitor.name, monitor.width, monitor.height, monitor.x, monitor.y)).on_click(
             move |_ctx, _data: &mut GrabData ,_env| {
+                _data.settings.selected_monitor = Some(index);
                 start_screening(_ctx, _data);
             });
-        monitor_buttons.with_child(btn)
+        monitor_buttons.add_default_spacer();
+        monitor_buttons.add_child(btn);
+    }
+    monitor_buttons
 }
 
-fn create_output_format_dropdown() -> Flex<GrabData> {
-    let file = File::open("settings.json").unwrap();
-    let data: GrabData = from_reader(file).unwrap();
+// the presets window is built again when a preset is added or deleted, to show the list
+pub fn open_presets_window(ctx: &mut EventCtx, data: &GrabData) {
+    ctx.window().close();
+    ctx.new_window(WindowDesc::new(presets_window(&data.settings)).title("Region presets")
+        .window_size((MAIN_WINDOW_WIDTH, MAIN_WINDOW_HEIGHT)).resizable(false));
+}
+
+fn presets_window(settings: &Settings) -> impl Widget<GrabData> {
+    let mut ui_column = Flex::column();
+    ui_column.add_default_spacer();
+    ui_column.add_child(Label::new("REGION PRESETS"));
+    ui_column.add_default_spacer();
+    if settings.region_presets.is_empty() {
+        ui_column.add_child(Label::new("No preset yet: select a region, then save it here with a name").with_text_size(11.0));
+    }
+    for preset in settings.region_presets.iter() {
+        let capture_preset = preset.clone();
+        let capture_button = Button::new(format!("▣ {} ({}x{} at {},{})", preset.name, preset.width, preset.height, preset.x, preset.y))
+            .on_click(move |ctx, data: &mut GrabData, _env| {
+                // the monitors may have changed since the preset was saved
+                match preset_rect(&*default_backend(), &capture_preset) {
+                    Ok(rect) => hide_and_capture(ctx, data, rect),
+                    Err(message) => create_error_window(ctx, data, &Error::Capture(message)),
+                }
+            });
+        let name = preset.name.clone();
+        let delete_button = Button::new("✖").on_click(move |ctx, data: &mut GrabData, _env| {
+            data.settings.delete_preset(&name);
+            if save_settings(ctx, data) {
+                open_presets_window(ctx, data);
+            }
+        });
+        ui_column.add_child(Flex::row().with_child(capture_button).with_default_spacer().with_child(delete_button));
+        ui_column.add_spacer(4.0);
+    }
+
+    let new_preset_box = TextBox::new().with_placeholder("preset name").lens(GrabData::new_preset_name);
+    let save_button = Button::new("Save the last region").on_click(|ctx, data: &mut GrabData, _env| {
+        let backend = default_backend();
+        let preset = last_region(&*backend, data)
+            .and_then(|rect| preset_from_rect(&*backend, &data.new_preset_name, rect))
+            .and_then(|preset| data.settings.add_preset(preset));
+        match preset {
+            Ok(()) => {
+                data.new_preset_name.clear();
+                if save_settings(ctx, data) {
+                    open_presets_window(ctx, data);
+                }
+            }
+            Err(message) => create_error_window(ctx, data, &Error::Message(message)),
+        }
+    });
+    let back_button = Button::new("Back").on_click(|ctx, _data: &mut GrabData, _env| {
+        back_to_main(ctx);
+    });
+
+    ui_column.add_default_spacer();
+    ui_column.add_child(Flex::row()
+        .with_child(new_preset_box.fix_width(180.0))
+        .with_default_spacer()
+        .with_child(save_button));
+    ui_column.add_default_spacer();
+    ui_column.add_child(back_button);
+    ui_column.controller(Enter)
+}
 
+// buttons to choose what the timelapse or the recording captures: the last region or a whole monitor
+fn create_source_buttons(icon: &str, start: fn(&mut EventCtx, &mut GrabData, (i32,i32,u32,u32), String)) -> Flex<GrabData> {
+    let mut sources = Flex::column();
+    let region_button = Button::new(format!("{} Last region", icon)).on_click(move |ctx, data: &mut GrabData, _env| {
+        match last_region(&*default_backend(), data) {
+            Ok(rect) => start(ctx, data, rect, "region".to_string()),
+            Err(message) => create_error_window(ctx, data, &Error::Capture(message)),
+        }
+    });
+    sources.add_child(region_button);
+    for monitor in default_backend().monitors().unwrap_or_default() {
+        let button = Button::new(format!("{} {} ({}x{} at {},{})", icon, monitor.name, monitor.width, monitor.height, monitor.x, monitor.y))
+            .on_click(move |ctx, data: &mut GrabData, _env| {
+                start(ctx, data, (monitor.x, monitor.y, monitor.width, monitor.height), monitor.name.clone());
+            });
+        sources.add_spacer(4.0);
+        sources.add_child(button);
+    }
+    sources
+}
+
+// the timelapse keeps running in its thread when this window is closed, it can be stopped when opened again
+pub fn open_timelapse_window(ctx: &mut EventCtx) {
+    ctx.window().close();
+    ctx.new_window(WindowDesc::new(timelapse_window()).title("Timelapse")
+        .window_size((MAIN_WINDOW_WIDTH, MAIN_WINDOW_HEIGHT)).resizable(false));
+}
+
+fn start_timelapse(ctx: &mut EventCtx, data: &mut GrabData, rect: (i32,i32,u32,u32), source: String) {
+    // vector formats have no frames to encode, each shot is saved as png
+    let format = match data.settings.save_format.as_str() {
+        "svg" | "pdf" => "png".to_string(),
+        format => format.to_string(),
+    };
+    let job = TimelapseJob {
+        rect,
+        source,
+        interval: data.settings.timelapse_interval.max(MIN_TIMELAPSE_INTERVAL),
+        shots: data.settings.timelapse_shots,
+        folder: data.settings.save_path.to_path_buf(),
+        template: data.settings.timelapse_template.clone(),
+        counter: data.settings.screenshot_number,
+        format,
+        quality: data.settings.quality as u8,
+        lossless: data.settings.lossless,
+    };
+    data.settings.screenshot_number = data.settings.screenshot_number.checked_add(1).unwrap_or(0);
+    data.timelapse_stop = Some(timelapse::start(ctx.get_external_handle(), job));
+    data.timelapse_running = true;
+    data.timelapse_status = "Timelapse started".to_string();
+}
+
+fn timelapse_window() -> impl Widget<GrabData> {
+    let mut ui_column = Flex::column();
+    ui_column.add_default_spacer();
+    ui_column.add_child(Label::new("TIMELAPSE"));
+    ui_column.add_default_spacer();
+
+    let interval_box = TextBox::new().with_formatter(ParseFormatter::new()).update_data_while_editing(true)
+        .lens(GrabData::settings.then(Settings::timelapse_interval));
+    let shots_box = TextBox::new().with_formatter(ParseFormatter::new()).update_data_while_editing(true)
+        .lens(GrabData::settings.then(Settings::timelapse_shots));
+    ui_column.add_child(Flex::row()
+        .with_child(Label::new("Every"))
+        .with_default_spacer()
+        .with_child(interval_box.fix_width(60.0))
+        .with_default_spacer()
+        .with_child(Label::new("seconds,"))
+        .with_default_spacer()
+        .with_child(shots_box.fix_width(60.0))
+        .with_default_spacer()
+        .with_child(Label::new("shots (0 until stopped)")));
+    ui_column.add_default_spacer();
+    ui_column.add_child(Flex::row()
+        .with_child(Label::new("File name:"))
+        .with_default_spacer()
+        .with_child(TextBox::new().lens(GrabData::settings.then(Settings::timelapse_template)).fix_width(220.0)));
+    ui_column.add_child(Label::new("{frame} is the number of the shot, {counter} the number of the timelapse").with_text_size(11.0));
+    ui_column.add_default_spacer();
+
+    let sources = create_source_buttons("▶", start_timelapse);
+    let stop_button = Button::new("■ Stop").on_click(|_ctx, data: &mut GrabData, _env| {
+        // the thread sends the finished progress when it sees the flag
+        if let Some(stop) = &data.timelapse_stop {
+            stop.store(true, Ordering::SeqCst);
+            data.timelapse_status = "Stopping..".to_string();
+        }
+    });
+    ui_column.add_child(Either::new(|data: &GrabData, _env| data.timelapse_running, stop_button, sources));
+    ui_column.add_default_spacer();
+    ui_column.add_child(Label::dynamic(|data: &GrabData, _env| data.timelapse_status.clone())
+        .with_line_break_mode(LineBreaking::WordWrap).with_text_size(11.0));
+    ui_column.add_default_spacer();
+
+    let back_button = Button::new("Back").on_click(|ctx, _data: &mut GrabData, _env| {
+        back_to_main(ctx);
+    });
+    ui_column.add_child(back_button);
+    ui_column.controller(Enter)
+}
+
+// as the timelapse, the recording goes on with the window closed
+pub fn open_recording_window(ctx: &mut EventCtx) {
+    ctx.window().close();
+    ctx.new_window(WindowDesc::new(recording_window()).title("Record")
+        .window_size((MAIN_WINDOW_WIDTH, MAIN_WINDOW_HEIGHT)).resizable(false));
+}
+
+fn start_recording(ctx: &mut EventCtx, data: &mut GrabData, rect: (i32,i32,u32,u32), source: String) {
+    let settings = &data.settings;
+    let job = RecordingJob {
+        rect,
+        source,
+        fps: settings.record_fps.clamp(1.0, MAX_RECORD_FPS),
+        duration: settings.record_duration.clamp(1.0 / MAX_RECORD_FPS, MAX_RECORD_DURATION),
+        format: settings.record_format,
+        colors: settings.gif_colors.clamp(MIN_GIF_COLORS, 256),
+        dither: settings.gif_dither,
+        folder: settings.save_path.to_path_buf(),
+        template: settings.file_template.clone(),
+        counter: settings.screenshot_number,
+    };
+    match recording::start(ctx.get_external_handle(), job) {
+        Ok(stop) => {
+            data.settings.screenshot_number = data.settings.screenshot_number.checked_add(1).unwrap_or(0);
+            data.recording_stop = Some(stop);
+            data.recording_running = true;
+            data.recording_status = "Recording started".to_string();
+        }
+        Err(error) => create_error_window(ctx, data, &error),
+    }
+}
+
+fn recording_window() -> impl Widget<GrabData> {
+    let mut ui_column = Flex::column();
+    ui_column.add_default_spacer();
+    ui_column.add_child(Label::new("RECORD AN ANIMATION"));
+    ui_column.add_default_spacer();
+
+    let fps_box = TextBox::new().with_formatter(ParseFormatter::new()).update_data_while_editing(true)
+        .lens(GrabData::settings.then(Settings::record_fps));
+    let duration_box = TextBox::new().with_formatter(ParseFormatter::new()).update_data_while_editing(true)
+        .lens(GrabData::settings.then(Settings::record_duration));
+    ui_column.add_child(Flex::row()
+        .with_child(fps_box.fix_width(50.0))
+        .with_default_spacer()
+        .with_child(Label::new(format!("frames per second (1-{}) for", MAX_RECORD_FPS)))
+        .with_default_spacer()
+        .with_child(duration_box.fix_width(50.0))
+        .with_default_spacer()
+        .with_child(Label::new(format!("seconds (up to {})", MAX_RECORD_DURATION))));
+    ui_column.add_default_spacer();
+
+    let format_dropdown = DropdownSelect::new(vec![("GIF", AnimationFormat::Gif), ("APNG", AnimationFormat::Apng)])
+        .lens(GrabData::settings.then(Settings::record_format));
+    let colors_box = TextBox::new().with_formatter(ParseFormatter::new()).update_data_while_editing(true)
+        .lens(GrabData::settings.then(Settings::gif_colors));
+    // the palette options only apply to gif, apng keeps all the colors
+    let gif_options = Either::new(|data: &GrabData, _env| data.settings.record_format == AnimationFormat::Gif,
+        Flex::row()
+            .with_child(Label::new(format!("Colors ({}-256):", MIN_GIF_COLORS)))
+            .with_default_spacer()
+            .with_child(colors_box.fix_width(50.0))
+            .with_default_spacer()
+            .with_child(Checkbox::new("Dithering").lens(GrabData::settings.then(Settings::gif_dither))),
+        SizedBox::empty());
+    ui_column.add_child(Flex::row()
+        .with_child(Label::new("Format:"))
+        .with_default_spacer()
+        .with_child(format_dropdown)
+        .with_default_spacer()
+        .with_child(gif_options));
+    ui_column.add_child(Label::new("The file is named by the file name template and saved in the save folder").with_text_size(11.0));
+    ui_column.add_default_spacer();
+
+    let stop_button = Button::new("■ Stop").on_click(|_ctx, data: &mut GrabData, _env| {
+        // the frames captured so far are encoded and saved
+        if let Some(stop) = &data.recording_stop {
+            stop.store(true, Ordering::SeqCst);
+        }
+    });
+    ui_column.add_child(Either::new(|data: &GrabData, _env| data.recording_running, stop_button,
+                                    create_source_buttons("●", start_recording)));
+    ui_column.add_default_spacer();
+    ui_column.add_child(Label::dynamic(|data: &GrabData, _env| data.recording_status.clone())
+        .with_line_break_mode(LineBreaking::WordWrap).with_text_size(11.0));
+    ui_column.add_default_spacer();
+
+    let back_button = Button::new("Back").on_click(|ctx, _data: &mut GrabData, _env| {
+        back_to_main(ctx);
+    });
+    ui_column.add_child(back_button);
+    ui_column.controller(Enter)
+}
+
+fn create_output_format_dropdown(current_format: &str) -> Flex<GrabData> {
     let standard_formats = vec![
         ("png".to_string(), "png".to_string()),
         ("jpg".to_string(), "jpg".to_string()),
         ("jpeg".to_string(), "jpeg".to_string()),
         ("bmp".to_string(), "bmp".to_string()),
         ("tiff".to_string(), "tiff".to_string()),
-        ("gif".to_string(), "gif".to_string())
+        ("gif".to_string(), "gif".to_string()),
+        ("webp".to_string(), "webp".to_string()),
+        ("avif".to_string(), "avif".to_string()),
+        ("qoi".to_string(), "qoi".to_string()),
+        ("svg".to_string(), "svg".to_string()),
+        ("pdf".to_string(), "pdf".to_string())
     ];
 
-    let mut build_formats = vec![(data.save_format.clone(),data.save_format.clone())];
+    let mut build_formats = vec![(current_format.to_string(), current_format.to_string())];
 
     for format in standard_formats {
-        if format.0 != data.save_format {
+        if format.0 != current_format {
             build_formats.push(format);
         }
     }
@@ -72,186 +408,369 @@ fn create_output_format_dropdown() -> Flex<GrabData> {
     row_dropdown.add_default_spacer();
     row_dropdown.add_flex_child(
         DropdownSelect::new(build_formats)
-            .lens(GrabData::save_format),
+            .lens(GrabData::settings.then(Settings::save_format)),
         1.0
     );
     row_dropdown
 }
 
-pub fn settings_window() -> impl Widget<GrabData> {
+// window explaining what failed, the capture (if any) is kept and the user can go back to it
+pub fn create_error_window(ctx: &mut EventCtx, data: &mut GrabData, error: &Error) {
+    eprintln!("Error: {}", error);
+    let has_capture = !data.image_data_old.is_empty() && !data.first_screen;
+
+    let back_button = Button::new(if has_capture { "Back to the capture" } else { "Back" }).on_click(move |ctx, data: &mut GrabData, _env| {
+        if has_capture {
+            create_selection_window(ctx, data);
+        } else {
+            back_to_main(ctx);
+        }
+    });
+    let discard_button = Button::new("Discard the capture").on_click(|ctx, data: &mut GrabData, _env| {
+        reset_data(data);
+        back_to_main(ctx);
+    });
+
+    let mut buttons = Flex::row().with_child(back_button);
+    if has_capture {
+        buttons.add_default_spacer();
+        buttons.add_child(discard_button);
+    }
+
+    ctx.window().close();
+    ctx.new_window(WindowDesc::new(
+        Flex::column()
+            .with_child(Label::new("Something went wrong").with_font(FontDescriptor::new(Default::default()).with_size(20.0)))
+            .with_default_spacer()
+            .with_child(Label::new(error.to_string()).with_line_break_mode(LineBreaking::WordWrap).fix_width(MAIN_WINDOW_WIDTH - 40.0))
+            .with_default_spacer()
+            .with_child(buttons)
+            .padding(20.0))
+        .title(APP_NAME)
+        .resizable(false)
+        .window_size((MAIN_WINDOW_WIDTH, MAIN_WINDOW_HEIGHT / 2.0)));
+}
+
+// close the window and show the main one again
+pub fn back_to_main(ctx: &mut EventCtx) {
+    ctx.window().close();
+    ctx.new_window(WindowDesc::new(build_ui())
+        .title(APP_NAME)
+        .resizable(false)
+        .window_size((MAIN_WINDOW_WIDTH, MAIN_WINDOW_HEIGHT)));
+}
+
+// write the settings as soon as they change, not only when a window is closed: false (with the error
+// window shown) if they could not be saved
+pub fn save_settings(ctx: &mut EventCtx, data: &mut GrabData) -> bool {
+    match data.settings.save() {
+        Ok(()) => true,
+        Err(message) => {
+            create_error_window(ctx, data, &Error::Message(message));
+            false
+        }
+    }
+}
+
+// the settings window is built again when the profile changes, to show its values
+pub fn open_settings_window(ctx: &mut EventCtx, data: &GrabData) {
+    ctx.window().close();
+    ctx.new_window(WindowDesc::new(settings_window(&data.settings)).title("Settings").window_size((600.0,620.0)).resizable(false));
+}
+
+fn create_profile_settings(settings: &Settings) -> Flex<GrabData> {
+    let mut profile_row = Flex::row();
+    profile_row.add_child(Label::new("Profile:"));
+    for name in settings.profile_names() {
+        let label = if name == settings.profile { format!("● {}", name) } else { name.clone() };
+        profile_row.add_default_spacer();
+        profile_row.add_child(Button::new(label).on_click(move |ctx, data: &mut GrabData, _env| {
+            if data.settings.switch_profile(&name).is_ok() {
+                global_hotkey::update(&data.settings.hotkeys);
+                if save_settings(ctx, data) {
+                    open_settings_window(ctx, data);
+                }
+            }
+        }));
+    }
+
+    let new_profile_box = TextBox::new().with_placeholder("new profile").lens(GrabData::new_profile_name);
+    let add_button = Button::new("Add").on_click(|ctx, data: &mut GrabData, _env| {
+        // the new profile starts from the values in use
+        let name = data.new_profile_name.clone();
+        if data.settings.switch_profile(&name).is_ok() {
+            data.new_profile_name.clear();
+            if save_settings(ctx, data) {
+                open_settings_window(ctx, data);
+            }
+        }
+    });
+    let delete_button = Button::new("Delete").on_click(|ctx, data: &mut GrabData, _env| {
+        // delete the active profile, activating another one
+        let current = data.settings.profile.clone();
+        if let Some(other) = data.settings.profile_names().into_iter().find(|name| *name != current) {
+            let _ = data.settings.switch_profile(&other);
+            let _ = data.settings.delete_profile(&current);
+            global_hotkey::update(&data.settings.hotkeys);
+            if save_settings(ctx, data) {
+                open_settings_window(ctx, data);
+            }
+        }
+    });
+
+    Flex::column()
+        .with_child(profile_row)
+        .with_child(Flex::row()
+            .with_child(new_profile_box.fix_width(150.0))
+            .with_default_spacer()
+            .with_child(add_button)
+            .with_default_spacer()
+            .with_child(delete_button))
+}
+
+pub fn settings_window(settings: &Settings) -> impl Widget<GrabData> {
+    let hotkeys_button = Button::new("⌨ Hotkeys").on_click(|ctx, _data: &mut GrabData, _env| {
+        open_hotkeys_window(ctx);
+    });
+    let back_button = Button::new("Back").on_click(|ctx, _data: &mut GrabData, _env| {
+        back_to_main(ctx);
+    });
+
     let mut ui_row = Flex::column();
     ui_row.add_default_spacer();
     ui_row.add_flex_child(Label::new("SETTINGS"), 1.0);
+    ui_row.add_flex_child(create_profile_settings(settings), 1.5);
     ui_row.add_flex_spacer(0.5);
-    ui_row.add_flex_child(create_output_format_dropdown(),1.0);
+    ui_row.add_flex_child(create_output_format_dropdown(&settings.save_format),1.0);
+    ui_row.add_flex_child(create_quality_settings(),1.0);
     ui_row.add_flex_spacer(0.5);
     ui_row.add_flex_child(build_path_dialog(),1.0);
+    ui_row.add_flex_child(create_auto_save_settings(),1.0);
     ui_row.add_flex_spacer(0.5);
     ui_row.add_flex_child(create_timer_settings(),1.0);
     ui_row.add_flex_spacer(0.5);
-    ui_row.add_flex_child(hotkeys_window(),2.0);
-    ui_row
+    ui_row.add_flex_child(Flex::row().with_child(hotkeys_button).with_default_spacer().with_child(back_button), 1.0);
+    ui_row.add_default_spacer();
+    ui_row.controller(Enter)
+
+}
+
+pub fn open_hotkeys_window(ctx: &mut EventCtx) {
+    ctx.window().close();
+    ctx.new_window(WindowDesc::new(hotkeys_window()).title("Hotkeys").window_size((760.0, 420.0)).resizable(false));
+}
+
+// one row for each action, after Edit the keys pressed are recorded by the Enter controller
+fn create_hotkey_row(action: Action) -> impl Widget<GrabData> {
+    let hotkey_label = Label::dynamic(move |data: &GrabData, _: &Env| {
+        if data.editing_hotkey == Some(action) {
+            data.hotkey_new.as_ref().map_or("Press the keys...".to_string(), |hotkey| hotkey.to_string())
+        } else {
+            data.settings.hotkeys.get(&action).map_or("—".to_string(), |hotkey| hotkey.to_string())
+        }
+    });
+    // the grab is done by another thread, the label is refreshed with the ui
+    let scope_label = Label::dynamic(move |data: &GrabData, _: &Env| {
+        if !data.settings.hotkeys.contains_key(&action) {
+            String::new()
+        } else if !action.is_global() {
+            "(while editing a capture)".to_string()
+        } else if global_hotkey::is_active(action) {
+            "(works in every application)".to_string()
+        } else {
+            "(only in this application)".to_string()
+        }
+    }).with_text_size(11.0);
+
+    let edit_button = Button::dynamic(move |data: &GrabData, _env| {
+        if data.editing_hotkey == Some(action) {
+            "Save".to_string()
+        } else {
+            "Edit".to_string()
+        }
+    }).on_click(move |ctx, data: &mut GrabData, _env| {
+        if data.editing_hotkey != Some(action) {
+            // start recording, also when another action was being edited
+            data.editing_hotkey = Some(action);
+            data.hotkey_new = None;
+            data.input_hotkey_error.0 = false;
+            return;
+        }
+        // an invalid or missing combination keeps the recording open
+        let checked = match &data.hotkey_new {
+            Some(hotkey) => check_hotkey(hotkey, action, &data.settings.hotkeys).map(|_| hotkey.clone()),
+            None if data.input_hotkey_error.0 => Err(data.input_hotkey_error.1.clone()),
+            None => Err("Press a key combination, e.g. Ctrl+Shift+S".to_string()),
+        };
+        match checked {
+            Ok(hotkey) => {
+                data.settings.hotkeys.insert(action, hotkey);
+                global_hotkey::update(&data.settings.hotkeys);
+                data.editing_hotkey = None;
+                data.hotkey_new = None;
+                data.input_hotkey_error.0 = false;
+                save_settings(ctx, data);
+            }
+            Err(error) => data.input_hotkey_error = (true, error),
+        }
+    });
 
+    let clear_button = Button::dynamic(move |data: &GrabData, _env| {
+        if data.editing_hotkey == Some(action) {
+            "Cancel".to_string()
+        } else {
+            "Clear".to_string()
+        }
+    }).on_click(move |ctx, data: &mut GrabData, _env| {
+        if data.editing_hotkey == Some(action) {
+            data.editing_hotkey = None;
+        } else {
+            // the action is left without a hotkey
+            data.settings.hotkeys.remove(&action);
+            global_hotkey::update(&data.settings.hotkeys);
+            save_settings(ctx, data);
+        }
+        data.hotkey_new = None;
+        data.input_hotkey_error.0 = false;
+        data.trigger_ui = !data.trigger_ui;
+    });
+
+    Flex::row()
+        .with_child(Label::new(action.label()).fix_width(230.0))
+        .with_child(hotkey_label.fix_width(140.0))
+        .with_child(edit_button)
+        .with_default_spacer()
+        .with_child(clear_button)
+        .with_default_spacer()
+        .with_child(scope_label)
 }
+
 pub fn hotkeys_window() -> impl Widget<GrabData> {
     let mut ui_row = Flex::column();
-    let mut ui_column = Flex::row();
     ui_row.add_default_spacer();
-    ui_column.add_default_spacer();
+    ui_row.add_child(Label::new("HOTKEYS"));
+    ui_row.add_default_spacer();
+    for action in Action::ALL {
+        ui_row.add_child(create_hotkey_row(action));
+        ui_row.add_spacer(4.0);
+    }
 
-        // button to edit
-        ui_column.add_flex_child(Button::dynamic(|data: & GrabData, _env| {
-            if data.set_hot_key {
-                "Save".to_string()
-            } else {
-                "Edit Hotkeys".to_string()
-            }
-        }).on_click(
-            move |_ctx, _data: &mut GrabData, _env| {
-                _data.set_hot_key = !_data.set_hot_key;
-                if _data.set_hot_key == true {
-                    //edit
-                    //_data.hotkey.clear();
-                    _data.hotkey_new.clear();
-                }else{
-                    //save
-                    if _data.hotkey_new.is_empty() {
-                        _data.input_hotkey_error.0 = true;
-                        _data.input_hotkey_error.1 = "Can't have empty hotkeys".to_string();
-                    }else {
-                        _data.hotkey = _data.hotkey_new.clone();
-                        _data.input_hotkey_error.0 = false;
-                        _ctx.window().close();
-                        let main_window = WindowDesc::new(build_ui())
-                            .title(APP_NAME)
-                            .window_size((MAIN_WINDOW_WIDTH, MAIN_WINDOW_HEIGHT)).resizable(false);
-                        _ctx.new_window(main_window);
-                    }
-                }
-            }
-        ), 1.0);
+    // label with errors
+    let error_label = Label::dynamic(|data: &GrabData, _: &Env| {
+        if data.input_hotkey_error.0 {
+            data.input_hotkey_error.1.clone()
+        } else {
+            String::new()
+        }
+    }).with_text_color(Color::rgb(0.8, 0.0, 0.0));
 
-        // Label with hotkeys
-        ui_column.add_flex_child(Label::dynamic(|data: &GrabData, _: &Env| {
-            if data.set_hot_key == true {
-                data.hotkey_new.join(" + ").to_ascii_uppercase()
-            }else{
-                data.hotkey.join(" + ").to_ascii_uppercase()
-            }
-        }), 1.0);
-        // label with errors
-        let error_label = Label::dynamic(|data: &GrabData, _: &Env| {
-            if data.input_hotkey_error.0 {
-                data.input_hotkey_error.1.clone()
-            } else {
-                String::new()
+    let back_button = Button::new("Back").on_click(|ctx, data: &mut GrabData, _env| {
+        // a recording not saved is discarded
+        data.editing_hotkey = None;
+        data.hotkey_new = None;
+        data.input_hotkey_error.0 = false;
+        open_settings_window(ctx, data);
+    });
+
+    ui_row.add_default_spacer();
+    ui_row.add_child(error_label);
+    ui_row.add_default_spacer();
+    ui_row.add_child(back_button);
+    ui_row.controller(Enter)
+}
+
+    // save the approved document, returning false if the user canceled the dialog
+    fn save_capture(data: &mut GrabData) -> Result<bool> {
+        if data.image_data_old.is_empty() {
+            return Ok(false);
+        }
+        // annotations are rasterised only now
+        let file_name = capture_file_name(data)?;
+        let path = if data.settings.auto_save {
+            // no dialog, never overwrite a previous capture
+            unique_path(&data.settings.save_path, &file_name, &data.settings.save_format)
+        } else {
+            let result = FileDialog::new()
+                .set_filename(format!("{}.{}", file_name, data.settings.save_format).as_str())
+                .add_filter("", &[data.settings.save_format.as_str()])
+                .set_location(&data.settings.save_path)
+                .show_save_single_file()
+                .context("Cannot choose where to save")?;
+            match result {
+                Some(path) => path,
+                // The user canceled the dialog.
+                None => return Ok(false),
             }
-        }).with_text_color(Color::rgb(0.8, 0.0, 0.0));
+        };
+        let bytes = export_capture(data)?;
+        fs::write(&path, bytes).context(format!("Cannot write {}", path.display()))?;
+        // increment the screenshot counter
+        // if the user selected a custom filename, no need to increment the automatic inner counter
+        if data.settings.auto_save || path.file_stem().map_or(false, |stem| stem.to_string_lossy() == file_name) {
+            data.settings.screenshot_number = data.settings.screenshot_number.checked_add(1).unwrap_or(0);
+        }
+        Ok(true)
+    }
 
-        let back_button =Button::dynamic(|data: & GrabData, _env| {
-            if data.set_hot_key {
-                "Reset".to_string()
-            } else {
-                "Back".to_string()
-            }
-        }).on_click(
-            move |_ctx, _data: &mut GrabData, _env| {
-                if _data.set_hot_key == true {
-                    // edit
-                    _data.hotkey_new.clear();
-                    _data.set_hot_key = false;
-                    _data.input_hotkey_error.0 = false;
-
-                } else {
-                    //no edit
-                    _data.input_hotkey_error.0 = false;
-                    _ctx.window().close();
-                    let main_window = WindowDesc::new(build_ui())
-                        .title(APP_NAME)
-                        .resizable(false)
-                        .window_size((MAIN_WINDOW_WIDTH, MAIN_WINDOW_HEIGHT));
-                    _ctx.new_window(main_window);
+    fn save_capture_project(data: &GrabData) -> Result<()> {
+        let result = FileDialog::new()
+            .set_filename(format!("{}.{}", capture_file_name(data)?, PROJECT_EXTENSION).as_str())
+            .add_filter("Screenshot project", &[PROJECT_EXTENSION])
+            .set_location(&data.settings.save_path)
+            .show_save_single_file()
+            .context("Cannot choose where to save the project")?;
+        if let Some(path) = result {
+            save_project(&path, &data.image_data_old, &data.document).context(format!("Cannot save {}", path.display()))?;
+        }
+        Ok(())
+    }
 
+    // save and go back to the main window, on error the capture is kept and the user can retry
+    fn save_and_close(ctx: &mut EventCtx, data: &mut GrabData) {
+        match save_capture(data) {
+            Ok(saved) => {
+                if saved {
+                    // cancel all image data
+                    data.image_data_old = vec![];
+                    data.first_screen = true;
                 }
+                back_to_main(ctx);
             }
-        );
-        ui_column.add_default_spacer();
-
-        ui_row.add_flex_child(ui_column,1.0);
-        ui_row.add_flex_child(error_label, 1.0);
-        ui_row.add_default_spacer();
-        ui_row.add_flex_child(back_button, 1.0);
-        ui_row.add_default_spacer();
-        //fusion.add_flex_child(ui_column,1.0);
+            Err(error) => create_error_window(ctx, data, &error),
+        }
+    }
 
-        ui_row.controller(Enter)
+    fn copy_capture(ctx: &mut EventCtx, data: &mut GrabData) {
+        match flatten_image(data).and_then(|image| copy_to_clipboard(&image, false)) {
+            // set copied to clipboard label
+            Ok(()) => data.image_copied = true,
+            Err(error) => create_error_window(ctx, data, &error),
+        }
     }
+
     pub fn create_save_cancel_clipboard_buttons() -> impl Widget<GrabData> {
         let save_button = Button::new("Save").on_click(move |_ctx, _data: &mut GrabData ,_env| {
-            if !_data.image_data_old.is_empty() {
-                // save file
-                let result = FileDialog::new()
-                    .set_filename(format!("Screen{}.{}",_data.screenshot_number, _data.save_format).as_str())
-                    .add_filter("", &[_data.save_format.as_str()])
-                    .set_location(_data.save_path.to_str().unwrap())
-                    .show_save_single_file()
-                    .unwrap();
-                match result {
-                    Some(path) => {
-                        // The user selected a file to save.
-                        // save the file and increment the screenshot counter
-                        // if the user selected a custom filename, no need to increment the automatic inner counter
-                        if path.file_name().unwrap().to_string_lossy().to_string().contains("Screen") {
-                            if _data.screenshot_number == u32::MAX {
-                                _data.screenshot_number = 0;
-                            } else {
-                                _data.screenshot_number+=1;
-                            }
-                        }
-                        fs::write(path, _data.image_data_old.clone()).unwrap();
-                        // cancel all image data
-                        _data.image_data_old = vec![];
-                        _data.first_screen = true;
-                    }
-                    None => {
-                        // The user canceled the dialog.
-                    }
-                }
-            }
-            // if handles the else, a message window saying no file, but the save button appears only when there is an image for now
-            _ctx.window().close();
-            _ctx.new_window(WindowDesc::new(build_ui())
-                .title(APP_NAME)
-                .resizable(false)
-                .window_size((MAIN_WINDOW_WIDTH, MAIN_WINDOW_HEIGHT)));
+            save_and_close(_ctx, _data);
         }).fix_size(BUTTON_WIDTH, BUTTON_HEIGHT);
 
         let cancel_button = Button::new("Cancel").on_click(move |_ctx, _data: &mut GrabData ,_env| {
             // cancel all image data
             _data.image_data_old = vec![];
             _data.first_screen = true;
-            _ctx.window().close();
-            _ctx.new_window(WindowDesc::new(build_ui())
-                .title(APP_NAME)
-                .resizable(false)
-                .window_size((MAIN_WINDOW_WIDTH, MAIN_WINDOW_HEIGHT)));
+            back_to_main(_ctx);
         }).fix_size(BUTTON_WIDTH, BUTTON_HEIGHT);
 
         let clipboard_button = Button::new("Copy to Clipboard").on_click(move |_ctx, _data: &mut GrabData ,_env| {
-            // copy to the clipboard
-            let image = load_from_memory_with_format(&_data.image_data_old, image::ImageFormat::Png).unwrap().to_rgba8();
-            let  mut clipboard = arboard::Clipboard::new().unwrap();
-
-            let img = arboard::ImageData {
-                width: image.width() as usize,
-                height: image.height() as usize,
-                bytes: Cow::from(image.as_bytes())
-            };
-            clipboard.set_image(img).unwrap();
-            // set copied to clipboard label
-            _data.image_copied = true;
+            copy_capture(_ctx, _data);
         }).fix_size(BUTTON_WIDTH * 2.0, BUTTON_HEIGHT);
 
+        let save_project_button = Button::new("Save Project").on_click(move |_ctx, _data: &mut GrabData ,_env| {
+            // save the original capture with crop and annotations, to edit it again later
+            if let Err(error) = save_capture_project(_data) {
+                create_error_window(_ctx, _data, &error);
+            }
+        }).fix_size(BUTTON_WIDTH * 1.5, BUTTON_HEIGHT);
+
         let copy_confirmed = Label::dynamic(|data: &GrabData, _: &Env| {
             if data.image_copied {
                 format!("✓")
@@ -267,37 +786,104 @@ pub fn hotkeys_window() -> impl Widget<GrabData> {
         ui_row.add_flex_child(cancel_button, 0.1);
         ui_row.add_default_spacer();
         ui_row.add_flex_child(Flex::row().with_child(clipboard_button).with_child(copy_confirmed), 0.1);
+        ui_row.add_default_spacer();
+        ui_row.add_flex_child(save_project_button, 0.1);
 
         ui_row
     }
 
+    fn create_open_project_button() -> impl Widget<GrabData> {
+        Button::new("📂 Open Project").on_click(|ctx, data: &mut GrabData, _env| {
+            let result = FileDialog::new()
+                .add_filter("Screenshot project", &[PROJECT_EXTENSION])
+                .set_location(&data.settings.save_path)
+                .show_open_single_file()
+                .context("Cannot open the project");
+            let loaded = match result {
+                Ok(Some(path)) => load_project(&path).context(format!("Cannot open {}", path.display())),
+                Ok(None) => return,
+                Err(error) => Err(error),
+            };
+            match loaded {
+                Ok((capture_png, document)) => {
+                    // reopen the editor as after a capture, with the saved crop and annotations
+                    reset_data(data);
+                    data.image_data_old = capture_png;
+                    data.document = document.clone();
+                    data.draft = document;
+                    data.first_screen = false;
+                    create_selection_window(ctx,data);
+                }
+                Err(error) => create_error_window(ctx, data, &error),
+            }
+        })
+    }
+
+    fn create_pdf_bundle_button() -> impl Widget<GrabData> {
+        Button::new("🗎 Captures to PDF").on_click(|ctx, data: &mut GrabData, _env| {
+            if let Err(error) = bundle_captures_to_pdf(data) {
+                create_error_window(ctx, data, &error);
+            }
+        })
+    }
+
+    // bundle several saved captures in a multi-page pdf
+    fn bundle_captures_to_pdf(data: &GrabData) -> Result<()> {
+        let inputs = FileDialog::new()
+            .add_filter("Images", &["png", "jpg", "jpeg", "bmp", "tiff", "gif", "webp", "avif", "qoi"])
+            .set_location(&data.settings.save_path)
+            .show_open_multiple_file()
+            .context("Cannot choose the captures")?;
+        if inputs.is_empty() {
+            return Ok(());
+        }
+        let output = FileDialog::new()
+            .set_filename("Captures.pdf")
+            .add_filter("", &["pdf"])
+            .set_location(&data.settings.save_path)
+            .show_save_single_file()
+            .context("Cannot choose the pdf file")?;
+        if let Some(output) = output {
+            // the dpi of saved captures is unknown, use the standard one
+            let mut pages: Vec<(DynamicImage, f32)> = vec![];
+            for path in inputs.iter() {
+                pages.push((image::open(path).context(format!("Cannot read {}", path.display()))?, BASE_DPI));
+            }
+            let pdf = images_to_pdf(&pages).context("Cannot create the pdf")?;
+            fs::write(&output, pdf).context(format!("Cannot write {}", output.display()))?;
+        }
+        Ok(())
+    }
+
     fn build_path_dialog() -> impl Widget<GrabData> {
         let path_label = Label::dynamic(|data: &GrabData, _env: &_| {
-            if data.save_path.to_str().unwrap().len() > 40 {
-                return "Current Path: ".to_owned() + &data.save_path.to_str().unwrap()[..40] + &*"...".to_owned();
+            let path = data.settings.save_path.to_string_lossy();
+            if path.chars().count() > 40 {
+                return "Current Path: ".to_owned() + &path.chars().take(40).collect::<String>() + &*"...".to_owned();
             }
-            return "Current Path: ".to_owned() + data.save_path.to_str().unwrap();
+            return "Current Path: ".to_owned() + &path;
         });
 
-        let change_path_button = Button::new("📁").on_click(|_ctx, data: &mut GrabData, _env| {
+        let change_path_button = Button::new("📁").on_click(|ctx, data: &mut GrabData, _env| {
             let result = FileDialog::new()
-                .set_location(data.save_path.to_str().unwrap())
-                .show_open_single_dir();
+                .set_location(&data.settings.save_path)
+                .show_open_single_dir()
+                .context("Cannot choose the save folder");
             match result {
                 Ok(opt_path) => {
                     match opt_path {
                         Some(path) => {
                             // set the new folder path and trigger ui refresh
                             data.trigger_ui = !data.trigger_ui;
-                            data.save_path = path.into_boxed_path();
+                            data.settings.save_path = path.into_boxed_path();
                         }
                         None => {
                             //the user cancelled the dialog
                         }
                     }
                 }
-                Err(_) => {
-                    panic!("Error in Setting the Path");
+                Err(error) => {
+                    create_error_window(ctx, data, &error);
                 }
             }
         });
@@ -311,33 +897,88 @@ pub fn hotkeys_window() -> impl Widget<GrabData> {
     }
 
 
+    fn create_quality_settings() -> impl Widget<GrabData> {
+        let quality_label = Label::dynamic(|data: &GrabData, _: &Env| {
+            format!("Quality: {}", data.settings.quality)
+        });
+        let slider = druid::widget::Slider::new().with_range(0.0, 100.0).with_step(1.0).lens(GrabData::settings.then(Settings::quality));
+        let lossless_checkbox = Checkbox::new("Lossless").lens(GrabData::settings.then(Settings::lossless));
+
+        // the slider is shown only for the lossy formats, webp can be lossless too
+        let lossy_row = Flex::row()
+            .with_child(Either::new(|data: &GrabData, _| data.settings.save_format == "webp" && data.settings.lossless,
+                                    Label::new("Lossless webp"),
+                                    Flex::row().with_child(slider).with_child(quality_label)))
+            .with_default_spacer()
+            .with_child(Either::new(|data: &GrabData, _| data.settings.save_format == "webp",
+                                    lossless_checkbox,
+                                    SizedBox::empty()));
+        Either::new(|data: &GrabData, _| ["jpg", "jpeg", "webp", "avif"].contains(&data.settings.save_format.as_str()),
+                    lossy_row,
+                    SizedBox::empty())
+    }
+
+    fn create_auto_save_settings() -> impl Widget<GrabData> {
+        let auto_save_checkbox = Checkbox::new("Auto-save without dialog").lens(GrabData::settings.then(Settings::auto_save));
+        let template_box = TextBox::new().with_placeholder("Screen{counter}").lens(GrabData::settings.then(Settings::file_template));
+        let tokens_label = Label::new("{date} {time} {counter} {monitor} {width} {height}").with_text_size(11.0);
+
+        Flex::column()
+            .with_child(Flex::row()
+                .with_child(auto_save_checkbox)
+                .with_default_spacer()
+                .with_child(Label::new("File name:"))
+                .with_child(template_box.fix_width(180.0)))
+            .with_child(tokens_label)
+    }
+
     fn create_timer_settings() -> impl Widget<GrabData> {
 
-        let seconds_label = Label::dynamic(|data: &GrabData, _: &Env| {
-            format!("Timer delay: {} seconds", data.delay.to_string())
-        });
-        let slider = druid::widget::Slider::new().with_range(1.0, 20.0).with_step(1.0).lens(GrabData::delay);
-        let ui_row = Flex::row().with_child(slider).with_child(seconds_label);
+        let seconds_label = Label::new("Timer delay (seconds):");
+        let slider = druid::widget::Slider::new().with_range(0.0, 60.0).with_step(0.5).lens(GrabData::settings.then(Settings::delay));
+        // longer or more precise delays are typed, up to MAX_DELAY
+        let seconds_box = TextBox::new()
+            .with_formatter(ParseFormatter::new())
+            .update_data_while_editing(true)
+            .lens(GrabData::settings.then(Settings::delay).map(|delay: &f64| *delay, |delay: &mut f64, value: f64| {
+                if value.is_finite() {
+                    *delay = value.clamp(0.0, MAX_DELAY);
+                }
+            }));
+        let ui_row = Flex::row().with_child(seconds_label).with_default_spacer().with_child(slider)
+            .with_default_spacer().with_child(seconds_box.fix_width(80.0));
         Flex::column().with_child(ui_row)
 
     }
 
+    // the countdown window stays on top, so the time left is visible while preparing the screen
+    fn start_timer(ctx: &mut EventCtx, _data: &mut GrabData) {
+        let countdown_label = Label::dynamic(|data: &GrabData, _: &Env| format!("{:.1}", data.countdown))
+            .with_font(FontDescriptor::new(Default::default()).with_size(40.0));
+        let cancel_button = Button::new("Cancel").on_click(|ctx, data: &mut GrabData, _env| cancel_timer(ctx, data));
+        ctx.window().close();
+        ctx.new_window(WindowDesc::new(
+            Flex::column()
+                .with_child(countdown_label)
+                .with_child(Label::new("seconds to the capture, Escape to cancel").with_text_size(11.0))
+                .with_default_spacer()
+                .with_child(cancel_button)
+                .padding(10.0)
+                .controller(Countdown::default())
+        ).title(APP_NAME).window_size((COUNTDOWN_WINDOW_WIDTH, COUNTDOWN_WINDOW_HEIGHT)).set_always_on_top(true).resizable(false));
+    }
+
+    pub fn cancel_timer(ctx: &mut EventCtx, _data: &mut GrabData) {
+        back_to_main(ctx);
+    }
+
     fn create_timer_button() -> impl Widget<GrabData> {
         let mut ui_row = Flex::row();
 
         let start_timer_btn =
             Button::new(|data: &GrabData, _: &Env| {
-                format!("Start timer (in {} seconds)", data.delay.to_string())
-            }).on_click(|ctx, data: &mut GrabData, _env| {
-            // Create a new window to trigger the timer request, conditioned by the timer_request flag
-            data.timer_requested = true;
-            ctx.window().close();
-            ctx.new_window(WindowDesc::new(
-                Flex::column()
-                    .with_child(Label::new("Waiting...").with_font(FontDescriptor::new(Default::default()).with_size(40.0)))
-                    .with_child(Spinner::new().fix_size(200.0,200.0)).controller(Enter)
-            ).title(APP_NAME).window_size((MAIN_WINDOW_WIDTH, MAIN_WINDOW_HEIGHT)).resizable(false));
-        });
+                format!("Start timer (in {} seconds)", data.settings.delay.to_string())
+            }).on_click(|ctx, data: &mut GrabData, _env| start_timer(ctx, data));
 
         ui_row.add_child(start_timer_btn);
         Flex::column().with_child(ui_row)
@@ -346,14 +987,18 @@ pub fn hotkeys_window() -> impl Widget<GrabData> {
     pub fn create_annotation_buttons() -> impl Widget<GrabData> {
         let mut ui_row1 = Flex::row();
         let mut ui_row2 = Flex::row();
-        let file = File::open("settings.json").unwrap();
-        let data: GrabData = from_reader(file).unwrap();
 
         ui_row1.add_flex_child(Button::new("✂").on_click(|ctx, data: &mut GrabData, _env| {
             data.annotation = Annotation::None;
             create_edit_window(ctx,data);
         }), 1.0);
         ui_row1.add_default_spacer();
+        ui_row1.add_flex_child(Button::new("☝").on_click(|ctx, data: &mut GrabData, _env| {
+            data.annotation = Annotation::Select;
+            data.selected = None;
+            create_edit_window(ctx,data);
+        }), 1.0);
+        ui_row1.add_default_spacer();
         ui_row1.add_flex_child(Button::new("◯").on_click(|ctx, data: &mut GrabData, _env| {
             data.annotation = Annotation::Circle;
             create_edit_window(ctx,data);
@@ -374,6 +1019,31 @@ pub fn hotkeys_window() -> impl Widget<GrabData> {
             create_edit_window(ctx,data);
         }), 1.0);
         ui_row1.add_default_spacer();
+        ui_row1.add_flex_child(Button::new("▦").on_click(|ctx, data: &mut GrabData, _env| {
+            data.annotation = Annotation::Pixelate;
+            create_edit_window(ctx,data);
+        }), 1.0);
+        ui_row1.add_default_spacer();
+        ui_row1.add_flex_child(Button::new("◍").on_click(|ctx, data: &mut GrabData, _env| {
+            data.annotation = Annotation::Blur;
+            create_edit_window(ctx,data);
+        }), 1.0);
+        ui_row1.add_default_spacer();
+        ui_row1.add_flex_child(Button::new("█").on_click(|ctx, data: &mut GrabData, _env| {
+            data.annotation = Annotation::Redact;
+            create_edit_window(ctx,data);
+        }), 1.0);
+        ui_row1.add_default_spacer();
+        ui_row1.add_flex_child(Button::new("⌫").on_click(|ctx, data: &mut GrabData, _env| {
+            // remove the annotation on top, the others are still editable objects
+            if !data.document.is_empty() {
+                data.history.record(data.document.clone());
+                data.document.remove_last();
+            }
+            data.draft = data.document.clone();
+            create_selection_window(ctx,data);
+        }), 1.0);
+        ui_row1.add_default_spacer();
 
         ui_row2.add_flex_child(Button::new("〜").on_click(|ctx, data: &mut GrabData, _env| {
             data.annotation = Annotation::FreeLine;
@@ -385,20 +1055,50 @@ pub fn hotkeys_window() -> impl Widget<GrabData> {
             create_edit_window(ctx,data);
         }), 1.0);
         ui_row2.add_default_spacer();
+        ui_row2.add_flex_child(Button::new("⬭").on_click(|ctx, data: &mut GrabData, _env| {
+            data.annotation = Annotation::Ellipse;
+            create_edit_window(ctx,data);
+        }), 1.0);
+        ui_row2.add_default_spacer();
+        ui_row2.add_flex_child(Button::new("▭").on_click(|ctx, data: &mut GrabData, _env| {
+            data.annotation = Annotation::RoundedRectangle;
+            create_edit_window(ctx,data);
+        }), 1.0);
+        ui_row2.add_default_spacer();
         ui_row2.add_flex_child(Button::new("A").on_click(|ctx, data: &mut GrabData, _env| {
             data.annotation = Annotation::Text;
             create_edit_window(ctx,data);
         }), 1.0);
         ui_row2.add_default_spacer();
+        ui_row2.add_flex_child(Button::new("①").on_click(|ctx, data: &mut GrabData, _env| {
+            data.annotation = Annotation::Step;
+            create_edit_window(ctx,data);
+        }), 1.0);
+        ui_row2.add_default_spacer();
         ui_row2.add_flex_child(Button::new("💄").on_click(|ctx, data: &mut GrabData, _env| {
             data.annotation = Annotation::Highlighter;
             create_edit_window(ctx,data);
         }), 1.0);
         ui_row2.add_default_spacer();
+        ui_row2.add_flex_child(Button::new("↶").on_click(|ctx, data: &mut GrabData, _env| {
+            undo_edit(ctx,data);
+        }), 1.0);
+        ui_row2.add_default_spacer();
+        ui_row2.add_flex_child(Button::new("↷").on_click(|ctx, data: &mut GrabData, _env| {
+            redo_edit(ctx,data);
+        }), 1.0);
+        ui_row2.add_default_spacer();
         ui_row2.add_flex_child(Button::from_label(Label::new("⬤")
-            .with_text_color(Color::rgba8(data.color.0,data.color.1,data.color.2,data.color.3)))
-                                   .on_click(|ctx, _data: &mut GrabData, _env| {
-                                       let rect = druid::Screen::get_monitors()[0].virtual_rect();
+            .with_text_color(CURRENT_COLOR)
+            .env_scope(|env, data: &GrabData| {
+                let color = data.settings.color;
+                env.set(CURRENT_COLOR, Color::rgba8(color.0, color.1, color.2, color.3));
+            }))
+                                   .on_click(|ctx, data: &mut GrabData, _env| {
+                                       let rect = match first_monitor_rect() {
+                                           Ok(rect) => rect,
+                                           Err(error) => return create_error_window(ctx, data, &error),
+                                       };
                                        ctx.window().close();
                                        ctx.new_window(WindowDesc::new(create_color_buttons()).title(APP_NAME).window_size((250.0,200.0)).show_titlebar(false).resizable(false).set_position((rect.x0,rect.y0)));
                                    }), 1.0);
@@ -406,6 +1106,45 @@ pub fn hotkeys_window() -> impl Widget<GrabData> {
         Flex::column().with_child(ui_row1).with_child(ui_row2)
     }
 
+    pub fn undo_edit(ctx: &mut EventCtx, data: &mut GrabData) {
+        // nothing to undo without a capture
+        if data.image_data_old.is_empty() || data.first_screen {
+            return;
+        }
+        // unapproved changes of the edit window are discarded first
+        if data.draft != data.document {
+            data.draft = data.document.clone();
+        } else if let Some(previous) = data.history.undo(&data.document) {
+            data.document = previous;
+            data.draft = data.document.clone();
+        }
+        data.annotation = Annotation::None;
+        data.positions = vec![];
+        create_selection_window(ctx,data);
+    }
+
+    pub fn redo_edit(ctx: &mut EventCtx, data: &mut GrabData) {
+        if data.image_data_old.is_empty() || data.first_screen {
+            return;
+        }
+        if let Some(next) = data.history.redo(&data.document) {
+            data.document = next;
+            data.draft = data.document.clone();
+            data.annotation = Annotation::None;
+            data.positions = vec![];
+            create_selection_window(ctx,data);
+        }
+    }
+
+    // colors of the annotations and of the fills
+    fn palette() -> [Color; 12] {
+        // giallo verde blu viola rosso arancione rosa nero bianco marrone grigio
+        let orange = Color::rgba8(255, 165, 0, 255);
+        let pink = Color::rgba8(255, 192, 203, 255);
+        let brown= Color::rgba8(139, 69, 19, 255);
+        [Color::RED, Color::GREEN, Color::BLUE, Color::YELLOW,orange, pink,brown, Color::BLACK,Color::WHITE, Color::GRAY, Color::PURPLE, Color::FUCHSIA]
+    }
+
     pub fn create_color_buttons() -> impl Widget<GrabData> {
         // 12 colors 4 x 3
         let mut ui_col = Flex::column();
@@ -414,13 +1153,7 @@ pub fn hotkeys_window() -> impl Widget<GrabData> {
         ui_col.add_flex_child(label,2.0);
         ui_col.add_default_spacer();
 
-        // giallo verde blu viola rosso arancione rosa nero bianco marrone grigio
-        let orange = Color::rgba8(255, 165, 0, 255);
-        let pink = Color::rgba8(255, 192, 203, 255);
-        let brown= Color::rgba8(139, 69, 19, 255);
-        let colors: [Color; 12] = [Color::RED, Color::GREEN, Color::BLUE, Color::YELLOW,orange, pink,brown, Color::BLACK,Color::WHITE, Color::GRAY, Color::PURPLE, Color::FUCHSIA];
-
-        for chunk in colors.chunks(4) {
+        for chunk in palette().chunks(4) {
             let mut ui_row = Flex::row();
             for &color in chunk {
                 //ui_row.add_flex_spacer(2.0);
@@ -429,10 +1162,10 @@ pub fn hotkeys_window() -> impl Widget<GrabData> {
                     Button::from_label(Label::new("⬤").with_text_color(color))
                         .on_click(move |ctx, data: &mut GrabData, _env| {
                             // Change the color and save it
-                            data.color = color.as_rgba8();
-
-                            let file = File::create("settings.json").unwrap();
-                            to_writer(file, data).unwrap();
+                            data.settings.color = color.as_rgba8();
+                            if let Err(error) = data.settings.save() {
+                                eprintln!("{}", error);
+                            }
                             create_selection_window(ctx,data);
                         }).expand_width(),2.0,);
             }
@@ -450,31 +1183,119 @@ pub fn hotkeys_window() -> impl Widget<GrabData> {
         ui_col
     }
 
+    // stroke width, line style and fill of the shape tool, kept in the settings for each tool
+    fn create_shape_style_widgets(kind: Annotation) -> impl Widget<GrabData> {
+        let style = GrabData::settings.then(Settings::shape_styles).then(shape_style_lens(kind.clone()));
+        let mut ui_column = Flex::column();
+
+        let stroke_width_slider = druid::widget::Slider::new()
+            .with_range(MIN_STROKE_WIDTH, MAX_STROKE_WIDTH)
+            .with_step(1.0)
+            .lens(style.clone().then(ShapeStyle::width));
+        let stroke_width = Label::dynamic(|width: &f64, _env: &_| "Stroke Width: ".to_owned() + width.to_string().as_str())
+            .lens(style.clone().then(ShapeStyle::width));
+        ui_column.add_child(Flex::row().with_child(stroke_width_slider).with_child(stroke_width));
+        ui_column.add_child(RadioGroup::row(vec![("Solid", LineStyle::Solid), ("Dashed", LineStyle::Dashed), ("Dotted", LineStyle::Dotted)])
+            .lens(style.clone().then(ShapeStyle::line_style)));
+
+        if kind == Annotation::RoundedRectangle {
+            let corner_radius_slider = druid::widget::Slider::new()
+                .with_range(0.0, MAX_CORNER_RADIUS)
+                .with_step(1.0)
+                .lens(GrabData::settings.then(Settings::corner_radius));
+            let corner_radius = Label::dynamic(|data: &GrabData, _env: &_| "Corner Radius: ".to_owned() + data.settings.corner_radius.to_string().as_str());
+            ui_column.add_child(Flex::row().with_child(corner_radius_slider).with_child(corner_radius));
+        }
+
+        if kind.is_closed_shape() {
+            let mut fill_row = Flex::row();
+            fill_row.add_child(Checkbox::new("Fill").lens(style.clone().then(ShapeStyle::fill)));
+            for color in palette() {
+                let kind = kind.clone();
+                fill_row.add_child(Button::from_label(Label::new("⬤").with_text_color(color))
+                    .on_click(move |_ctx, data: &mut GrabData, _env| {
+                        // the opacity is kept
+                        if let Some(style) = data.settings.shape_styles.style_mut(&kind) {
+                            let (r, g, b, _) = color.as_rgba8();
+                            style.fill_color = (r, g, b, style.fill_color.3);
+                            style.fill = true;
+                        }
+                    }));
+            }
+            let fill_alpha = style.then(ShapeStyle::fill_color).map(
+                |color: &(u8,u8,u8,u8)| color.3 as f64,
+                |color: &mut (u8,u8,u8,u8), alpha: f64| color.3 = alpha as u8);
+            let fill_alpha_slider = druid::widget::Slider::new()
+                .with_range(0.0, 255.0)
+                .with_step(1.0)
+                .lens(fill_alpha.clone());
+            let fill_alpha_label = Label::dynamic(|alpha: &f64, _env: &_| "Fill Opacity: ".to_owned() + alpha.to_string().as_str())
+                .lens(fill_alpha);
+            ui_column.add_child(fill_row);
+            ui_column.add_child(Flex::row().with_child(fill_alpha_slider).with_child(fill_alpha_label));
+        }
+        ui_column
+    }
+
+    // change the annotation picked with the select tool and show the draft again
+    fn edit_selected(ctx: &mut EventCtx, data: &mut GrabData, edit: impl FnOnce(&mut GrabData, u32)) {
+        if let Some(id) = data.selected.filter(|id| data.draft.get(*id).is_some()) {
+            edit(data, id);
+            create_edit_window(ctx, data);
+        }
+    }
+
+    fn create_selection_widgets() -> impl Widget<GrabData> {
+        let restyle = Button::new("Apply Color and Width").on_click(|ctx, data: &mut GrabData, _env| {
+            edit_selected(ctx, data, |data, id| {
+                // the width of the tool that drew the annotation
+                let kind = data.draft.get(id).map(|object| object.kind.clone()).unwrap_or(Annotation::None);
+                let width = tool_width(data, &kind);
+                data.draft.restyle(id, data.settings.color, width);
+            });
+        });
+        let front = Button::new("Bring to Front").on_click(|ctx, data: &mut GrabData, _env| {
+            edit_selected(ctx, data, |data, id| data.draft.bring_to_front(id));
+        });
+        let back = Button::new("Send to Back").on_click(|ctx, data: &mut GrabData, _env| {
+            edit_selected(ctx, data, |data, id| data.draft.send_to_back(id));
+        });
+        let delete = Button::new("Delete").on_click(|ctx, data: &mut GrabData, _env| {
+            edit_selected(ctx, data, |data, id| {
+                data.draft.remove(id);
+                data.selected = None;
+            });
+        });
+        Flex::row().with_child(restyle).with_default_spacer().with_child(front).with_default_spacer()
+            .with_child(back).with_default_spacer().with_child(delete)
+    }
+
     pub fn create_edit_window_widgets(data: &GrabData) -> impl Widget<GrabData> {
         let ui_column = Flex::column();
         let mut ui_row1 = Flex::row();
 
         let approve = Button::new("✔").on_click(|ctx, data: &mut GrabData ,_env| {
-            // modified, so new image become the old image, ready to be saved
-            if !data.image_data_new.is_empty() {
-                data.image_data_old = data.image_data_new.clone();
+            // modified, so the draft become the approved document, ready to be saved
+            if data.draft != data.document {
+                data.history.record(data.document.clone());
+                data.document = data.draft.clone();
             }
-            // clear the new image
-            data.image_data_new = vec![];
             // reset annotation
             data.annotation = Annotation::None;
-            // clear positions (for text annotation case)
+            // clear positions (for text annotation case) and the selection
             data.positions = vec![];
+            data.selected = None;
             // return in the selection window
             create_selection_window(ctx,data);
         });
         let reject = Button::new("✖").on_click(|ctx, data: &mut GrabData ,_env| {
-            // discard the new image
-            data.image_data_new = vec![];
+            // discard the changes of the draft
+            data.draft = data.document.clone();
             // reset annotation
             data.annotation = Annotation::None;
-            // clear positions (for text annotation case)
+            // clear positions (for text annotation case) and the selection
             data.positions = vec![];
+            data.selected = None;
             // return in the selection window
             create_selection_window(ctx,data);
         });
@@ -488,24 +1309,10 @@ pub fn hotkeys_window() -> impl Widget<GrabData> {
             Annotation::Text => {
                 // add also text handling widgets
                 let add_text = Button::new("Add Text").on_click(|ctx, data: &mut GrabData, _env| {
-                    // draw text
-                    let image = load_image(data);
-
-                    let font_data: &[u8] = include_bytes!("../OpenSans-Semibold.ttf");
-                    //fs::read(Path::new(format!("{}{}",std::env::current_dir().unwrap().to_str().unwrap(),"\\OpenSans-Semibold.ttf").as_str())).unwrap().as_slice();
-                    let font: Font<'static> = Font::try_from_bytes(font_data).unwrap();
-                    // draw line with first and last position, then clear the vector
+                    // add the text to the draft document
                     if !data.positions.is_empty() {
-                        // take the only point to draw the text from it
-                        // the last point if we click many times, so len-1
-                        let (x,y) = (((data.positions[data.positions.len()-1].0 - data.offsets.0) * data.scale_factors.0) as i32,
-                                     ((data.positions[data.positions.len()-1].1 - data.offsets.1) * data.scale_factors.1) as i32);
-                        let text_image = DynamicImage::from(
-                            draw_text(&image,
-                                      Rgba([data.color.0, data.color.1, data.color.2, data.color.3]), x, y,
-                                      rusttype::Scale::uniform(data.text_size as f32), &font, data.text_annotation.as_str()));
-                        // save the modified version of the image
-                        data.image_data_new = image_to_buffer(text_image);
+                        let object = make_annotation_object(data);
+                        data.draft.add(object);
 
                         // empty position vector, not done in ScreenshotWidget
                         data.positions = vec![];
@@ -518,8 +1325,8 @@ pub fn hotkeys_window() -> impl Widget<GrabData> {
                 let text_font_size = druid::widget::Slider::new()
                     .with_range(10.0, 60.0)
                     .with_step(1.0)
-                    .lens(GrabData::text_size);
-                let font_size = Label::dynamic(|data: &GrabData, _env: &_| "Font Size: ".to_owned() + data.text_size.to_string().as_str());
+                    .lens(GrabData::settings.then(Settings::text_size));
+                let font_size = Label::dynamic(|data: &GrabData, _env: &_| "Font Size: ".to_owned() + data.settings.text_size.to_string().as_str());
 
                 return ui_column.with_child(ui_row1).with_child(add_text).with_child(text_input)
                     .with_child(Flex::row().with_child(text_font_size).with_child(font_size))
@@ -528,12 +1335,48 @@ pub fn hotkeys_window() -> impl Widget<GrabData> {
                 let highlighter_width_slider = druid::widget::Slider::new()
                     .with_range(5.0, 40.0)
                     .with_step(1.0)
-                    .lens(GrabData::highlighter_width);
+                    .lens(GrabData::settings.then(Settings::highlighter_width));
 
-                let highlighter_width = Label::dynamic(|data: &GrabData, _env: &_| "Highlighter Width: ".to_owned() + data.highlighter_width.to_string().as_str());
+                let highlighter_width = Label::dynamic(|data: &GrabData, _env: &_| "Highlighter Width: ".to_owned() + data.settings.highlighter_width.to_string().as_str());
 
                 return ui_column.with_child(ui_row1).with_child(highlighter_width_slider).with_child(highlighter_width)
             }
+            ref kind if kind.is_shape() => {
+                return ui_column.with_child(ui_row1).with_child(create_shape_style_widgets(kind.clone()))
+            }
+            Annotation::Select => {
+                return ui_column.with_child(ui_row1).with_child(create_selection_widgets())
+            }
+            Annotation::Step => {
+                // the badges are sized by the font size
+                let text_font_size = druid::widget::Slider::new()
+                    .with_range(10.0, 60.0)
+                    .with_step(1.0)
+                    .lens(GrabData::settings.then(Settings::text_size));
+                let font_size = Label::dynamic(|data: &GrabData, _env: &_| "Badge Size: ".to_owned() + data.settings.text_size.to_string().as_str());
+
+                return ui_column.with_child(ui_row1).with_child(Flex::row().with_child(text_font_size).with_child(font_size))
+            }
+            Annotation::Pixelate => {
+                let pixelate_size_slider = druid::widget::Slider::new()
+                    .with_range(MIN_REDACT_STRENGTH, MAX_REDACT_STRENGTH)
+                    .with_step(1.0)
+                    .lens(GrabData::settings.then(Settings::pixelate_size));
+
+                let pixelate_size = Label::dynamic(|data: &GrabData, _env: &_| "Block Size: ".to_owned() + data.settings.pixelate_size.to_string().as_str());
+
+                return ui_column.with_child(ui_row1).with_child(pixelate_size_slider).with_child(pixelate_size)
+            }
+            Annotation::Blur => {
+                let blur_strength_slider = druid::widget::Slider::new()
+                    .with_range(MIN_REDACT_STRENGTH, MAX_REDACT_STRENGTH)
+                    .with_step(1.0)
+                    .lens(GrabData::settings.then(Settings::blur_strength));
+
+                let blur_strength = Label::dynamic(|data: &GrabData, _env: &_| "Blur Strength: ".to_owned() + data.settings.blur_strength.to_string().as_str());
+
+                return ui_column.with_child(ui_row1).with_child(blur_strength_slider).with_child(blur_strength)
+            }
             _ => {}
         }
 
@@ -559,7 +1402,7 @@ pub fn create_edit_window(ctx: &mut EventCtx, data: &mut GrabData) {
                 return "Click and Drag to Draw a Cross: ".to_string();
             }
             Annotation::Rectangle => {
-                return "Click and Drag to Draw a Rectangle: ".to_string();
+                return "Click and Drag to Draw a Rectangle (Shift for a Square): ".to_string();
             }
             Annotation::FreeLine => {
                 return "Click and Drag to Draw a Free Line: ".to_string();
@@ -573,15 +1416,45 @@ pub fn create_edit_window(ctx: &mut EventCtx, data: &mut GrabData) {
             Annotation::Text => {
                 return "Click on image, write text in textbox, and select font size:".to_string();
             }
+            Annotation::Select => {
+                return "Click an Annotation to Select it, Drag to Move it: ".to_string();
+            }
+            Annotation::Ellipse => {
+                return "Click and Drag to Draw an Ellipse (Shift for a Circle): ".to_string();
+            }
+            Annotation::RoundedRectangle => {
+                return "Click and Drag to Draw a Rounded Rectangle (Shift for a Square): ".to_string();
+            }
+            Annotation::Step => {
+                return "Click to Place the Next Numbered Step, Right Click to Remove One: ".to_string();
+            }
+            Annotation::Pixelate => {
+                return "Click and Drag to Pixelate an Area: ".to_string();
+            }
+            Annotation::Blur => {
+                return "Click and Drag to Blur an Area: ".to_string();
+            }
+            Annotation::Redact => {
+                return "Click and Drag to Cover an Area: ".to_string();
+            }
         }
     }).fix_size(10000.0, 20.0);
 
-        let image = load_image(data);
+        let image = match render_image(data) {
+            Ok(image) => image,
+            Err(error) => return create_error_window(ctx, data, &error),
+        };
         let rgba_image = image.to_rgba8();
 
-        let (image_width,image_height) = resize_image(image,data);
+        let (image_width,image_height) = match resize_image(image,data) {
+            Ok(size) => size,
+            Err(error) => return create_error_window(ctx, data, &error),
+        };
 
-        let rect = druid::Screen::get_monitors()[0].virtual_rect();
+        let rect = match first_monitor_rect() {
+            Ok(rect) => rect,
+            Err(error) => return create_error_window(ctx, data, &error),
+        };
         let image_buf = ImageBuf::from_raw(
             rgba_image.clone().into_raw(),
             ImageFormat::RgbaSeparate,
@@ -611,12 +1484,21 @@ pub fn create_edit_window(ctx: &mut EventCtx, data: &mut GrabData) {
         // reset copied to clipboard label
         data.image_copied = false;
 
-        let image = load_image(data);
+        let image = match render_image(data) {
+            Ok(image) => image,
+            Err(error) => return create_error_window(ctx, data, &error),
+        };
         let rgba_image = image.to_rgba8();
 
-        let (image_width,image_height) = resize_image(image,data);
+        let (image_width,image_height) = match resize_image(image,data) {
+            Ok(size) => size,
+            Err(error) => return create_error_window(ctx, data, &error),
+        };
 
-        let rect = druid::Screen::get_monitors()[0].virtual_rect();
+        let rect = match first_monitor_rect() {
+            Ok(rect) => rect,
+            Err(error) => return create_error_window(ctx, data, &error),
+        };
         let image_buf = ImageBuf::from_raw(
             rgba_image.clone().into_raw(),
             ImageFormat::RgbaSeparate,
@@ -646,8 +1528,7 @@ pub fn create_edit_window(ctx: &mut EventCtx, data: &mut GrabData) {
         //SETTINGS
         let btn = Button::new( "⚙ Settings".to_string() ).on_click(
             move |_ctx, _data: &mut GrabData ,_env| {
-                _ctx.window().close();
-                _ctx.new_window(WindowDesc::new(settings_window()).title("Settings").window_size((600.0,500.0)).resizable(false));
+                open_settings_window(_ctx, _data);
             });
         row.add_flex_spacer(2.0);
         row.add_flex_child(btn,0.7);
@@ -655,13 +1536,35 @@ pub fn create_edit_window(ctx: &mut EventCtx, data: &mut GrabData) {
         //TAKE A SCREEN
         ui_column.add_flex_child(create_monitor_buttons(),1.0);
         ui_column.add_default_spacer();
-        //TIMER
-        ui_column.add_flex_child(create_timer_button(),1.0);
+        //TIMER AND SAME REGION
+        let repeat_button = Button::new("↻ Same region again").on_click(|ctx, data: &mut GrabData, _env| {
+            run_action(ctx, data, Action::RepeatRegion);
+        });
+        let presets_button = Button::new("▣ Region presets").on_click(|ctx, data: &mut GrabData, _env| {
+            open_presets_window(ctx, data);
+        });
+        ui_column.add_flex_child(Flex::row().with_child(create_timer_button()).with_default_spacer()
+                                     .with_child(repeat_button).with_default_spacer()
+                                     .with_child(presets_button),1.0);
+        ui_column.add_default_spacer();
+        //PROJECT AND PDF
+        let timelapse_button = Button::new("⏱ Timelapse").on_click(|ctx, _data: &mut GrabData, _env| {
+            open_timelapse_window(ctx);
+        });
+        let record_button = Button::new("🎞 Record").on_click(|ctx, _data: &mut GrabData, _env| {
+            open_recording_window(ctx);
+        });
+        ui_column.add_flex_child(Flex::row().with_child(create_open_project_button()).with_default_spacer()
+                                     .with_child(create_pdf_bundle_button()).with_default_spacer()
+                                     .with_child(timelapse_button).with_default_spacer()
+                                     .with_child(record_button),1.0);
         ui_column.add_flex_spacer(1.0);
         //hotkey
         ui_column.add_flex_child(Label::dynamic(|data: &GrabData, _: &Env| {
-            let hotkey_text = data.hotkey.join(" + ").to_ascii_uppercase();
-            format!("Press {} to start screening", hotkey_text)
+            match data.settings.hotkeys.get(&Action::CaptureRegion) {
+                Some(hotkey) => format!("Press {} to start screening", hotkey),
+                None => "Set the hotkeys in ⚙ Settings".to_string(),
+            }
         }), 1.0);
 
 
@@ -0,0 +1,94 @@
+// FILE NAMES OF THE SAVED CAPTURES
+// the name comes from a template with the tokens {date}, {time}, {counter}, {monitor}, {width}, {height}
+// and {frame} (number of the shot in a timelapse, e.g. 0007)
+
+use std::path::{Path, PathBuf};
+use chrono::Local;
+
+pub struct TemplateValues {
+    pub counter: u32,
+    pub monitor: String,
+    pub width: u32,
+    pub height: u32,
+    pub frame: u32,
+}
+
+// characters not allowed in file names on some platform are replaced
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_whitespace() || "/\\:*?\"<>|".contains(c) { '_' } else { c })
+        .collect()
+}
+
+// file name without extension
+pub fn expand_template(template: &str, values: &TemplateValues) -> String {
+    let now = Local::now();
+    let name = template
+        .replace("{date}", &now.format("%Y-%m-%d").to_string())
+        .replace("{time}", &now.format("%H-%M-%S").to_string())
+        .replace("{counter}", &values.counter.to_string())
+        .replace("{monitor}", &values.monitor)
+        .replace("{width}", &values.width.to_string())
+        .replace("{height}", &values.height.to_string())
+        .replace("{frame}", &format!("{:04}", values.frame));
+    let name = sanitize(name.trim());
+    if name.is_empty() { "Screen".to_string() } else { name }
+}
+
+// path in the folder that does not overwrite an existing file, adding -1, -2.. to the name if needed
+pub fn unique_path(folder: &Path, name: &str, extension: &str) -> PathBuf {
+    let mut path = folder.join(format!("{}.{}", name, extension));
+    let mut number = 1;
+    while path.exists() {
+        path = folder.join(format!("{}-{}.{}", name, number, extension));
+        number += 1;
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+
+    fn values() -> TemplateValues {
+        TemplateValues { counter: 3, monitor: "Monitor 1".to_string(), width: 1920, height: 1080, frame: 7 }
+    }
+
+    #[test]
+    fn capture_tokens() {
+        assert_eq!(expand_template("{counter}-{monitor}-{width}x{height}-{frame}", &values()), "3-Monitor_1-1920x1080-0007");
+    }
+
+    #[test]
+    fn date_and_time_tokens() {
+        let format = |now: chrono::DateTime<Local>| now.format("%Y-%m-%d_%H-%M-%S").to_string();
+        let before = format(Local::now());
+        let name = expand_template("{date} {time}", &values());
+        let after = format(Local::now());
+        assert!(name == before || name == after, "{}", name);
+    }
+
+    #[test]
+    fn invalid_characters_and_empty_names() {
+        assert_eq!(expand_template("a/b\\c:d*e?f\"g<h>i|j", &values()), "a_b_c_d_e_f_g_h_i_j");
+        assert_eq!(expand_template("  ", &values()), "Screen");
+        assert_eq!(expand_template("{unknown}", &values()), "{unknown}");
+    }
+
+    #[test]
+    fn collisions_get_a_number() {
+        let folder = std::env::temp_dir().join(format!("pds_project-naming-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let first = unique_path(&folder, "Screen", "png");
+        assert_eq!(first, folder.join("Screen.png"));
+        fs::write(&first, []).unwrap();
+        let second = unique_path(&folder, "Screen", "png");
+        assert_eq!(second, folder.join("Screen-1.png"));
+        fs::write(&second, []).unwrap();
+        assert_eq!(unique_path(&folder, "Screen", "png"), folder.join("Screen-2.png"));
+        // another extension does not collide
+        assert_eq!(unique_path(&folder, "Screen", "jpg"), folder.join("Screen.jpg"));
+        let _ = fs::remove_dir_all(&folder);
+    }
+}
@@ -0,0 +1,143 @@
+// PROJECT FILES
+// zip container with the original capture (capture.png) and the document with crop and annotations
+// (document.json), so the work can be reopened and edited later
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use zip::{ZipArchive, ZipWriter};
+use zip::write::FileOptions;
+use crate::constants::PROJECT_VERSION;
+use crate::document::Document;
+
+const CAPTURE_ENTRY: &str = "capture.png";
+const DOCUMENT_ENTRY: &str = "document.json";
+
+#[derive(Serialize, Deserialize)]
+struct ProjectManifest {
+    version: u32,
+    document: Document,
+}
+
+pub fn save_project(path: &Path, capture_png: &[u8], document: &Document) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Cannot create {}: {}", path.display(), e))?;
+    let mut zip = ZipWriter::new(file);
+
+    // the png is already compressed
+    zip.start_file(CAPTURE_ENTRY, FileOptions::default().compression_method(zip::CompressionMethod::Stored))
+        .map_err(|e| e.to_string())?;
+    zip.write_all(capture_png).map_err(|e| e.to_string())?;
+
+    let manifest = ProjectManifest { version: PROJECT_VERSION, document: document.clone() };
+    zip.start_file(DOCUMENT_ENTRY, FileOptions::default()).map_err(|e| e.to_string())?;
+    serde_json::to_writer_pretty(&mut zip, &manifest).map_err(|e| e.to_string())?;
+
+    zip.finish().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// return the original capture (png bytes) and the document
+pub fn load_project(path: &Path) -> Result<(Vec<u8>, Document), String> {
+    let file = File::open(path).map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+    let mut zip = ZipArchive::new(file).map_err(|e| format!("Not a valid project: {}", e))?;
+
+    let mut capture_png = vec![];
+    zip.by_name(CAPTURE_ENTRY).map_err(|e| format!("Not a valid project: {}", e))?
+        .read_to_end(&mut capture_png).map_err(|e| e.to_string())?;
+
+    let manifest: ProjectManifest = serde_json::from_reader(zip.by_name(DOCUMENT_ENTRY).map_err(|e| format!("Not a valid project: {}", e))?)
+        .map_err(|e| format!("Not a valid project: {}", e))?;
+    if manifest.version > PROJECT_VERSION {
+        return Err(format!("The project was saved by a newer version (format {})", manifest.version));
+    }
+    let mut document = manifest.document;
+    if manifest.version < 2 {
+        document.reset_shape_widths();
+    }
+
+    Ok((capture_png, document))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use super::*;
+    use crate::Annotation;
+    use crate::document::AnnotationObject;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pds_project-{}-{}.pdsp", std::process::id(), name))
+    }
+
+    fn document() -> Document {
+        let mut document = Document::default();
+        document.crop = Some((10, 20, 300, 200));
+        let mut circle = AnnotationObject::new(Annotation::Circle, vec![(50.0, 50.0), (90.0, 80.0)], (255, 0, 0, 255));
+        circle.width = 6.0;
+        document.add(circle);
+        document.add(AnnotationObject::new(Annotation::Step, vec![(120.0, 40.0)], (0, 0, 255, 255)));
+        document
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = temp_path("save");
+        let capture = vec![1, 2, 3, 4];
+        save_project(&path, &capture, &document()).unwrap();
+        let loaded = load_project(&path);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(loaded.unwrap(), (capture, document()));
+    }
+
+    // written as the version 1, before the shapes had their own width
+    fn write_version_1(path: &Path, document: &Document) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        zip.start_file(CAPTURE_ENTRY, FileOptions::default()).unwrap();
+        zip.write_all(&[1, 2, 3]).unwrap();
+        zip.start_file(DOCUMENT_ENTRY, FileOptions::default()).unwrap();
+        serde_json::to_writer(&mut zip, &ProjectManifest { version: 1, document: document.clone() }).unwrap();
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn version_1_project() {
+        let path = temp_path("version1");
+        write_version_1(&path, &document());
+        let loaded = load_project(&path);
+        let _ = std::fs::remove_file(&path);
+        let (capture, loaded) = loaded.unwrap();
+        assert_eq!(capture, vec![1, 2, 3]);
+        assert_eq!(loaded.crop, Some((10, 20, 300, 200)));
+        assert_eq!(loaded.annotations()[1].text, "1");
+    }
+
+    #[test]
+    fn version_1_shape_widths_are_reset() {
+        // the shapes were drawn 1 pixel wide whatever their width, the highlighter used it
+        let mut document = document();
+        let mut highlighter = AnnotationObject::new(Annotation::Highlighter, vec![(0.0, 0.0), (40.0, 0.0)], (255, 255, 0, 255));
+        highlighter.width = 20.0;
+        document.add(highlighter);
+        let path = temp_path("version1-widths");
+        write_version_1(&path, &document);
+        let loaded = load_project(&path);
+        let _ = std::fs::remove_file(&path);
+        let widths: Vec<f64> = loaded.unwrap().1.annotations().iter().map(|a| a.width).collect();
+        assert_eq!(widths, vec![1.0, 1.0, 20.0]);
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let path = temp_path("newer");
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        zip.start_file(CAPTURE_ENTRY, FileOptions::default()).unwrap();
+        zip.start_file(DOCUMENT_ENTRY, FileOptions::default()).unwrap();
+        serde_json::to_writer(&mut zip, &ProjectManifest { version: PROJECT_VERSION + 1, document: Document::default() }).unwrap();
+        zip.finish().unwrap();
+
+        let loaded = load_project(&path);
+        let _ = std::fs::remove_file(&path);
+        assert!(loaded.is_err());
+    }
+}
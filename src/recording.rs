@@ -0,0 +1,260 @@
+// ANIMATED RECORDING
+// a rect of the desktop is captured at a frame rate for a duration (or until stopped) in a separate thread,
+// then the frames are encoded as an animated gif, each frame with its own palette (quantized by neuquant, with
+// optional floyd-steinberg dithering), or as an apng with all the colors. The delays of the frames come from the
+// real capture times, so a slow capture does not speed up the animation
+
+use std::borrow::Cow;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::thread;
+use std::time::{Duration, Instant};
+use color_quant::NeuQuant;
+use druid::{Data, ExtEventSink, Selector, Target};
+use image::RgbaImage;
+use image::imageops::{self, FilterType};
+use serde::{Deserialize, Serialize};
+use crate::capture::default_backend;
+use crate::constants::{GIF_SAMPLE_FACTOR, MAX_RECORD_BYTES};
+use crate::error::{Context, Error, Result};
+use crate::naming::{expand_template, TemplateValues, unique_path};
+use crate::timelapse::wait_until;
+
+pub const RECORDING_PROGRESS: Selector<RecordingProgress> = Selector::new("pds_project.recording-progress");
+
+#[derive(Clone, Copy, Data, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+}
+
+impl AnimationFormat {
+    // apng files are png files, readable also as a still image
+    pub fn extension(self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "gif",
+            AnimationFormat::Apng => "png",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RecordingJob {
+    // x, y, width, height in physical pixels of the virtual desktop
+    pub rect: (i32,i32,u32,u32),
+    // monitor name or "region", for the {monitor} token
+    pub source: String,
+    pub fps: f64,
+    pub duration: f64,
+    pub format: AnimationFormat,
+    // gif palette size and dithering
+    pub colors: u16,
+    pub dither: bool,
+    pub folder: PathBuf,
+    pub template: String,
+    pub counter: u32,
+}
+
+impl RecordingJob {
+    fn frame_count(&self) -> u32 {
+        (self.duration * self.fps).round().max(1.0) as u32
+    }
+
+    // the frames are kept in memory until the encoding
+    fn check_memory(&self) -> Result<()> {
+        let bytes = self.rect.2 as u64 * self.rect.3 as u64 * 4 * self.frame_count() as u64;
+        if bytes > MAX_RECORD_BYTES {
+            return Err(Error::Message(format!("The recording would need {} MB of memory, reduce the region, the frame rate or the duration",
+                                              bytes >> 20)));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum RecordingProgress {
+    // frames captured and total
+    Frame(u32, u32),
+    Encoding(u32),
+    Saved(PathBuf),
+    Failed(String),
+}
+
+struct CapturedFrame {
+    image: RgbaImage,
+    // since the start of the recording
+    time: Duration,
+}
+
+// start the recording, it is encoded after the last frame or when the returned flag is set
+pub fn start(sink: ExtEventSink, job: RecordingJob) -> Result<Arc<AtomicBool>> {
+    job.check_memory()?;
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
+    thread::spawn(move || run(sink, job, thread_stop));
+    Ok(stop)
+}
+
+fn record(sink: &ExtEventSink, job: &RecordingJob, stop: &AtomicBool) -> Result<Vec<CapturedFrame>> {
+    let backend = default_backend();
+    let (x, y, width, height) = job.rect;
+    let interval = Duration::from_secs_f64(1.0 / job.fps);
+    let total = job.frame_count();
+    let start = Instant::now();
+    let mut frames: Vec<CapturedFrame> = Vec::with_capacity(total as usize);
+    for index in 0..total {
+        if !wait_until(start + interval * index, stop) {
+            break;
+        }
+        let time = start.elapsed();
+        let mut image = backend.capture_rect(x, y, width, height).map_err(Error::Capture)?.to_rgba8();
+        // all the frames must have the size of the first one
+        if let Some(first) = frames.first() {
+            if image.dimensions() != first.image.dimensions() {
+                image = imageops::resize(&image, first.image.width(), first.image.height(), FilterType::Triangle);
+            }
+        }
+        frames.push(CapturedFrame { image, time });
+        let _ = sink.submit_command(RECORDING_PROGRESS, RecordingProgress::Frame(index + 1, total), Target::Auto);
+    }
+    if frames.is_empty() {
+        return Err(Error::Message("The recording was stopped before the first frame".to_string()));
+    }
+    Ok(frames)
+}
+
+// display time of each frame in the given unit (e.g. 100 for centiseconds), rounded on the absolute times
+// so that the rounding errors do not add up
+fn frame_delays(frames: &[CapturedFrame], interval: Duration, units_per_second: f64) -> Vec<u16> {
+    let to_units = |time: Duration| (time.as_secs_f64() * units_per_second).round();
+    let mut delays: Vec<u16> = frames.windows(2)
+        .map(|pair| (to_units(pair[1].time) - to_units(pair[0].time)).clamp(1.0, u16::MAX as f64) as u16)
+        .collect();
+    delays.push(to_units(interval).clamp(1.0, u16::MAX as f64) as u16);
+    delays
+}
+
+fn encode_gif(frames: Vec<CapturedFrame>, interval: Duration, colors: u16, dither: bool) -> Result<Vec<u8>> {
+    let (width, height) = frames[0].image.dimensions();
+    let (width, height) = match (u16::try_from(width), u16::try_from(height)) {
+        (Ok(width), Ok(height)) => (width, height),
+        _ => return Err(Error::Message(format!("A gif cannot be larger than {}x{} pixels", u16::MAX, u16::MAX))),
+    };
+    let delays = frame_delays(&frames, interval, 100.0);
+    let mut bytes = vec![];
+    {
+        let mut encoder = gif::Encoder::new(&mut bytes, width, height, &[])?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        for (frame, delay) in frames.into_iter().zip(delays) {
+            let mut image = frame.image;
+            let quantizer = NeuQuant::new(GIF_SAMPLE_FACTOR, colors as usize, image.as_raw());
+            if dither {
+                imageops::dither(&mut image, &quantizer);
+            }
+            let indices: Vec<u8> = image.pixels().map(|pixel| quantizer.index_of(&pixel.0) as u8).collect();
+            encoder.write_frame(&gif::Frame {
+                width,
+                height,
+                delay,
+                palette: Some(quantizer.color_map_rgb()),
+                buffer: Cow::Owned(indices),
+                ..gif::Frame::default()
+            })?;
+        }
+    }
+    Ok(bytes)
+}
+
+fn encode_apng(frames: Vec<CapturedFrame>, interval: Duration) -> Result<Vec<u8>> {
+    let (width, height) = frames[0].image.dimensions();
+    let delays = frame_delays(&frames, interval, 1000.0);
+    let mut bytes = vec![];
+    {
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        // 0 plays is an endless loop
+        encoder.set_animated(frames.len() as u32, 0)?;
+        let mut writer = encoder.write_header()?;
+        for (frame, delay) in frames.iter().zip(delays) {
+            writer.set_frame_delay(delay, 1000)?;
+            writer.write_image_data(frame.image.as_raw())?;
+        }
+        writer.finish()?;
+    }
+    Ok(bytes)
+}
+
+fn save(job: &RecordingJob, frames: Vec<CapturedFrame>) -> Result<PathBuf> {
+    let interval = Duration::from_secs_f64(1.0 / job.fps);
+    let (width, height) = frames[0].image.dimensions();
+    let bytes = match job.format {
+        AnimationFormat::Gif => encode_gif(frames, interval, job.colors, job.dither),
+        AnimationFormat::Apng => encode_apng(frames, interval),
+    }.context("Cannot encode the recording")?;
+    let values = TemplateValues { counter: job.counter, monitor: job.source.clone(), width, height, frame: 0 };
+    let path = unique_path(&job.folder, &expand_template(&job.template, &values), job.format.extension());
+    fs::write(&path, bytes).context(format!("Cannot write {}", path.display()))?;
+    Ok(path)
+}
+
+fn run(sink: ExtEventSink, job: RecordingJob, stop: Arc<AtomicBool>) {
+    let result = record(&sink, &job, &stop).and_then(|frames| {
+        let _ = sink.submit_command(RECORDING_PROGRESS, RecordingProgress::Encoding(frames.len() as u32), Target::Auto);
+        save(&job, frames)
+    });
+    let progress = match result {
+        Ok(path) => RecordingProgress::Saved(path),
+        Err(error) => RecordingProgress::Failed(error.to_string()),
+    };
+    let _ = sink.submit_command(RECORDING_PROGRESS, progress, Target::Auto);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(rect: (i32,i32,u32,u32), fps: f64, duration: f64) -> RecordingJob {
+        RecordingJob { rect, source: "region".to_string(), fps, duration, format: AnimationFormat::Gif, colors: 256,
+                       dither: false, folder: PathBuf::new(), template: "Screen{counter}".to_string(), counter: 0 }
+    }
+
+    fn frames(times: &[f64]) -> Vec<CapturedFrame> {
+        times.iter().map(|time| CapturedFrame { image: RgbaImage::new(1, 1), time: Duration::from_secs_f64(*time) }).collect()
+    }
+
+    #[test]
+    fn frame_count() {
+        assert_eq!(job((0, 0, 10, 10), 10.0, 2.5).frame_count(), 25);
+        assert_eq!(job((0, 0, 10, 10), 30.0, 0.51).frame_count(), 15);
+        assert_eq!(job((0, 0, 10, 10), 1.0, 0.1).frame_count(), 1);
+    }
+
+    #[test]
+    fn memory_cap() {
+        // 8 MB a frame: 240 frames fit in 2 GB, 300 do not
+        assert!(job((0, 0, 1920, 1080), 4.0, 60.0).check_memory().is_ok());
+        assert!(job((0, 0, 1920, 1080), 5.0, 60.0).check_memory().is_err());
+        assert!(job((0, 0, 640, 360), 30.0, 60.0).check_memory().is_ok());
+    }
+
+    #[test]
+    fn delays_follow_the_capture_times() {
+        // centiseconds rounded on the absolute times: 0, 3, 7, 10
+        let delays = frame_delays(&frames(&[0.0, 0.033, 0.071, 0.1]), Duration::from_secs_f64(1.0 / 30.0), 100.0);
+        assert_eq!(delays, vec![3, 4, 3, 3]);
+        // the sum is the recorded time, without the rounding errors of each frame
+        let delays = frame_delays(&frames(&[0.0, 0.015, 0.03, 0.045, 0.06]), Duration::from_millis(15), 100.0);
+        assert_eq!(delays[..4].iter().sum::<u16>(), 6);
+    }
+
+    #[test]
+    fn delays_are_at_least_one_unit() {
+        let delays = frame_delays(&frames(&[0.0, 0.001, 0.5]), Duration::from_millis(1), 100.0);
+        assert_eq!(delays, vec![1, 50, 1]);
+        assert_eq!(frame_delays(&frames(&[0.0]), Duration::from_secs(1000), 100.0), vec![u16::MAX]);
+    }
+}
@@ -0,0 +1,659 @@
+// PERSISTED SETTINGS
+// the user preferences, kept apart from the runtime state of the editor: loaded once at startup,
+// missing fields take the default value and files of older versions are migrated.
+// The file is in the user config directory (or the one given with --config) and holds named profiles,
+// the fields of the active one are copied in Settings while it is in use
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use druid::{lens, Data, Lens};
+use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+use crate::constants::{CONFIG_DIR_NAME, DEFAULT_FILE_TEMPLATE, DEFAULT_PROFILE, DEFAULT_QUALITY, DEFAULT_TIMELAPSE_TEMPLATE, LAST_REGIONS_LIMIT,
+                       MAX_CORNER_RADIUS, MAX_DELAY, MAX_RECORD_DURATION, MAX_RECORD_FPS, MAX_REDACT_STRENGTH, MAX_STROKE_WIDTH, MIN_GIF_COLORS, MIN_REDACT_STRENGTH,
+                       MIN_STROKE_WIDTH, MIN_TIMELAPSE_INTERVAL, SETTINGS_FILE, SETTINGS_VERSION};
+use crate::hotkey::{default_hotkeys, Action, Hotkey};
+use crate::Annotation;
+use crate::document::LineStyle;
+use crate::recording::AnimationFormat;
+
+// settings file given on the command line
+static CONFIG_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+pub fn set_config_path(path: PathBuf) {
+    let _ = CONFIG_OVERRIDE.set(path);
+}
+
+// preferences that change with the profile
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    pub save_path: Box<Path>,
+    pub save_format: String,
+    pub hotkeys: BTreeMap<Action, Hotkey>,
+    pub color: (u8,u8,u8,u8),
+    pub quality: f64,
+    pub lossless: bool,
+    pub auto_save: bool,
+    pub file_template: String,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Settings::default().current_profile()
+    }
+}
+
+// last region selected with the overlay under a monitor layout (see capture::layout_signature)
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct LastRegion {
+    pub layout: String,
+    // x, y, width, height in physical pixels of the virtual desktop
+    pub rect: (i32,i32,u32,u32),
+}
+
+// named fixed region, e.g. "browser viewport" 1280x720 at 0,80 of a monitor
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct RegionPreset {
+    pub name: String,
+    // id of the monitor given by the capture backend (MonitorInfo::id)
+    pub monitor_id: u32,
+    // rect relative to the top left corner of the monitor, in physical pixels
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// style of a shape tool, applied to the shapes drawn with it
+#[derive(Clone, Data, Serialize, Deserialize, Debug, Lens, PartialEq)]
+#[serde(default)]
+pub struct ShapeStyle {
+    // in screen pixels, as the highlighter width
+    pub width: f64,
+    pub line_style: LineStyle,
+    // only for the closed shapes
+    pub fill: bool,
+    pub fill_color: (u8,u8,u8,u8),
+}
+
+impl Default for ShapeStyle {
+    fn default() -> Self {
+        ShapeStyle { width: MIN_STROKE_WIDTH, line_style: LineStyle::Solid, fill: false, fill_color: (255, 255, 255, 128) }
+    }
+}
+
+#[derive(Clone, Data, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct ShapeStyles {
+    pub circle: ShapeStyle,
+    pub rectangle: ShapeStyle,
+    pub line: ShapeStyle,
+    pub cross: ShapeStyle,
+    pub arrow: ShapeStyle,
+    pub ellipse: ShapeStyle,
+    pub rounded_rectangle: ShapeStyle,
+}
+
+impl ShapeStyles {
+    pub fn style(&self, kind: &Annotation) -> Option<&ShapeStyle> {
+        match kind {
+            Annotation::Circle => Some(&self.circle),
+            Annotation::Rectangle => Some(&self.rectangle),
+            Annotation::Line => Some(&self.line),
+            Annotation::Cross => Some(&self.cross),
+            Annotation::Arrow => Some(&self.arrow),
+            Annotation::Ellipse => Some(&self.ellipse),
+            Annotation::RoundedRectangle => Some(&self.rounded_rectangle),
+            _ => None,
+        }
+    }
+
+    pub fn style_mut(&mut self, kind: &Annotation) -> Option<&mut ShapeStyle> {
+        match kind {
+            Annotation::Circle => Some(&mut self.circle),
+            Annotation::Rectangle => Some(&mut self.rectangle),
+            Annotation::Line => Some(&mut self.line),
+            Annotation::Cross => Some(&mut self.cross),
+            Annotation::Arrow => Some(&mut self.arrow),
+            Annotation::Ellipse => Some(&mut self.ellipse),
+            Annotation::RoundedRectangle => Some(&mut self.rounded_rectangle),
+            _ => None,
+        }
+    }
+
+    fn all_mut(&mut self) -> [&mut ShapeStyle; 7] {
+        [&mut self.circle, &mut self.rectangle, &mut self.line, &mut self.cross, &mut self.arrow,
+         &mut self.ellipse, &mut self.rounded_rectangle]
+    }
+}
+
+// lens to the style of one tool, for the controls of the edit window
+pub fn shape_style_lens(kind: Annotation) -> impl Lens<ShapeStyles, ShapeStyle> + Clone {
+    let put_kind = kind.clone();
+    lens::Map::new(
+        move |styles: &ShapeStyles| styles.style(&kind).cloned().unwrap_or_default(),
+        move |styles: &mut ShapeStyles, style: ShapeStyle| {
+            if let Some(current) = styles.style_mut(&put_kind) {
+                *current = style;
+            }
+        })
+}
+
+#[derive(Clone, Data, Serialize, Deserialize, Debug, Lens, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u32,
+    // name of the active profile, and all the profiles as last saved
+    pub profile: String,
+    #[data(ignore)]
+    pub profiles: Vec<Profile>,
+    // counter of the automatic file names
+    pub screenshot_number: u32,
+    #[data(ignore)]
+    pub save_path: Box<Path>,
+    pub save_format: String,
+    // the actions without a hotkey are missing
+    #[data(ignore)]
+    pub hotkeys: BTreeMap<Action, Hotkey>,
+    // seconds of the timer, fractions allowed
+    pub delay: f64,
+    pub color: (u8,u8,u8,u8),
+    pub text_size: f64,
+    pub highlighter_width: f64,
+    // stroke, line style and fill of each shape tool
+    pub shape_styles: ShapeStyles,
+    // corners of the rounded rectangle tool, in screen pixels
+    pub corner_radius: f64,
+    // block size of the pixelate tool and strength of the blur tool, in screen pixels (in pixels of the capture
+    // once scaled by tool_width)
+    pub pixelate_size: f64,
+    pub blur_strength: f64,
+    // monitor chosen for the capture (index in the backend monitor list), None for all the monitors
+    pub selected_monitor: Option<usize>,
+    // encoding quality (0-100) of the lossy formats and lossless webp
+    pub quality: f64,
+    pub lossless: bool,
+    // save directly into save_path without the file dialog, the name comes from file_template
+    pub auto_save: bool,
+    pub file_template: String,
+    // most recent first
+    #[data(ignore)]
+    pub last_regions: Vec<LastRegion>,
+    #[data(ignore)]
+    pub region_presets: Vec<RegionPreset>,
+    // timelapse: seconds between the shots, number of shots (0 until stopped) and file names
+    pub timelapse_interval: f64,
+    pub timelapse_shots: u32,
+    pub timelapse_template: String,
+    // animated recording: frames per second, seconds, format and gif palette options
+    pub record_fps: f64,
+    pub record_duration: f64,
+    pub record_format: AnimationFormat,
+    pub gif_colors: u16,
+    pub gif_dither: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            version: SETTINGS_VERSION,
+            profile: DEFAULT_PROFILE.to_string(),
+            profiles: vec![],
+            screenshot_number: 1,
+            save_path: Path::new("").into(),
+            save_format: "png".to_string(),
+            hotkeys: default_hotkeys(),
+            delay: 1.0,
+            color: (255, 255, 255, 255),
+            text_size: 10.0,
+            highlighter_width: 20.0,
+            shape_styles: ShapeStyles::default(),
+            corner_radius: 10.0,
+            pixelate_size: 12.0,
+            blur_strength: 10.0,
+            selected_monitor: None,
+            quality: DEFAULT_QUALITY,
+            lossless: false,
+            auto_save: false,
+            file_template: DEFAULT_FILE_TEMPLATE.to_string(),
+            last_regions: vec![],
+            region_presets: vec![],
+            timelapse_interval: 5.0,
+            timelapse_shots: 10,
+            timelapse_template: DEFAULT_TIMELAPSE_TEMPLATE.to_string(),
+            record_fps: 10.0,
+            record_duration: 5.0,
+            record_format: AnimationFormat::Gif,
+            gif_colors: 256,
+            gif_dither: true,
+        }
+    }
+}
+
+// runtime fields written in the settings file by the versions without Settings (version 0)
+const RUNTIME_FIELDS: [&str; 17] = ["image_data_old", "image_data_new", "press", "first_screen", "scale_factors", "image_size",
+    "positions", "offsets", "hotkey_new", "hotkey_pressed", "set_hot_key", "input_hotkey_error", "trigger_ui",
+    "annotation", "text_annotation", "timer_requested", "image_copied"];
+
+// up to version 3 only the region capture had a hotkey, up to version 2 it was the list of the recorded keys
+// (e.g. ["Control", "s"]); hotkeys that cannot be parsed are dropped, instead of invalidating the file
+fn migrate_hotkeys(fields: &mut Map<String, Value>) {
+    if let Some(old) = fields.remove("hotkey") {
+        let text = match old {
+            Value::Array(keys) => keys.iter().filter_map(Value::as_str).collect::<Vec<_>>().join("+"),
+            Value::String(text) => text,
+            _ => String::new(),
+        };
+        let mut hotkeys = default_hotkeys();
+        match text.parse::<Hotkey>() {
+            Ok(hotkey) => {
+                // the old hotkey wins over a default using the same keys
+                hotkeys.retain(|_, other| *other != hotkey);
+                hotkeys.insert(Action::CaptureRegion, hotkey);
+            }
+            Err(error) => eprintln!("Invalid hotkey {} in the settings, using the default: {}", text, error),
+        }
+        fields.insert("hotkeys".to_string(), serde_json::to_value(hotkeys).unwrap_or_default());
+    }
+    if let Some(Value::Object(hotkeys)) = fields.get_mut("hotkeys") {
+        hotkeys.retain(|action, hotkey| {
+            let valid = serde_json::from_value::<Action>(Value::from(action.as_str())).is_ok()
+                && hotkey.as_str().is_some_and(|hotkey| hotkey.parse::<Hotkey>().is_ok());
+            if !valid {
+                eprintln!("Invalid hotkey {} for {} in the settings, it is removed", hotkey, action);
+            }
+            valid
+        });
+    }
+}
+
+// bring a settings file of an older version to the current one
+fn migrate(mut fields: Map<String, Value>) -> Map<String, Value> {
+    let version = fields.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version < 1 {
+        // version 0 was the whole application state
+        for field in RUNTIME_FIELDS {
+            fields.remove(field);
+        }
+    }
+    if version < 2 {
+        // the preferences of version 1 become the default profile (created when sanitized)
+        fields.insert("profile".to_string(), Value::from(DEFAULT_PROFILE));
+        fields.remove("profiles");
+    }
+    migrate_hotkeys(&mut fields);
+    if let Some(Value::Array(profiles)) = fields.get_mut("profiles") {
+        for profile in profiles.iter_mut() {
+            if let Value::Object(profile) = profile {
+                migrate_hotkeys(profile);
+            }
+        }
+    }
+    fields.insert("version".to_string(), Value::from(SETTINGS_VERSION));
+    fields
+}
+
+impl Settings {
+    // the --config file, or settings.json in the user config directory
+    pub fn path() -> PathBuf {
+        if let Some(path) = CONFIG_OVERRIDE.get() {
+            return path.clone();
+        }
+        match dirs::config_dir() {
+            Some(config_dir) => config_dir.join(CONFIG_DIR_NAME).join(SETTINGS_FILE),
+            None => PathBuf::from(SETTINGS_FILE),
+        }
+    }
+
+    // settings from the file, the defaults if it does not exist or cannot be read
+    pub fn load() -> Settings {
+        let mut path = Settings::path();
+        // the versions before used the working directory
+        if !path.exists() && CONFIG_OVERRIDE.get().is_none() && Path::new(SETTINGS_FILE).exists() {
+            path = PathBuf::from(SETTINGS_FILE);
+        }
+        let settings = match fs::read(&path) {
+            Ok(bytes) => Settings::from_json(&bytes).unwrap_or_else(|error| {
+                eprintln!("Invalid settings file {}, using the defaults: {}", path.display(), error);
+                Settings::default()
+            }),
+            Err(_) => Settings::default(),
+        };
+        settings.sanitized()
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Settings, String> {
+        match serde_json::from_slice::<Value>(bytes).map_err(|e| e.to_string())? {
+            Value::Object(fields) => serde_json::from_value(Value::Object(migrate(fields))).map_err(|e| e.to_string()),
+            _ => Err("the settings are not a json object".to_string()),
+        }
+    }
+
+    // values out of range (e.g. edited by hand) are replaced
+    fn sanitized(mut self) -> Settings {
+        let defaults = Settings::default();
+        if !self.delay.is_finite() || self.delay < 0.0 {
+            self.delay = defaults.delay;
+        }
+        self.delay = self.delay.min(MAX_DELAY);
+        if !self.timelapse_interval.is_finite() || self.timelapse_interval < MIN_TIMELAPSE_INTERVAL {
+            self.timelapse_interval = defaults.timelapse_interval;
+        }
+        if self.timelapse_template.trim().is_empty() {
+            self.timelapse_template = defaults.timelapse_template;
+        }
+        if !self.record_fps.is_finite() || !(1.0..=MAX_RECORD_FPS).contains(&self.record_fps) {
+            self.record_fps = defaults.record_fps;
+        }
+        if !self.record_duration.is_finite() || self.record_duration <= 0.0 {
+            self.record_duration = defaults.record_duration;
+        }
+        self.record_duration = self.record_duration.min(MAX_RECORD_DURATION);
+        self.gif_colors = self.gif_colors.clamp(MIN_GIF_COLORS, 256);
+        for style in self.shape_styles.all_mut() {
+            if !(MIN_STROKE_WIDTH..=MAX_STROKE_WIDTH).contains(&style.width) {
+                style.width = MIN_STROKE_WIDTH;
+            }
+        }
+        if !(0.0..=MAX_CORNER_RADIUS).contains(&self.corner_radius) {
+            self.corner_radius = defaults.corner_radius;
+        }
+        if !(MIN_REDACT_STRENGTH..=MAX_REDACT_STRENGTH).contains(&self.pixelate_size) {
+            self.pixelate_size = defaults.pixelate_size;
+        }
+        if !(MIN_REDACT_STRENGTH..=MAX_REDACT_STRENGTH).contains(&self.blur_strength) {
+            self.blur_strength = defaults.blur_strength;
+        }
+        if !(0.0..=100.0).contains(&self.quality) {
+            self.quality = defaults.quality;
+        }
+        if self.file_template.trim().is_empty() {
+            self.file_template = defaults.file_template;
+        }
+        if self.profile.trim().is_empty() {
+            self.profile = defaults.profile;
+        }
+        if !self.profiles.iter().any(|p| p.name == self.profile) {
+            self.profiles.push(self.current_profile());
+        }
+        self
+    }
+
+    pub fn profile_names(&self) -> Vec<String> {
+        self.profiles.iter().map(|p| p.name.clone()).collect()
+    }
+
+    // the active profile with the values in use
+    pub fn current_profile(&self) -> Profile {
+        Profile {
+            name: self.profile.clone(),
+            save_path: self.save_path.clone(),
+            save_format: self.save_format.clone(),
+            hotkeys: self.hotkeys.clone(),
+            color: self.color,
+            quality: self.quality,
+            lossless: self.lossless,
+            auto_save: self.auto_save,
+            file_template: self.file_template.clone(),
+        }
+    }
+
+    // store the values in use in the active profile
+    fn store_profile(&mut self) {
+        let current = self.current_profile();
+        match self.profiles.iter_mut().find(|p| p.name == current.name) {
+            Some(profile) => *profile = current,
+            None => self.profiles.push(current),
+        }
+    }
+
+    // activate the profile with the given name, a new one is created from the values in use
+    pub fn switch_profile(&mut self, name: &str) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("The profile name cannot be empty".to_string());
+        }
+        self.store_profile();
+        let profile = self.profiles.iter().find(|p| p.name == name).cloned()
+            .unwrap_or(Profile { name: name.to_string(), ..self.current_profile() });
+        self.profile = profile.name.clone();
+        self.save_path = profile.save_path;
+        self.save_format = profile.save_format;
+        self.hotkeys = profile.hotkeys;
+        self.color = profile.color;
+        self.quality = profile.quality;
+        self.lossless = profile.lossless;
+        self.auto_save = profile.auto_save;
+        self.file_template = profile.file_template;
+        self.store_profile();
+        Ok(())
+    }
+
+    // the active profile cannot be deleted
+    pub fn delete_profile(&mut self, name: &str) -> Result<(), String> {
+        if name == self.profile {
+            return Err("The active profile cannot be deleted".to_string());
+        }
+        self.profiles.retain(|p| p.name != name);
+        Ok(())
+    }
+
+    // the last region selected under the monitor layout
+    pub fn last_region(&self, layout: &str) -> Option<(i32,i32,u32,u32)> {
+        self.last_regions.iter().find(|region| region.layout == layout).map(|region| region.rect)
+    }
+
+    pub fn remember_region(&mut self, layout: String, rect: (i32,i32,u32,u32)) {
+        self.last_regions.retain(|region| region.layout != layout);
+        self.last_regions.insert(0, LastRegion { layout, rect });
+        self.last_regions.truncate(LAST_REGIONS_LIMIT);
+    }
+
+    pub fn find_preset(&self, name: &str) -> Option<&RegionPreset> {
+        self.region_presets.iter().find(|preset| preset.name == name)
+    }
+
+    // a preset with the same name is replaced
+    pub fn add_preset(&mut self, preset: RegionPreset) -> Result<(), String> {
+        if preset.name.trim().is_empty() {
+            return Err("The preset name cannot be empty".to_string());
+        }
+        match self.region_presets.iter_mut().find(|other| other.name == preset.name) {
+            Some(other) => *other = preset,
+            None => self.region_presets.push(preset),
+        }
+        Ok(())
+    }
+
+    pub fn delete_preset(&mut self, name: &str) {
+        self.region_presets.retain(|preset| preset.name != name);
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        self.save_to(&Settings::path())
+    }
+
+    // write a temporary file and rename it, so an interrupted save never leaves a truncated file
+    fn save_to(&self, path: &Path) -> Result<(), String> {
+        if let Some(folder) = path.parent().filter(|folder| !folder.as_os_str().is_empty()) {
+            fs::create_dir_all(folder).map_err(|e| format!("Cannot create {}: {}", folder.display(), e))?;
+        }
+        let temporary = path.with_extension("json.tmp");
+        let mut settings = self.clone();
+        settings.store_profile();
+        let json = serde_json::to_vec_pretty(&settings).map_err(|e| e.to_string())?;
+        let mut file = File::create(&temporary).map_err(|e| format!("Cannot write {}: {}", temporary.display(), e))?;
+        file.write_all(&json).and_then(|_| file.sync_all()).map_err(|e| format!("Cannot write {}: {}", temporary.display(), e))?;
+        fs::rename(&temporary, path).map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_0_application_state() {
+        // the whole GrabData of the first versions, with the hotkey as the recorded keys
+        let json = r#"{
+            "screenshot_number": 12, "image_data_old": [1, 2], "image_data_new": [], "save_path": "/tmp/captures",
+            "save_format": "jpg", "press": false, "first_screen": true, "scale_factors": [1.0, 1.0], "image_size": [0.0, 0.0],
+            "positions": [], "offsets": [0.0, 0.0], "hotkey": ["Control", "Shift", "a"], "hotkey_new": [], "hotkey_pressed": [],
+            "set_hot_key": false, "delay": 2.5, "input_hotkey_error": [false, ""], "trigger_ui": false, "annotation": "Circle",
+            "color": [255, 0, 0, 255], "text_annotation": "", "text_size": 20.0, "highlighter_width": 15.0,
+            "timer_requested": false, "image_copied": false
+        }"#;
+        let settings = Settings::from_json(json.as_bytes()).unwrap().sanitized();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.profile, DEFAULT_PROFILE);
+        assert_eq!(settings.screenshot_number, 12);
+        assert_eq!(&*settings.save_path, Path::new("/tmp/captures"));
+        assert_eq!(settings.save_format, "jpg");
+        assert_eq!(settings.delay, 2.5);
+        assert_eq!(settings.color, (255, 0, 0, 255));
+        assert_eq!(settings.hotkeys[&Action::CaptureRegion], "Ctrl+Shift+A".parse().unwrap());
+        // the fields added later have their defaults
+        assert_eq!(settings.shape_styles, ShapeStyles::default());
+        assert_eq!(settings.record_format, AnimationFormat::Gif);
+    }
+
+    #[test]
+    fn version_3_hotkey_takes_the_keys_of_a_default() {
+        let json = r#"{ "version": 3, "profile": "default", "hotkey": "Ctrl+S" }"#;
+        let settings = Settings::from_json(json.as_bytes()).unwrap();
+        assert_eq!(settings.hotkeys[&Action::CaptureRegion], "Ctrl+S".parse().unwrap());
+        // the default of Save used the same keys
+        assert!(!settings.hotkeys.contains_key(&Action::Save));
+        assert!(settings.hotkeys.contains_key(&Action::CopyToClipboard));
+    }
+
+    #[test]
+    fn invalid_hotkeys_are_dropped() {
+        let json = r#"{ "version": 2, "hotkey": ["Control"] }"#;
+        assert_eq!(Settings::from_json(json.as_bytes()).unwrap().hotkeys, default_hotkeys());
+
+        let json = r#"{ "version": 4, "hotkeys": { "save": "Ctrl+", "unknown_action": "Ctrl+A", "capture_desktop": "Alt+D" } }"#;
+        let hotkeys = Settings::from_json(json.as_bytes()).unwrap().hotkeys;
+        assert_eq!(hotkeys.len(), 1);
+        assert_eq!(hotkeys[&Action::CaptureDesktop], "Alt+D".parse().unwrap());
+    }
+
+    #[test]
+    fn out_of_range_values_are_replaced() {
+        let json = r#"{ "version": 4, "delay": -1.0, "corner_radius": 1000.0, "pixelate_size": 12.0 }"#;
+        let settings = Settings::from_json(json.as_bytes()).unwrap().sanitized();
+        let defaults = Settings::default();
+        assert_eq!(settings.delay, defaults.delay);
+        assert_eq!(settings.corner_radius, defaults.corner_radius);
+        assert_eq!(settings.pixelate_size, 12.0);
+    }
+
+    #[test]
+    fn not_an_object() {
+        assert!(Settings::from_json(b"[1, 2]").is_err());
+        assert!(Settings::from_json(b"{").is_err());
+    }
+
+    #[test]
+    fn switch_profile() {
+        let mut settings = Settings::default().sanitized();
+        settings.save_format = "jpg".to_string();
+        settings.switch_profile("work").unwrap();
+        // a new profile starts from the values in use, the previous one keeps them
+        assert_eq!(settings.profile, "work");
+        assert_eq!(settings.profile_names(), vec![DEFAULT_PROFILE.to_string(), "work".to_string()]);
+        assert_eq!(settings.save_format, "jpg");
+
+        settings.save_format = "webp".to_string();
+        settings.color = (0, 0, 255, 255);
+        settings.switch_profile(DEFAULT_PROFILE).unwrap();
+        assert_eq!(settings.save_format, "jpg");
+        assert_eq!(settings.color, Settings::default().color);
+        settings.switch_profile(" work ").unwrap();
+        assert_eq!(settings.save_format, "webp");
+        assert_eq!(settings.color, (0, 0, 255, 255));
+        assert_eq!(settings.profiles.len(), 2);
+
+        assert!(settings.switch_profile("  ").is_err());
+        assert_eq!(settings.profile, "work");
+    }
+
+    #[test]
+    fn delete_profile() {
+        let mut settings = Settings::default().sanitized();
+        // the last profile is the active one
+        assert!(settings.delete_profile(DEFAULT_PROFILE).is_err());
+        assert_eq!(settings.profile_names(), vec![DEFAULT_PROFILE.to_string()]);
+
+        settings.switch_profile("work").unwrap();
+        assert!(settings.delete_profile("work").is_err());
+        settings.delete_profile(DEFAULT_PROFILE).unwrap();
+        assert_eq!(settings.profile_names(), vec!["work".to_string()]);
+        // deleting a missing profile changes nothing
+        settings.delete_profile("missing").unwrap();
+        assert_eq!(settings.profile_names(), vec!["work".to_string()]);
+    }
+
+    #[test]
+    fn sanitized_creates_the_active_profile() {
+        let json = r#"{ "version": 4, "profile": " ", "profiles": [], "quality": 250.0, "file_template": "",
+            "shape_styles": { "arrow": { "width": 0.0 } } }"#;
+        let settings = Settings::from_json(json.as_bytes()).unwrap().sanitized();
+        let defaults = Settings::default();
+        assert_eq!(settings.profile, DEFAULT_PROFILE);
+        assert_eq!(settings.profile_names(), vec![DEFAULT_PROFILE.to_string()]);
+        assert_eq!(settings.quality, defaults.quality);
+        assert_eq!(settings.file_template, defaults.file_template);
+        assert_eq!(settings.shape_styles.arrow.width, MIN_STROKE_WIDTH);
+
+        let json = r#"{ "version": 4, "profile": "work", "profiles": [{ "name": "work", "save_format": "gif" }] }"#;
+        let settings = Settings::from_json(json.as_bytes()).unwrap().sanitized();
+        assert_eq!(settings.profile_names(), vec!["work".to_string()]);
+    }
+
+    #[test]
+    fn regions_are_remembered_per_layout() {
+        let mut settings = Settings::default();
+        settings.remember_region("1920x1080+0+0".to_string(), (0, 0, 100, 100));
+        settings.remember_region("1920x1080+0+0,1280x1024+1920+0".to_string(), (2000, 10, 300, 200));
+        // a new region of the same layout replaces the old one and becomes the most recent
+        settings.remember_region("1920x1080+0+0".to_string(), (5, 5, 50, 50));
+        assert_eq!(settings.last_regions.len(), 2);
+        assert_eq!(settings.last_regions[0].layout, "1920x1080+0+0");
+        assert_eq!(settings.last_region("1920x1080+0+0"), Some((5, 5, 50, 50)));
+        assert_eq!(settings.last_region("1920x1080+0+0,1280x1024+1920+0"), Some((2000, 10, 300, 200)));
+        assert_eq!(settings.last_region("1280x1024+0+0"), None);
+
+        // the oldest layouts are forgotten
+        for width in 0..LAST_REGIONS_LIMIT as u32 {
+            settings.remember_region(format!("{}x1080+0+0", 800 + width), (0, 0, 10, 10));
+        }
+        assert_eq!(settings.last_regions.len(), LAST_REGIONS_LIMIT);
+        assert_eq!(settings.last_region("1920x1080+0+0"), None);
+    }
+
+    #[test]
+    fn save_replaces_the_file() {
+        let folder = std::env::temp_dir().join(format!("pds_project-settings-{}", std::process::id()));
+        let path = folder.join(SETTINGS_FILE);
+        let mut settings = Settings::default().sanitized();
+        settings.save_to(&path).unwrap();
+        settings.screenshot_number = 7;
+        settings.save_format = "bmp".to_string();
+        settings.save_to(&path).unwrap();
+
+        // the temporary file is renamed, the profile is stored with the values in use
+        assert!(!path.with_extension("json.tmp").exists());
+        let saved = Settings::from_json(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(saved.screenshot_number, 7);
+        assert_eq!(saved.profiles, vec![settings.current_profile()]);
+        assert_eq!(saved.profiles[0].save_format, "bmp");
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
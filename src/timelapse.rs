@@ -0,0 +1,192 @@
+// TIMELAPSE
+// interval and burst capture: a rect of the desktop is captured every interval seconds for a number of shots
+// (0 means until stopped) in a separate thread, through the same capture path of screen_all. Each frame is
+// saved in the save folder, named by the timelapse template, and the progress is sent to the application
+// with the TIMELAPSE_PROGRESS command
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use druid::{ExtEventSink, Selector, Target};
+use crate::constants::TIMELAPSE_STOP_POLL_MS;
+use crate::error::{Context, Result};
+use crate::naming::{expand_template, TemplateValues, unique_path};
+use crate::utilities::{capture_rect, encode_image};
+
+pub const TIMELAPSE_PROGRESS: Selector<TimelapseProgress> = Selector::new("pds_project.timelapse-progress");
+
+#[derive(Clone, Debug)]
+pub struct TimelapseJob {
+    // x, y, width, height in physical pixels of the virtual desktop
+    pub rect: (i32,i32,u32,u32),
+    // monitor name or "region", for the {monitor} token
+    pub source: String,
+    pub interval: f64,
+    pub shots: u32,
+    pub folder: PathBuf,
+    pub template: String,
+    // value of {counter}, the same for all the frames of a run
+    pub counter: u32,
+    pub format: String,
+    pub quality: u8,
+    pub lossless: bool,
+}
+
+#[derive(Clone, Debug)]
+pub enum TimelapseProgress {
+    Saved(u32, PathBuf),
+    Failed(u32, String),
+    // number of frames taken
+    Finished(u32),
+}
+
+// start the run, it stops after the last shot or when the returned flag is set
+pub fn start(sink: ExtEventSink, job: TimelapseJob) -> Arc<AtomicBool> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
+    thread::spawn(move || run(sink, job, thread_stop));
+    stop
+}
+
+// false if stopped while waiting
+pub fn wait_until(instant: Instant, stop: &AtomicBool) -> bool {
+    loop {
+        if stop.load(Ordering::SeqCst) {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= instant {
+            return true;
+        }
+        thread::sleep((instant - now).min(Duration::from_millis(TIMELAPSE_STOP_POLL_MS)));
+    }
+}
+
+// when the shot after `taken` frames is due, None after the last one: the first shot is immediate, the next ones
+// follow a fixed schedule that a slow capture does not shift
+fn next_shot(start: Instant, interval: Duration, shots: u32, taken: u32) -> Option<Instant> {
+    if shots != 0 && taken >= shots {
+        return None;
+    }
+    Some(start + interval * taken)
+}
+
+// call shot with the number of each frame when it is due, until the last one, the stop flag or shot returning false;
+// the number of frames taken is returned
+fn run_schedule(interval: Duration, shots: u32, stop: &AtomicBool, mut shot: impl FnMut(u32) -> bool) -> u32 {
+    let start = Instant::now();
+    let mut frame = 0;
+    while let Some(due) = next_shot(start, interval, shots, frame) {
+        if !wait_until(due, stop) {
+            break;
+        }
+        frame += 1;
+        if !shot(frame) {
+            break;
+        }
+    }
+    frame
+}
+
+fn save_frame(job: &TimelapseJob, frame: u32) -> Result<PathBuf> {
+    let (x, y, width, height) = job.rect;
+    let image = capture_rect(x, y, width, height)?;
+    let bytes = encode_image(&image, &job.format, job.quality, job.lossless)?;
+    let values = TemplateValues { counter: job.counter, monitor: job.source.clone(), width: image.width(), height: image.height(), frame };
+    let path = unique_path(&job.folder, &expand_template(&job.template, &values), &job.format);
+    fs::write(&path, bytes).context(format!("Cannot write {}", path.display()))?;
+    Ok(path)
+}
+
+fn run(sink: ExtEventSink, job: TimelapseJob, stop: Arc<AtomicBool>) {
+    let mut closed = false;
+    let frames = run_schedule(Duration::from_secs_f64(job.interval), job.shots, &stop, |frame| {
+        // a failed frame (e.g. a monitor turned off) does not stop the run
+        let progress = match save_frame(&job, frame) {
+            Ok(path) => TimelapseProgress::Saved(frame, path),
+            Err(error) => TimelapseProgress::Failed(frame, error.to_string()),
+        };
+        // the application has been closed
+        closed = sink.submit_command(TIMELAPSE_PROGRESS, progress, Target::Auto).is_err();
+        !closed
+    });
+    if !closed {
+        let _ = sink.submit_command(TIMELAPSE_PROGRESS, TimelapseProgress::Finished(frames), Target::Auto);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schedule() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(2500);
+        assert_eq!(next_shot(start, interval, 3, 0), Some(start));
+        assert_eq!(next_shot(start, interval, 3, 2), Some(start + Duration::from_secs(5)));
+        assert_eq!(next_shot(start, interval, 3, 3), None);
+        // until stopped
+        assert_eq!(next_shot(start, interval, 0, 1000), Some(start + Duration::from_secs(2500)));
+    }
+
+    #[test]
+    fn slow_shots_do_not_drift() {
+        let stop = AtomicBool::new(false);
+        let start = Instant::now();
+        let mut times = vec![];
+        let frames = run_schedule(Duration::from_millis(40), 5, &stop, |_| {
+            times.push(start.elapsed());
+            thread::sleep(Duration::from_millis(25));
+            true
+        });
+        assert_eq!(frames, 5);
+        assert_eq!(times.len(), 5);
+        // the last shot is due after 4 intervals, not after 4 intervals and 4 shots
+        assert!(times[4] >= Duration::from_millis(160), "{:?}", times);
+        assert!(times[4] < Duration::from_millis(230), "{:?}", times);
+    }
+
+    #[test]
+    fn stopped_runs() {
+        // the flag set during a shot stops the run before the next one
+        let stop = AtomicBool::new(false);
+        let frames = run_schedule(Duration::from_millis(10), 0, &stop, |frame| {
+            if frame == 3 {
+                stop.store(true, Ordering::SeqCst);
+            }
+            true
+        });
+        assert_eq!(frames, 3);
+        // the application closed
+        let frames = run_schedule(Duration::from_millis(10), 5, &AtomicBool::new(false), |frame| frame < 2);
+        assert_eq!(frames, 2);
+        // stopped before the first shot
+        let mut taken = 0;
+        assert_eq!(run_schedule(Duration::from_millis(10), 5, &stop, |_| { taken += 1; true }), 0);
+        assert_eq!(taken, 0);
+    }
+
+    #[test]
+    fn wait_until_stops_early() {
+        let stop = AtomicBool::new(false);
+        assert!(wait_until(Instant::now(), &stop));
+        let started = Instant::now();
+        assert!(wait_until(started + Duration::from_millis(30), &stop));
+        assert!(started.elapsed() >= Duration::from_millis(30));
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            thread_stop.store(true, Ordering::SeqCst);
+        });
+        let started = Instant::now();
+        assert!(!wait_until(started + Duration::from_secs(60), &stop));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        stopper.join().unwrap();
+    }
+}
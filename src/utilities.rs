@@ -1,11 +1,19 @@
 // IMAGE SCREEN FUNCTIONS
 
-use std::f64::consts::PI;
-use druid::{EventCtx, Point};
-use image::{DynamicImage, GenericImage, load_from_memory_with_format};
-use screenshots::Screen;
+use std::borrow::Cow;
+use druid::{EventCtx, Point, Rect};
+use image::{ColorType, DynamicImage, EncodableLayout, ImageEncoder, ImageFormat, load_from_memory_with_format};
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::{WebPEncoder, WebPQuality};
 use crate::{Annotation, GrabData};
-use crate::constants::{BORDER_WIDTH, BUTTON_HEIGHT, NORMAL_BIG_IMAGE_LIMIT, OFFSET_X, OFFSET_Y, SMALL_IMAGE_LIMIT};
+use crate::settings::RegionPreset;
+use crate::error::{Context, Error, Result};
+use crate::capture::{CaptureBackend, default_backend, is_on_monitors, layout_signature, MonitorInfo, virtual_bounds};
+use crate::export::{document_to_svg, images_to_pdf};
+use crate::naming::{expand_template, TemplateValues};
+use crate::document::{AnnotationObject, Document, highlighter_polygon};
+use crate::constants::{AVIF_SPEED, BASE_DPI, BORDER_WIDTH, BUTTON_HEIGHT, NORMAL_BIG_IMAGE_LIMIT, OFFSET_X, OFFSET_Y, SMALL_IMAGE_LIMIT};
 
 pub fn compute_offsets(ctx: &mut EventCtx, data: &mut GrabData) {
     // Calculate the offset to center mouse positions in the Image
@@ -28,26 +36,72 @@ pub fn compute_offsets(ctx: &mut EventCtx, data: &mut GrabData) {
     }
 }
 
-pub fn load_image(data: &GrabData ) -> DynamicImage {
-    let image;
-
-    if data.image_data_new.is_empty() {
-        image = load_from_memory_with_format(&data.image_data_old, image::ImageFormat::Png)
-            .expect("Failed to load image from memory");
-    } else {
-        image = load_from_memory_with_format(&data.image_data_new, image::ImageFormat::Png)
-            .expect("Failed to load image from memory");
-    }
-
-    image
+// the original capture, without crop and annotations
+pub fn load_image(data: &GrabData ) -> Result<DynamicImage> {
+    load_from_memory_with_format(&data.image_data_old, image::ImageFormat::Png)
+        .context("Cannot read the captured image")
 }
 
-pub fn image_to_buffer(image: DynamicImage) -> Vec<u8> {
+pub fn image_to_buffer(image: DynamicImage) -> Result<Vec<u8>> {
     let mut png_buffer = std::io::Cursor::new(Vec::new());
     image.write_to(&mut png_buffer, image::ImageFormat::Png)
-        .expect("Failed to Save Modified Image");
+        .context("Cannot encode the image as png")?;
+
+    Ok(png_buffer.into_inner())
+}
+
+// encode the image in the given format (the file extension, e.g. "png" or "jpg"),
+// quality (0-100) is used by the lossy formats, webp can also be lossless
+pub fn encode_image(image: &DynamicImage, format: &str, quality: u8, lossless: bool) -> Result<Vec<u8>> {
+    let image_format = ImageFormat::from_extension(format).ok_or(Error::Message(format!("Unsupported format: {}", format)))?;
+    let mut buffer = std::io::Cursor::new(Vec::new());
+    match image_format {
+        ImageFormat::Jpeg => {
+            // jpeg has no alpha channel, so it is written as rgb
+            let rgb_image = image.to_rgb8();
+            JpegEncoder::new_with_quality(&mut buffer, quality)
+                .write_image(rgb_image.as_raw(), rgb_image.width(), rgb_image.height(), ColorType::Rgb8)
+        }
+        ImageFormat::WebP => {
+            let webp_quality = if lossless { WebPQuality::lossless() } else { WebPQuality::lossy(quality) };
+            let rgba_image = image.to_rgba8();
+            WebPEncoder::new_with_quality(&mut buffer, webp_quality)
+                .write_image(rgba_image.as_raw(), rgba_image.width(), rgba_image.height(), ColorType::Rgba8)
+        }
+        ImageFormat::Avif => {
+            let rgba_image = image.to_rgba8();
+            AvifEncoder::new_with_speed_quality(&mut buffer, AVIF_SPEED, quality)
+                .write_image(rgba_image.as_raw(), rgba_image.width(), rgba_image.height(), ColorType::Rgba8)
+        }
+        // qoi only supports 8 bit rgb and rgba
+        ImageFormat::Qoi => DynamicImage::from(image.to_rgba8()).write_to(&mut buffer, image_format),
+        _ => image.write_to(&mut buffer, image_format),
+    }.context(format!("Cannot encode the image as {}", format))?;
+
+    Ok(buffer.into_inner())
+}
 
-    png_buffer.into_inner()
+pub fn copy_to_clipboard(image: &DynamicImage, wait: bool) -> Result<()> {
+    let image = image.to_rgba8();
+    let mut clipboard = arboard::Clipboard::new().context("Cannot copy to the clipboard")?;
+
+    let img = arboard::ImageData {
+        width: image.width() as usize,
+        height: image.height() as usize,
+        bytes: Cow::from(image.as_bytes())
+    };
+
+    // on linux the clipboard content lives as long as the process that set it,
+    // so short-lived processes (command line mode) wait until it is replaced
+    #[cfg(target_os = "linux")]
+    if wait {
+        use arboard::SetExtLinux;
+        return clipboard.set().wait().image(img).context("Cannot copy to the clipboard");
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = wait;
+
+    clipboard.set_image(img).context("Cannot copy to the clipboard")
 }
 
 pub fn make_rectangle_from_points(data: &GrabData ) -> Option<(f64,f64,f64,f64)> {
@@ -88,100 +142,136 @@ pub fn make_rectangle_from_points(data: &GrabData ) -> Option<(f64,f64,f64,f64)>
     Some((min_x,min_y,max_x,max_y))
 }
 
-pub fn compute_circle_center_radius(data: &GrabData, min_x: i32, min_y: i32, max_x: i32, max_y: i32) -> (f64,f64) {
-    // compute the center
-    let center_x = ((max_x as f64 - data.offsets.0) - (min_x as f64 - data.offsets.0)) / 2.0 +  (min_x as f64 - data.offsets.0);
-    let center_y = ((max_y as f64 - data.offsets.1) - (min_y as f64 - data.offsets.1)) / 2.0 +  (min_y as f64 - data.offsets.1);
-    (center_x ,center_y)
-}
-
-pub fn compute_arrow_points(data: &GrabData) -> Option<((Point,Point),(Point,Point),(Point,Point))> {
+pub fn compute_highlighter_points(data: &GrabData) -> Option<(Point, Point, Point, Point)> {
     if data.positions.is_empty() {
         return None;
     }
-    let main_line_p0 = Point::new(data.positions[0].0 - data.offsets.0, data.positions[0].1 - data.offsets.1);
-    let main_line_p1 = Point::new(data.positions[data.positions.len()-1].0 - data.offsets.0, data.positions[data.positions.len()-1].1 - data.offsets.1);
-
-    //direzione = endX - startX , endY - startY
-    let direction = Point::new((data.positions[data.positions.len() - 1].0 - data.offsets.0) - (data.positions[0].0 - data.offsets.0),
-                               (data.positions[data.positions.len() - 1].1 - data.offsets.1) - (data.positions[0].1 - data.offsets.1));
-    //lunghezza = ipotenusa teorema di pitagora
-    let arrow_length = ((direction.x.powi(2) + direction.y.powi(2)) as f64).sqrt();
-    // angolo tra asseX e freccia
-    let angle = (direction.y as f64).atan2(direction.x as f64);
-    // lunghezza punta della freccia [settata ad un terzo]
-    let arrow_tip = arrow_length/3.0;
+    // draw line with first and last position, then clear the vector
+    let p0 = (data.positions[0].0 - data.offsets.0, data.positions[0].1 - data.offsets.1);
+    let p1 = (data.positions[data.positions.len()-1].0 - data.offsets.0,data.positions[data.positions.len()-1].1 - data.offsets.1);
+    let [rect_point1, rect_point2, rect_point3, rect_point4] = highlighter_polygon(p0, p1, data.settings.highlighter_width);
 
-    // Calcola punti della punta della freccia
-    let arrow_x1 = (data.positions[data.positions.len() - 1].0 - data.offsets.0) - (direction.x / arrow_length);
-    let arrow_y1 = (data.positions[data.positions.len() - 1].1 - data.offsets.1) - (direction.y / arrow_length);
+    Some((Point::from(rect_point1),Point::from(rect_point2),Point::from(rect_point3),Point::from(rect_point4)))
+}
 
-    let arrow_l0_p0 = main_line_p1;
-    let arrow_l0_p1 = Point::new(arrow_x1 - arrow_tip * (angle + PI / 6.0).cos(),arrow_y1 - arrow_tip * (angle + PI / 6.0).sin());
-    let arrow_l1_p0 = main_line_p1;
-    let arrow_l1_p1 = Point::new(arrow_x1 - arrow_tip * (angle - PI / 6.0).cos(),arrow_y1 - arrow_tip * (angle - PI / 6.0).sin());
+// the point moved so that the box from the start is a square, as large as its longer side
+pub fn constrain_to_square(start: (f64,f64), point: (f64,f64)) -> (f64,f64) {
+    let (dx, dy) = (point.0 - start.0, point.1 - start.1);
+    let side = dx.abs().max(dy.abs());
+    (start.0 + side.copysign(dx), start.1 + side.copysign(dy))
+}
 
-    // main line point couple, first line point couple, second line point couple
-    Some(((main_line_p0,main_line_p1),(arrow_l0_p0, arrow_l0_p1),(arrow_l1_p0, arrow_l1_p1)))
+// convert a point of the edit window in pixels of the original capture
+pub fn to_image_point(data: &GrabData, position: (f64,f64)) -> (f64,f64) {
+    let (origin_x, origin_y) = data.draft.origin();
+    ((position.0 - data.offsets.0) * data.scale_factors.0 + origin_x,
+     (position.1 - data.offsets.1) * data.scale_factors.1 + origin_y)
 }
 
-pub fn compute_highlighter_points(data: &GrabData) -> Option<(Point, Point, Point, Point)> {
-    if data.positions.is_empty() {
-        return None;
-    }
-    // draw line with first and last position, then clear the vector
-    let point1 = Point::new(data.positions[0].0 - data.offsets.0, data.positions[0].1 - data.offsets.1);
-    let point2 = Point::new(data.positions[data.positions.len()-1].0 - data.offsets.0,data.positions[data.positions.len()-1].1 - data.offsets.1);
+// convert a point in pixels of the original capture in coordinates of the edit window widget
+pub fn to_widget_point(data: &GrabData, point: (f64,f64)) -> (f64,f64) {
+    let (origin_x, origin_y) = data.draft.origin();
+    ((point.0 - origin_x) / data.scale_factors.0, (point.1 - origin_y) / data.scale_factors.1)
+}
 
-    // Define your margin and the two points representing the line segment
-    let highlighter_width = data.highlighter_width;
+// width of the annotations of the tool in pixels of the capture: stroke width of the shapes, width of the
+// highlighter, block size of the pixelate and strength of the blur
+pub fn tool_width(data: &GrabData, kind: &Annotation) -> f64 {
+    let width = match data.settings.shape_styles.style(kind) {
+        Some(style) => style.width,
+        None => match kind {
+            Annotation::Pixelate => data.settings.pixelate_size,
+            Annotation::Blur => data.settings.blur_strength,
+            _ => data.settings.highlighter_width,
+        },
+    };
+    width * data.scale_factors.0
+}
 
-    // Calculate the slope of the line
-    let dx = point2.x - point1.x;
-    let dy = point2.y - point1.y;
-    let slope = dy / dx;
+// build the annotation object for the current positions and style
+pub fn make_annotation_object(data: &GrabData) -> AnnotationObject {
+    let points = match data.annotation {
+        Annotation::FreeLine => data.positions.iter().map(|p| to_image_point(data, *p)).collect(),
+        // the last point if we click many times
+        Annotation::Text | Annotation::Step => vec![to_image_point(data, data.positions[data.positions.len()-1])],
+        _ => vec![to_image_point(data, data.positions[0]), to_image_point(data, data.positions[data.positions.len()-1])],
+    };
+    let mut object = AnnotationObject::new(data.annotation.clone(), points, data.settings.color);
+    object.width = tool_width(data, &data.annotation);
+    if let Some(style) = data.settings.shape_styles.style(&data.annotation) {
+        object.line_style = style.line_style;
+        object.fill = if style.fill && data.annotation.is_closed_shape() { Some(style.fill_color) } else { None };
+    }
+    if data.annotation == Annotation::RoundedRectangle {
+        object.corner_radius = data.settings.corner_radius * data.scale_factors.0;
+    }
+    object.text = data.text_annotation.clone();
+    object.text_size = data.settings.text_size;
+    object
+}
 
-    // Calculate the angle of the line with respect to the horizontal axis
-    let angle = slope.atan();
+// the draft document (the one being edited) over the original capture
+pub fn render_image(data: &GrabData) -> Result<DynamicImage> {
+    Ok(data.draft.flatten(&load_image(data)?))
+}
 
-    // Calculate the change in x and y coordinates for the margin
-    let delta_x = highlighter_width * (angle + PI / 2.0).cos();
-    let delta_y = highlighter_width * (angle + PI / 2.0).sin();
+// the approved document over the original capture, used to save and copy
+pub fn flatten_image(data: &GrabData) -> Result<DynamicImage> {
+    Ok(data.document.flatten(&load_image(data)?))
+}
 
-    // Create the four vertices of the rectangle
-    let rect_point1 = Point::new(point1.x + delta_x, point1.y + delta_y);
-    let rect_point2 = Point::new(point1.x - delta_x, point1.y - delta_y);
-    let rect_point3 = Point::new(point2.x - delta_x, point2.y - delta_y);
-    let rect_point4 = Point::new(point2.x + delta_x, point2.y + delta_y);
+// encoded file of the approved document in the save format: vector svg, pdf at the native dpi or raster image
+pub fn export_capture(data: &GrabData) -> Result<Vec<u8>> {
+    match data.settings.save_format.as_str() {
+        "svg" => Ok(document_to_svg(&load_image(data)?, &data.document).context("Cannot export the svg")?.into_bytes()),
+        "pdf" => images_to_pdf(&[(flatten_image(data)?, capture_dpi(&*default_backend(), data.settings.selected_monitor))])
+            .context("Cannot export the pdf"),
+        format => encode_image(&flatten_image(data)?, format, data.settings.quality as u8, data.settings.lossless),
+    }
+}
 
-    Some((rect_point1,rect_point2,rect_point3,rect_point4))
+// file name (without extension) of the capture from the template in the settings
+pub fn capture_file_name(data: &GrabData) -> Result<String> {
+    let monitor = match data.settings.selected_monitor {
+        Some(index) => default_backend().monitors().unwrap_or_default().get(index)
+            .map(|m| m.name.clone()).unwrap_or(format!("Monitor {}", index)),
+        None => "all".to_string(),
+    };
+    let (width, height) = match data.document.crop {
+        Some((_, _, width, height)) => (width, height),
+        None => {
+            let image = load_image(data)?;
+            (image.width(), image.height())
+        }
+    };
+    Ok(expand_template(&data.settings.file_template, &TemplateValues { counter: data.settings.screenshot_number, monitor, width, height, frame: 0 }))
 }
 
 // Image Resizing
-pub fn resize_image(image: DynamicImage, data: &mut GrabData) -> (f64, f64) {
-    let screen = Screen::all().unwrap()[0];
+pub fn resize_image(image: DynamicImage, data: &mut GrabData) -> Result<(f64, f64)> {
+    let screen = primary_monitor(&*default_backend()).map_err(Error::Capture)?;
     let scale_factor_x ;
     let scale_factor_y;
 
-    if image.width() >= (screen.display_info.width as f64 * NORMAL_BIG_IMAGE_LIMIT) as u32 || image.height() >= (screen.display_info.height as f64 * NORMAL_BIG_IMAGE_LIMIT) as u32 {
+    if image.width() >= (screen.width as f64 * NORMAL_BIG_IMAGE_LIMIT) as u32 || image.height() >= (screen.height as f64 * NORMAL_BIG_IMAGE_LIMIT) as u32 {
         // NORMAL OR BIG IMAGE (>= 50% of the screen)
-        scale_factor_x = image.width() as f64 / (screen.display_info.width as f64 * 1.6);
-        scale_factor_y = image.height() as f64 / (screen.display_info.height as f64 * 1.6);
+        scale_factor_x = image.width() as f64 / (screen.width as f64 * 1.6);
+        scale_factor_y = image.height() as f64 / (screen.height as f64 * 1.6);
 
-    } else if image.width() <= (screen.display_info.width as f64 * SMALL_IMAGE_LIMIT) as u32 && image.height() <= (screen.display_info.height as f64 * SMALL_IMAGE_LIMIT) as u32 {
+    } else if image.width() <= (screen.width as f64 * SMALL_IMAGE_LIMIT) as u32 && image.height() <= (screen.height as f64 * SMALL_IMAGE_LIMIT) as u32 {
         // VERY SMALL IMAGE (<= 20% of the screen)
         scale_factor_x = 0.25;
         scale_factor_y = 0.25;
-        //image = image.resize((screen.display_info.width / 4), (screen.display_info.height / 4), FilterType::Nearest);
+        //image = image.resize((screen.width / 4), (screen.height / 4), FilterType::Nearest);
     }else{
         // SMALL IMAGE (20% of the screen < size < 50% of the screen)
-        scale_factor_x = (image.width() as f64 * 1.4) / (screen.display_info.width as f64);
-        scale_factor_y = (image.height() as f64 * 1.4) / (screen.display_info.height as f64);
+        scale_factor_x = (image.width() as f64 * 1.4) / (screen.width as f64);
+        scale_factor_y = (image.height() as f64 * 1.4) / (screen.height as f64);
     }
 
     let aspect_ratio = image.width() as f64 / image.height() as f64;
-    let desired_width = (screen.display_info.width as f64) * scale_factor_x;
-    let desired_height = (screen.display_info.height as f64 - 7.0 * BUTTON_HEIGHT) * scale_factor_y;
+    let desired_width = (screen.width as f64) * scale_factor_x;
+    let desired_height = (screen.height as f64 - 7.0 * BUTTON_HEIGHT) * scale_factor_y;
     // Calculate the scaled dimensions while preserving aspect ratio
     let (mut scaled_width, mut scaled_height) = if image.width() as f64 / desired_width > image.height() as f64 / desired_height {
         // Fit by width
@@ -193,9 +283,9 @@ pub fn resize_image(image: DynamicImage, data: &mut GrabData) -> (f64, f64) {
 
     //let window_size = Size::new( scaled_width,(scaled_height + BUTTON_HEIGHT * 7.0));
 
-    if image.width() as f64>0.9* screen.display_info.width as f64 || image.height() as f64 > 0.9*screen.display_info.height as f64 {
+    if image.width() as f64>0.9* screen.width as f64 || image.height() as f64 > 0.9*screen.height as f64 {
         // if window size becames bigger than the monitor, rescale
-        let big_factor = (image.width()as f64/screen.display_info.width as f64).max(image.height() as f64/screen.display_info.height as f64)+0.1;
+        let big_factor = (image.width()as f64/screen.width as f64).max(image.height() as f64/screen.height as f64)+0.1;
         scaled_width /= big_factor;
         scaled_height /= big_factor;
     }
@@ -206,101 +296,264 @@ pub fn resize_image(image: DynamicImage, data: &mut GrabData) -> (f64, f64) {
     data.scale_factors.0 = image.width() as f64 / scaled_width;
     data.scale_factors.1 = image.height() as f64 / scaled_height;
 
-    (scaled_width,scaled_height)
+    Ok((scaled_width,scaled_height))
 }
 
 // Reset Data Function
 pub fn reset_data(data: &mut GrabData) {
     // set data fields to their initial state
     data.image_data_old = vec![];
-    data.image_data_new = vec![];
+    data.document = Document::default();
+    data.draft = Document::default();
+    data.history.clear();
     data.press = false;
     data.first_screen = true;
     data.scale_factors = (1.0,1.0);
     data.positions = vec![];
+    data.selected = None;
     data.offsets = (0.0,0.0);
-    data.hotkey_new = vec![];
-    data.hotkey_pressed = vec![];
-    data.set_hot_key = false;
+    data.hotkey_new = None;
+    data.editing_hotkey = None;
     data.input_hotkey_error = (false,"Invalid Input: Wrong Hotkey.".to_string());
     data.trigger_ui = false;
     data.annotation = Annotation::None;
     data.text_annotation = "".to_string();
 }
 
-struct ScreenImage {
-    screen: screenshots::Screen,
-    image: screenshots::Image,
+// rect of the first monitor reported by the windowing system, where the editor windows are placed
+pub fn first_monitor_rect() -> Result<Rect> {
+    druid::Screen::get_monitors().first()
+        .map(|monitor| monitor.virtual_rect())
+        .ok_or(Error::Capture("No monitor found".to_string()))
 }
 
-pub fn compute_screening_coordinates(_data: &mut GrabData) -> (i32,i32,i32,i32) {
-    /* get monitors info
-    let mut accumulator = (0,0);
-    for screen in Screen::all().unwrap() {
-        accumulator.0 += screen.display_info.x as i32;
-        accumulator.1 += screen.display_info.y as i32;
-        data.monitors_info.push(accumulator);
-    }*/
-
-    // Capture all screens
-    let screen_images = Screen::all()
-        .unwrap()
-        .into_iter()
-        .collect::<Vec<_>>();
+// the primary monitor (or the first one if none is marked as primary)
+pub fn primary_monitor(backend: &dyn CaptureBackend) -> Result<MonitorInfo, String> {
+    let monitors = backend.monitors()?;
+    monitors.iter().find(|m| m.is_primary).or(monitors.first()).cloned()
+        .ok_or("No monitor found".to_string())
+}
 
+pub fn compute_screening_coordinates(backend: &dyn CaptureBackend) -> Result<(i32,i32,i32,i32), String> {
     // Compute coordinates of combined image
-    let x_min = screen_images.iter().map(|s| s.display_info.x).min().unwrap();
-    let y_min = screen_images.iter().map(|s| s.display_info.y).min().unwrap();
-    let x_max = screen_images
-        .iter()
-        .map(|s| s.display_info.x + s.display_info.width as i32)
-        .max()
-        .unwrap();
-    let y_max = screen_images
-        .iter()
-        .map(|s| s.display_info.y + s.display_info.height as i32)
-        .max()
-        .unwrap();
-
-    (x_min,y_min,x_max,y_max)
-}
-
-pub fn screen_all(min_x_grab: i32,min_y_grab: i32,max_x_grab: i32,max_y_grab: i32,data: &mut GrabData) {
-    // Capture all screens
-    let screen_images = Screen::all()
-        .unwrap()
-        .into_iter()
-        .map(|screen| {
-            let image = screen.capture().unwrap();
-            ScreenImage { screen, image }
-        })
-        .collect::<Vec<_>>();
-
-    let (x_min,y_min,x_max,y_max) = compute_screening_coordinates(data);
-    let offset = (x_min, y_min);
-    let size = ((x_max - x_min) as u32, (y_max - y_min) as u32);
-
-    let mut img = DynamicImage::new_rgba8(size.0,size.1);
-    for screen_image in screen_images {
-        let screenshot = image::io::Reader::new(std::io::Cursor::new(screen_image.image.to_png(None).unwrap()))
-            .with_guessed_format()
-            .unwrap()
-            .decode()
-            .unwrap();
-        img.copy_from(
-            &screenshot,
-            (screen_image.screen.display_info.x - offset.0) as u32,
-            (screen_image.screen.display_info.y - offset.1) as u32,
-        )
-            .unwrap();
+    virtual_bounds(&backend.monitors()?).ok_or("No monitor found".to_string())
+}
+
+// area covered by the selection overlay: the selected monitor, or all of them
+// (also when the selected monitor is not connected anymore)
+pub fn compute_overlay_coordinates(backend: &dyn CaptureBackend, data: &GrabData) -> Result<(i32,i32,i32,i32), String> {
+    if let Some(index) = data.settings.selected_monitor {
+        if let Some(monitor) = backend.monitors()?.get(index) {
+            return Ok((monitor.x, monitor.y, monitor.right(), monitor.bottom()));
+        }
+    }
+    compute_screening_coordinates(backend)
+}
+
+// native dpi of the capture, from the scale factor of its monitor
+pub fn capture_dpi(backend: &dyn CaptureBackend, selected_monitor: Option<usize>) -> f32 {
+    let monitors = backend.monitors().unwrap_or_default();
+    let monitor = selected_monitor.and_then(|index| monitors.get(index))
+        .or(monitors.iter().find(|m| m.is_primary));
+    BASE_DPI * monitor.map(|m| m.scale_factor).unwrap_or(1.0)
+}
+
+// selection rect of the overlay (without the border) in desktop coordinates
+pub fn selection_rect(backend: &dyn CaptureBackend, min_x_grab: i32, min_y_grab: i32, max_x_grab: i32, max_y_grab: i32, data: &GrabData) -> Result<(i32,i32,u32,u32), String> {
+    let (x_min,y_min,_,_) = compute_overlay_coordinates(backend, data)?;
+
+    // selection rect (without the border) relative to the overlay
+    let crop_x = (((min_x_grab as f64 - data.offsets.0) * data.scale_factors.0) + BORDER_WIDTH) as u32;
+    let crop_y = (((min_y_grab as f64 - data.offsets.1) * data.scale_factors.1) + BORDER_WIDTH) as u32;
+    let crop_width = (((max_x_grab as f64- data.offsets.0) - ((min_x_grab as f64 - data.offsets.0) + 2.0 * BORDER_WIDTH)) * data.scale_factors.0) as u32;
+    let crop_height = (((max_y_grab as f64- data.offsets.1) - ((min_y_grab as f64 - data.offsets.1) + 2.0 * BORDER_WIDTH)) * data.scale_factors.1) as u32;
+
+    Ok((x_min + crop_x as i32, y_min + crop_y as i32, crop_width, crop_height))
+}
+
+// capture a rect of the desktop (physical pixels), stitching the monitors it spans
+pub fn capture_rect(x: i32, y: i32, width: u32, height: u32) -> Result<DynamicImage> {
+    default_backend().capture_rect(x, y, width, height).map_err(Error::Capture)
+}
+
+// capture a rect of the desktop as the original capture
+pub fn screen_all(x: i32, y: i32, width: u32, height: u32, data: &mut GrabData) -> Result<()> {
+    let img = capture_rect(x, y, width, height)?;
+
+    data.image_data_old = image_to_buffer(img)?;
+    Ok(())
+}
+
+// capture the area selected in the overlay, it is remembered for the monitor layout and saved at once
+pub fn screen_selection(min_x_grab: i32,min_y_grab: i32,max_x_grab: i32,max_y_grab: i32,data: &mut GrabData) -> Result<()> {
+    let backend = default_backend();
+    let (x, y, width, height) = selection_rect(&*backend, min_x_grab, min_y_grab, max_x_grab, max_y_grab, data).map_err(Error::Capture)?;
+    screen_all(x, y, width, height, data)?;
+
+    let layout = layout_signature(&backend.monitors().map_err(Error::Capture)?);
+    data.settings.remember_region(layout, (x, y, width, height));
+    data.settings.save().map_err(Error::Message)
+}
+
+// the region last selected under the current monitor layout
+pub fn last_region(backend: &dyn CaptureBackend, data: &GrabData) -> Result<(i32,i32,u32,u32), String> {
+    let layout = layout_signature(&backend.monitors()?);
+    data.settings.last_region(&layout).ok_or("No region has been selected yet with these monitors".to_string())
+}
+
+// desktop rect of a preset, checked against the monitors connected now
+pub fn preset_rect(backend: &dyn CaptureBackend, preset: &RegionPreset) -> Result<(i32,i32,u32,u32), String> {
+    let monitors = backend.monitors()?;
+    let monitor = monitors.iter().find(|m| m.id == preset.monitor_id)
+        .ok_or(format!("The monitor of the preset {} is not connected", preset.name))?;
+    let (x, y) = (monitor.x + preset.x, monitor.y + preset.y);
+    // it can span the monitors, but not the parts of the bounding box that no monitor shows
+    if !is_on_monitors(&monitors, (x, y, preset.width, preset.height)) {
+        return Err(format!("The preset {} ({}x{} at {},{} of {}) is outside the screens", preset.name,
+                           preset.width, preset.height, preset.x, preset.y, monitor.name));
     }
+    Ok((x, y, preset.width, preset.height))
+}
 
-    img = img.crop(
-        (((min_x_grab as f64 - data.offsets.0) * data.scale_factors.0) + BORDER_WIDTH) as u32,
-        (((min_y_grab as f64 - data.offsets.1) * data.scale_factors.1) + BORDER_WIDTH) as u32,
-        (((max_x_grab as f64- data.offsets.0) - ((min_x_grab as f64 - data.offsets.0) + 2.0 * BORDER_WIDTH)) * data.scale_factors.0) as u32,
-        (((max_y_grab as f64- data.offsets.1) - ((min_y_grab as f64 - data.offsets.1) + 2.0 * BORDER_WIDTH)) * data.scale_factors.1) as u32
-    );
+// preset of a desktop rect, relative to the monitor containing its top left corner
+pub fn preset_from_rect(backend: &dyn CaptureBackend, name: &str, (x, y, width, height): (i32,i32,u32,u32)) -> Result<RegionPreset, String> {
+    let monitors = backend.monitors()?;
+    let monitor = monitors.iter().find(|m| m.x <= x && x < m.right() && m.y <= y && y < m.bottom())
+        .ok_or("The region is outside the screens".to_string())?;
+    Ok(RegionPreset { name: name.trim().to_string(), monitor_id: monitor.id, x: x - monitor.x, y: y - monitor.y, width, height })
+}
 
-    data.image_data_old = image_to_buffer(img);
-}
\ No newline at end of file
+// the monitor showing the window of the event, the selected (or primary) one if not found
+pub fn current_monitor(ctx: &EventCtx, backend: &dyn CaptureBackend, data: &GrabData) -> Result<MonitorInfo, String> {
+    let position = ctx.window().get_position();
+    let (x, y) = ((position.x * ctx.scale().x()) as i32, (position.y * ctx.scale().y()) as i32);
+    let monitors = backend.monitors()?;
+    if let Some(monitor) = monitors.iter().find(|m| m.x <= x && x < m.right() && m.y <= y && y < m.bottom()) {
+        return Ok(monitor.clone());
+    }
+    match data.settings.selected_monitor.and_then(|index| monitors.get(index)) {
+        Some(monitor) => Ok(monitor.clone()),
+        None => primary_monitor(backend),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GenericImageView, Rgba, RgbaImage};
+    use crate::capture::SyntheticBackend;
+    use crate::settings::Settings;
+    use super::*;
+
+    // opaque gradient with some noise, the lossy formats cannot keep it exactly
+    fn test_image() -> DynamicImage {
+        DynamicImage::from(RgbaImage::from_fn(64, 48, |x, y| Rgba([(x * 4) as u8, (y * 5) as u8, ((x * y) % 251) as u8, 255])))
+    }
+
+    #[test]
+    fn formats_round_trip() {
+        let image = test_image();
+        for (extension, format, exact) in [("png", ImageFormat::Png, true), ("jpg", ImageFormat::Jpeg, false), ("gif", ImageFormat::Gif, false),
+                                           ("bmp", ImageFormat::Bmp, true), ("qoi", ImageFormat::Qoi, true)] {
+            let bytes = encode_image(&image, extension, 90, false).unwrap();
+            assert_eq!(image::guess_format(&bytes).unwrap(), format, "{}", extension);
+            let decoded = image::load_from_memory(&bytes).unwrap();
+            assert_eq!(decoded.dimensions(), (64, 48), "{}", extension);
+            if exact {
+                assert_eq!(decoded.to_rgba8(), image.to_rgba8(), "{}", extension);
+            }
+        }
+        assert!(encode_image(&image, "xyz", 90, false).is_err());
+    }
+
+    #[test]
+    fn jpeg_quality() {
+        let image = test_image();
+        let low = encode_image(&image, "jpg", 10, false).unwrap();
+        let high = encode_image(&image, "jpg", 95, false).unwrap();
+        assert!(low.len() < high.len());
+    }
+
+    #[test]
+    fn webp_quality_and_lossless() {
+        let image = test_image();
+        let low = encode_image(&image, "webp", 10, false).unwrap();
+        let high = encode_image(&image, "webp", 95, false).unwrap();
+        assert!(low.len() < high.len());
+        assert_eq!(image::load_from_memory(&high).unwrap().dimensions(), (64, 48));
+        let lossless = encode_image(&image, "webp", 10, true).unwrap();
+        assert_eq!(image::load_from_memory(&lossless).unwrap().to_rgba8(), image.to_rgba8());
+    }
+
+    // without the avif decoder only the container is checked
+    #[test]
+    fn avif_quality() {
+        let image = test_image();
+        let low = encode_image(&image, "avif", 10, false).unwrap();
+        let high = encode_image(&image, "avif", 95, false).unwrap();
+        assert_eq!(image::guess_format(&high).unwrap(), ImageFormat::Avif);
+        assert!(low.len() < high.len());
+    }
+
+    #[test]
+    fn last_region_follows_the_monitor_layout() {
+        let backend = SyntheticBackend::default();
+        let mut data = GrabData::new(Settings::default());
+        assert!(last_region(&backend, &data).is_err());
+        let layout = layout_signature(&backend.monitors().unwrap());
+        assert_eq!(layout, "1920x1080+0+0,1920x1080+1920+0");
+        data.settings.remember_region(layout, (1800, 100, 300, 200));
+        assert_eq!(last_region(&backend, &data), Ok((1800, 100, 300, 200)));
+
+        // with the right monitor disconnected the region is not offered, until the layout comes back
+        let single = SyntheticBackend::new(vec![SyntheticBackend::solid_monitor(0, 0, 0, 1920, 1080, (255, 0, 0, 255))]);
+        assert!(last_region(&single, &data).is_err());
+        data.settings.remember_region(layout_signature(&single.monitors().unwrap()), (10, 10, 50, 50));
+        assert_eq!(last_region(&single, &data), Ok((10, 10, 50, 50)));
+        assert_eq!(last_region(&backend, &data), Ok((1800, 100, 300, 200)));
+    }
+
+    #[test]
+    fn presets_round_trip() {
+        let backend = SyntheticBackend::new(vec![
+            SyntheticBackend::solid_monitor(0, 0, 0, 1920, 1080, (255, 0, 0, 255)),
+            SyntheticBackend::solid_monitor(1, 1920, 0, 1280, 1024, (0, 0, 255, 255)),
+        ]);
+        // relative to the monitor of the top left corner, it can span the monitors
+        for rect in [(2000, 80, 1024, 768), (1800, 100, 300, 200), (0, 0, 1920, 1080)] {
+            let preset = preset_from_rect(&backend, " browser ", rect).unwrap();
+            assert_eq!(preset.name, "browser");
+            assert_eq!(preset_rect(&backend, &preset), Ok(rect));
+        }
+        let preset = preset_from_rect(&backend, "browser", (2000, 80, 1024, 768)).unwrap();
+        assert_eq!((preset.monitor_id, preset.x, preset.y), (1, 80, 80));
+        assert!(preset_from_rect(&backend, "outside", (3200, 0, 10, 10)).is_err());
+        assert!(preset_from_rect(&backend, "outside", (-10, 0, 100, 100)).is_err());
+    }
+
+    #[test]
+    fn presets_off_the_monitors_are_rejected() {
+        let backend = SyntheticBackend::new(vec![
+            SyntheticBackend::solid_monitor(0, 0, 0, 1920, 1080, (255, 0, 0, 255)),
+            SyntheticBackend::solid_monitor(1, 1920, 0, 1280, 1024, (0, 0, 255, 255)),
+        ]);
+        let preset = |monitor_id, x, y, width, height| RegionPreset { name: "preset".to_string(), monitor_id, x, y, width, height };
+        assert_eq!(preset_rect(&backend, &preset(1, 0, 0, 1280, 1024)), Ok((1920, 0, 1280, 1024)));
+        // below the smaller monitor, inside the bounding box of the desktop
+        assert!(preset_rect(&backend, &preset(1, 0, 900, 100, 200)).is_err());
+        assert!(preset_rect(&backend, &preset(0, 1800, 1000, 300, 50)).is_err());
+        // right of the last monitor, and a monitor no longer connected
+        assert!(preset_rect(&backend, &preset(1, 1200, 0, 100, 100)).is_err());
+        assert!(preset_rect(&backend, &preset(2, 0, 0, 100, 100)).is_err());
+    }
+
+    #[test]
+    fn constrain_to_square_in_every_quadrant() {
+        let start = (100.0, 100.0);
+        // the longer side wins, the direction of the drag is kept
+        assert_eq!(constrain_to_square(start, (130.0, 110.0)), (130.0, 130.0));
+        assert_eq!(constrain_to_square(start, (90.0, 140.0)), (60.0, 140.0));
+        assert_eq!(constrain_to_square(start, (70.0, 95.0)), (70.0, 70.0));
+        assert_eq!(constrain_to_square(start, (105.0, 50.0)), (150.0, 50.0));
+        assert_eq!(constrain_to_square(start, start), start);
+    }
+}